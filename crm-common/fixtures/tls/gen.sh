@@ -0,0 +1,31 @@
+#!/bin/sh
+# Regenerate the test CA and the certificates it signs, valid for ten years.
+set -e
+cd "$(dirname "$0")"
+
+openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -days 3650 \
+    -subj "/CN=crm test ca" -keyout ca.key -out ca.pem \
+    -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign"
+
+issue() {
+    name=$1
+    ext=$2
+    openssl req -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes \
+        -subj "/CN=$name" -keyout "$name.key" -out "$name.csr"
+    printf "%s\n" "$ext" > "$name.ext"
+    openssl x509 -req -in "$name.csr" -CA ca.pem -CAkey ca.key -CAcreateserial -days 3650 \
+        -extfile "$name.ext" -out "$name.pem"
+    rm "$name.csr" "$name.ext"
+}
+
+# services present the same certificate to their callers and to the services they call
+issue server "subjectAltName=DNS:localhost,IP:127.0.0.1,IP:::1
+basicConstraints=critical,CA:FALSE
+keyUsage=critical,digitalSignature
+extendedKeyUsage=serverAuth,clientAuth"
+issue client "subjectAltName=DNS:crm-client
+basicConstraints=critical,CA:FALSE
+keyUsage=critical,digitalSignature
+extendedKeyUsage=clientAuth"
+
+rm -f ca.key ca.srl
@@ -0,0 +1,438 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use jwt_simple::prelude::{
+    Duration as JwtDuration, Ed25519KeyPair, Ed25519PublicKey, EdDSAKeyPairLike,
+    EdDSAPublicKeyLike, HashSet, VerificationOptions,
+};
+use serde::{Deserialize, Serialize};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::Channel,
+    Request, Status,
+};
+
+use crate::{transport, TlsConfig};
+
+const JWT_ISS: &str = "crm";
+const JWT_AUD: &str = "crm";
+const AUTHORIZATION: &str = "authorization";
+/// Role of the services calling one another.
+pub const SERVICE_ROLE: &str = "service";
+/// How long a service token is valid for.
+const SERVICE_TOKEN_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// A channel whose calls carry a service token.
+pub type AuthChannel = InterceptedService<Channel, ServiceToken>;
+
+/// Who the caller is, injected into the request extensions once the token is verified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Claims {
+    pub sub: String,
+    pub roles: Vec<String>,
+}
+
+/// Claims carried by the token on top of the registered ones.
+#[derive(Debug, Serialize, Deserialize)]
+struct CustomClaims {
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+/// Ed25519 private key tokens are signed with.
+pub struct EncodingKey(Ed25519KeyPair);
+
+/// Ed25519 public key tokens are verified with.
+pub struct DecodingKey(Ed25519PublicKey);
+
+/// Server interceptor rejecting calls without a valid bearer token, signed by the issuer or
+/// by one of the services trusted.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    dk: Arc<DecodingKey>,
+    services: Arc<HashMap<String, DecodingKey>>,
+}
+
+/// Client interceptor attaching a bearer token to every call.
+#[derive(Clone)]
+pub struct TokenInterceptor {
+    value: MetadataValue<Ascii>,
+}
+
+/// Client interceptor attaching a token of the service to every call, minted anew once three
+/// quarters of its lifetime have passed.
+#[derive(Clone)]
+pub struct ServiceToken {
+    inner: Arc<ServiceTokenInner>,
+}
+
+struct ServiceTokenInner {
+    ek: EncodingKey,
+    claims: Claims,
+    ttl: Duration,
+    current: Mutex<MintedToken>,
+}
+
+struct MintedToken {
+    value: MetadataValue<Ascii>,
+    refresh_at: Instant,
+}
+
+impl Claims {
+    pub fn new(sub: impl Into<String>, roles: &[&str]) -> Self {
+        Self {
+            sub: sub.into(),
+            roles: roles.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+}
+
+impl EncodingKey {
+    pub fn load(pem: &str) -> Result<Self> {
+        let key = Ed25519KeyPair::from_pem(pem).context("invalid Ed25519 private key")?;
+        Ok(Self(key))
+    }
+
+    /// Sign a token for the claims, valid for `ttl`.
+    pub fn sign(&self, claims: Claims, ttl: Duration) -> Result<String> {
+        let custom = CustomClaims {
+            roles: claims.roles,
+        };
+        let claims = jwt_simple::prelude::Claims::with_custom_claims(
+            custom,
+            JwtDuration::from_secs(ttl.as_secs()),
+        )
+        .with_subject(claims.sub)
+        .with_issuer(JWT_ISS)
+        .with_audience(JWT_AUD);
+        self.0.sign(claims)
+    }
+}
+
+impl DecodingKey {
+    pub fn load(pem: &str) -> Result<Self> {
+        let key = Ed25519PublicKey::from_pem(pem).context("invalid Ed25519 public key")?;
+        Ok(Self(key))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let opts = VerificationOptions {
+            allowed_issuers: Some(HashSet::from([JWT_ISS.to_string()])),
+            allowed_audiences: Some(HashSet::from([JWT_AUD.to_string()])),
+            ..Default::default()
+        };
+        let claims = self.0.verify_token::<CustomClaims>(token, Some(opts))?;
+        // a token without expiry would be valid forever
+        if claims.expires_at.is_none() {
+            bail!("token has no expiry");
+        }
+        Ok(Claims {
+            sub: claims.subject.unwrap_or_default(),
+            roles: claims.custom.roles,
+        })
+    }
+}
+
+impl AuthInterceptor {
+    pub fn new(dk: DecodingKey) -> Self {
+        Self {
+            dk: Arc::new(dk),
+            services: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// An interceptor verifying tokens with the PEM encoded public key.
+    pub fn load(pk: &str) -> Result<Self> {
+        Ok(Self::new(DecodingKey::load(pk)?))
+    }
+
+    /// Also accept the tokens of the services signed with their own PEM encoded keys, by the
+    /// name the services give themselves in their tokens.
+    pub fn with_services(mut self, services: &HashMap<String, String>) -> Result<Self> {
+        let services = services
+            .iter()
+            .map(|(name, pk)| {
+                let dk = DecodingKey::load(pk)
+                    .with_context(|| format!("invalid key of service {}", name))?;
+                Ok((name.clone(), dk))
+            })
+            .collect::<Result<_>>()?;
+        self.services = Arc::new(services);
+        Ok(self)
+    }
+
+    /// The claims of a token of the issuer, or of a service vouching for itself only: its key
+    /// can't mint tokens of other callers, nor grant more than the service role.
+    fn verify(&self, token: &str) -> Result<Claims> {
+        let err = match self.dk.verify(token) {
+            Ok(claims) => return Ok(claims),
+            Err(e) => e,
+        };
+        for (name, dk) in self.services.iter() {
+            if let Ok(claims) = dk.verify(token) {
+                if claims.sub != *name {
+                    bail!("token of service {} is for {:?}", name, claims.sub);
+                }
+                let roles = claims.roles.iter().filter(|v| *v == SERVICE_ROLE);
+                return Ok(Claims {
+                    roles: roles.cloned().collect(),
+                    ..claims
+                });
+            }
+        }
+        Err(err)
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        let token = req
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+
+        let claims = self
+            .verify(token)
+            .map_err(|e| Status::unauthenticated(format!("invalid token: {}", e)))?;
+        req.extensions_mut().insert(claims);
+        Ok(req)
+    }
+}
+
+impl TokenInterceptor {
+    pub fn new(token: &str) -> Result<Self> {
+        let value = format!("Bearer {}", token).parse()?;
+        Ok(Self { value })
+    }
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        req.metadata_mut().insert(AUTHORIZATION, self.value.clone());
+        Ok(req)
+    }
+}
+
+impl ServiceToken {
+    pub fn new(ek: EncodingKey, sub: &str, ttl: Duration) -> Result<Self> {
+        let claims = Claims::new(sub, &[SERVICE_ROLE]);
+        let current = mint(&ek, &claims, ttl, Instant::now())?;
+        let inner = ServiceTokenInner {
+            ek,
+            claims,
+            ttl,
+            current: Mutex::new(current),
+        };
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Tokens of the service signed with the PEM encoded private key.
+    pub fn load(sk: &str, sub: &str) -> Result<Self> {
+        Self::new(EncodingKey::load(sk)?, sub, SERVICE_TOKEN_TTL)
+    }
+
+    /// Connect to the service at the address, attaching the token to every call.
+    pub async fn connect(&self, addr: &str, tls: Option<&TlsConfig>) -> Result<AuthChannel> {
+        let channel = transport::connect(addr, tls).await?;
+        Ok(InterceptedService::new(channel, self.clone()))
+    }
+
+    /// Like `connect`, connecting on the first call.
+    pub fn connect_lazy(&self, addr: &str, tls: Option<&TlsConfig>) -> Result<AuthChannel> {
+        let channel = transport::connect_lazy(addr, tls)?;
+        Ok(InterceptedService::new(channel, self.clone()))
+    }
+
+    /// The token to send at `now`, minting a new one if the current is close to expiry.
+    fn current(&self, now: Instant) -> Result<MetadataValue<Ascii>> {
+        let inner = &self.inner;
+        let mut current = inner.current.lock().expect("service token lock poisoned");
+        if now >= current.refresh_at {
+            *current = mint(&inner.ek, &inner.claims, inner.ttl, now)?;
+        }
+        Ok(current.value.clone())
+    }
+}
+
+impl Interceptor for ServiceToken {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        let value = self
+            .current(Instant::now())
+            .map_err(|e| Status::internal(format!("failed to mint service token: {}", e)))?;
+        req.metadata_mut().insert(AUTHORIZATION, value);
+        Ok(req)
+    }
+}
+
+fn mint(ek: &EncodingKey, claims: &Claims, ttl: Duration, now: Instant) -> Result<MintedToken> {
+    let token = ek.sign(claims.clone(), ttl)?;
+    Ok(MintedToken {
+        value: format!("Bearer {}", token).parse()?,
+        refresh_at: now + ttl * 3 / 4,
+    })
+}
+
+/// Public key of the test key pair, trusted by the configs of the tests only.
+#[cfg(feature = "test_utils")]
+pub const TEST_PK: &str = include_str!("../fixtures/decoding.pem");
+/// Private key of the test key pair.
+#[cfg(feature = "test_utils")]
+pub const TEST_SK: &str = include_str!("../fixtures/encoding.pem");
+
+#[cfg(feature = "test_utils")]
+impl EncodingKey {
+    /// The key of the test key pair.
+    pub fn for_test() -> Self {
+        Self::load(TEST_SK).expect("invalid test key")
+    }
+}
+
+#[cfg(feature = "test_utils")]
+impl ServiceToken {
+    /// Tokens of the service signed with the test key.
+    pub fn for_test(sub: &str) -> Self {
+        Self::new(EncodingKey::for_test(), sub, SERVICE_TOKEN_TTL)
+            .expect("failed to mint test token")
+    }
+}
+
+#[cfg(feature = "test_utils")]
+impl TokenInterceptor {
+    /// Attach a token of the caller with the roles, signed with the test key.
+    pub fn for_test(sub: &str, roles: &[&str]) -> Self {
+        let token = EncodingKey::for_test()
+            .sign(Claims::new(sub, roles), Duration::from_secs(3600))
+            .expect("failed to sign test token");
+        Self::new(&token).expect("invalid test token")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Result<(EncodingKey, DecodingKey)> {
+        let ek = EncodingKey::load(include_str!("../fixtures/encoding.pem"))?;
+        let dk = DecodingKey::load(include_str!("../fixtures/decoding.pem"))?;
+        Ok((ek, dk))
+    }
+
+    fn request(token: Option<&str>) -> Request<()> {
+        let mut req = Request::new(());
+        if let Some(token) = token {
+            let value = format!("Bearer {}", token).parse().unwrap();
+            req.metadata_mut().insert(AUTHORIZATION, value);
+        }
+        req
+    }
+
+    #[test]
+    fn token_should_sign_and_verify() -> Result<()> {
+        let (ek, dk) = keys()?;
+        let claims = Claims::new("crm", &["service"]);
+        let token = ek.sign(claims.clone(), Duration::from_secs(60))?;
+        assert_eq!(dk.verify(&token)?, claims);
+
+        let other = Ed25519KeyPair::generate();
+        let forged = EncodingKey(other).sign(claims, Duration::from_secs(60))?;
+        assert!(dk.verify(&forged).is_err());
+
+        let mut forever = jwt_simple::prelude::Claims::with_custom_claims(
+            CustomClaims {
+                roles: vec!["admin".to_string()],
+            },
+            JwtDuration::from_secs(60),
+        )
+        .with_issuer(JWT_ISS)
+        .with_audience(JWT_AUD);
+        forever.expires_at = None;
+        let token = ek.0.sign(forever)?;
+        let err = dk.verify(&token).unwrap_err();
+        assert!(err.to_string().contains("no expiry"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn interceptor_should_inject_claims() -> Result<()> {
+        let (ek, dk) = keys()?;
+        let mut interceptor = AuthInterceptor::new(dk);
+        let token = ek.sign(Claims::new("alice", &["admin"]), Duration::from_secs(60))?;
+
+        let req = interceptor.call(request(Some(&token)))?;
+        assert_eq!(
+            req.extensions().get::<Claims>(),
+            Some(&Claims::new("alice", &["admin"]))
+        );
+
+        for req in [request(None), request(Some("garbage"))] {
+            let err = interceptor.call(req).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn interceptor_should_accept_tokens_of_trusted_services() -> Result<()> {
+        let (_, dk) = keys()?;
+        let crm = Ed25519KeyPair::generate();
+        let services = HashMap::from([("crm".to_string(), crm.public_key().to_pem())]);
+        let mut interceptor = AuthInterceptor::new(dk).with_services(&services)?;
+        let ek = EncodingKey(crm);
+
+        let claims = Claims::new("crm", &[SERVICE_ROLE, "admin"]);
+        let token = ek.sign(claims, Duration::from_secs(60))?;
+        let req = interceptor.call(request(Some(&token)))?;
+        assert_eq!(
+            req.extensions().get::<Claims>(),
+            Some(&Claims::new("crm", &[SERVICE_ROLE]))
+        );
+
+        // the key of a service doesn't mint tokens of anyone else
+        let token = ek.sign(Claims::new("alice", &["admin"]), Duration::from_secs(60))?;
+        let err = interceptor.call(request(Some(&token))).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        Ok(())
+    }
+
+    #[test]
+    fn service_token_should_be_refreshed_before_expiry() -> Result<()> {
+        let (ek, dk) = keys()?;
+        let ttl = Duration::from_secs(60);
+        let token = ServiceToken::new(ek, "crm", ttl)?;
+        let now = Instant::now();
+
+        let refresh_at = || token.inner.current.lock().unwrap().refresh_at;
+
+        let first = token.current(now)?;
+        let minted_at = refresh_at();
+        assert_eq!(token.current(now + Duration::from_secs(30))?, first);
+        assert_eq!(refresh_at(), minted_at);
+        // the token itself only differs once its jwt timestamps, in seconds, do
+        let second = token.current(now + Duration::from_secs(45))?;
+        assert_eq!(refresh_at(), now + Duration::from_secs(90));
+        assert_eq!(token.current(now + Duration::from_secs(46))?, second);
+        assert_eq!(refresh_at(), now + Duration::from_secs(90));
+
+        let mut auth = AuthInterceptor::new(dk);
+        let bearer = second
+            .to_str()?
+            .strip_prefix("Bearer ")
+            .unwrap()
+            .to_string();
+        let req = auth.call(request(Some(&bearer)))?;
+        assert_eq!(
+            req.extensions().get::<Claims>(),
+            Some(&Claims::new("crm", &[SERVICE_ROLE]))
+        );
+        Ok(())
+    }
+}
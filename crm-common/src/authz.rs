@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::{bail, Result};
+use futures::future::{self, Either, Ready};
+use serde::{Deserialize, Serialize};
+use tonic::{
+    body::BoxBody,
+    codegen::{http, Service},
+    server::NamedService,
+    service::interceptor::InterceptedService,
+    Status,
+};
+use tracing::warn;
+
+use crate::{AuthInterceptor, Claims};
+
+/// Policy entry applying to the methods not listed, of any service.
+const ANY_METHOD: &str = "*";
+
+/// A server only letting through authenticated callers with the roles the policy asks for.
+pub type AuthServer<S> = InterceptedService<Authorized<S>, AuthInterceptor>;
+
+/// Roles allowed to call each method, by full gRPC path, e.g. `/crm.Crm/Welcome`. The
+/// methods of a service not listed fall back to `/<service>/*`, then to `*`; methods the
+/// policy doesn't cover at all are denied.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RolePolicy(HashMap<String, Vec<String>>);
+
+/// Service rejecting calls whose caller lacks the roles of the method.
+#[derive(Debug, Clone)]
+pub struct Authorized<S> {
+    inner: S,
+    policy: Arc<RolePolicy>,
+}
+
+impl RolePolicy {
+    pub fn new(rules: &[(&str, &[&str])]) -> Self {
+        let rules = rules
+            .iter()
+            .map(|(method, roles)| {
+                let roles = roles.iter().map(|v| v.to_string()).collect();
+                (method.to_string(), roles)
+            })
+            .collect();
+        Self(rules)
+    }
+
+    /// Whether the caller may call the method at the path, e.g. `/crm.Crm/Welcome`.
+    pub fn allows(&self, path: &str, claims: &Claims) -> bool {
+        let any_of_service = path
+            .rsplit_once('/')
+            .map(|(service, _)| format!("{}/{}", service, ANY_METHOD));
+        let roles = self
+            .0
+            .get(path)
+            .or_else(|| any_of_service.and_then(|v| self.0.get(&v)))
+            .or_else(|| self.0.get(ANY_METHOD));
+        match roles {
+            Some(roles) => claims.roles.iter().any(|v| roles.contains(v)),
+            None => false,
+        }
+    }
+
+    /// Check each entry is `*` or the path of a method of one of the services, so that a typo
+    /// doesn't silently deny a method.
+    pub fn check(&self, services: &[&str]) -> Result<()> {
+        for path in self.0.keys() {
+            if path == ANY_METHOD {
+                continue;
+            }
+            let known = path
+                .strip_prefix('/')
+                .and_then(|v| v.split_once('/'))
+                .is_some_and(|(service, method)| {
+                    services.contains(&service) && !method.is_empty() && !method.contains('/')
+                });
+            if !known {
+                bail!(
+                    "{:?} is not a method path of {}, e.g. /{}/<method> or /{}/*",
+                    path,
+                    services.join(", "),
+                    services.first().copied().unwrap_or_default(),
+                    services.first().copied().unwrap_or_default(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wrap the server so that callers need a token verified with the PEM encoded public key of the
+/// issuer, or with the key of the service it names, and the roles the policy asks for.
+pub fn authorize<S>(
+    svc: S,
+    pk: &str,
+    services: &HashMap<String, String>,
+    policy: RolePolicy,
+) -> Result<AuthServer<S>> {
+    let auth = AuthInterceptor::load(pk)?.with_services(services)?;
+    let svc = Authorized {
+        inner: svc,
+        policy: Arc::new(policy),
+    };
+    Ok(InterceptedService::new(svc, auth))
+}
+
+impl<S, B> Service<http::Request<B>> for Authorized<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let path = req.uri().path();
+        let method = path.rsplit('/').next().unwrap_or_default();
+        // the claims are put in place by the auth interceptor running before
+        let claims = req.extensions().get::<Claims>();
+        match claims {
+            Some(claims) if self.policy.allows(path, claims) => Either::Right(self.inner.call(req)),
+            _ => {
+                let claims = claims.cloned().unwrap_or_default();
+                warn!(
+                    target: "audit",
+                    sub = %claims.sub,
+                    roles = ?claims.roles,
+                    path = %req.uri().path(),
+                    "permission denied"
+                );
+                let status = Status::permission_denied(format!("{} is not allowed", method));
+                Either::Left(future::ok(status.into_http()))
+            }
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for Authorized<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_should_check_roles_of_listed_methods() {
+        let policy = RolePolicy::new(&[
+            ("/user_stats.UserStats/RawQuery", &["admin"]),
+            ("/user_stats.UserStats/*", &["admin", "marketer"]),
+            ("/notification.Notification/Send", &["service"]),
+        ]);
+        let admin = Claims::new("alice", &["admin"]);
+        let marketer = Claims::new("bob", &["marketer"]);
+
+        assert!(policy.allows("/user_stats.UserStats/RawQuery", &admin));
+        assert!(!policy.allows("/user_stats.UserStats/RawQuery", &marketer));
+        assert!(!policy.allows("/notification.Notification/Send", &admin));
+        assert!(policy.allows("/user_stats.UserStats/Query", &marketer));
+        assert!(!policy.allows("/user_stats.UserStats/Query", &Claims::default()));
+        // methods the policy doesn't cover are denied
+        assert!(!policy.allows("/notification.Notification/Subscribe", &admin));
+        assert!(!policy.allows("/crm.Crm/Query", &admin));
+
+        let policy = RolePolicy::new(&[("*", &["admin"]), ("/crm.Crm/Welcome", &["marketer"])]);
+        assert!(!policy.allows("/crm.Crm/Recall", &marketer));
+        assert!(policy.allows("/crm.Crm/Recall", &admin));
+        assert!(policy.allows("/crm.Crm/Welcome", &marketer));
+    }
+
+    #[test]
+    fn policy_should_only_list_methods_of_the_services() {
+        let services = ["crm.Crm", "user.UserSrv"];
+        let policy = RolePolicy::new(&[
+            ("*", &["admin"]),
+            ("/crm.Crm/*", &["marketer"]),
+            ("/user.UserSrv/GetUser", &["service"]),
+        ]);
+        assert!(policy.check(&services).is_ok());
+
+        for path in [
+            "GetUser",
+            "/user.UserSrv/",
+            "/metadata.Metadata/Preview",
+            "/crm.Crm/a/b",
+        ] {
+            let policy = RolePolicy::new(&[(path, &["admin"])]);
+            assert!(policy.check(&services).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn policy_should_load_from_yaml() -> Result<()> {
+        let yaml = "/user_stats.UserStats/RawQuery: [admin]\n'*': [service]";
+        let policy: RolePolicy = serde_yaml::from_str(yaml)?;
+        assert_eq!(
+            policy,
+            RolePolicy::new(&[
+                ("/user_stats.UserStats/RawQuery", &["admin"]),
+                ("*", &["service"])
+            ])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+
+use crate::{transport::unix_path, DecodingKey, EncodingKey, TlsConfig};
+
+/// Separator of the keys of nested fields in the names of environment variables.
+const ENV_SEPARATOR: &str = "__";
+/// Suffix of the keys whose value is read from the file they point to.
+const FILE_SUFFIX: &str = "_file";
+const CONFIG_FLAG: &str = "--config";
+
+/// Checks of a config beyond what deserializing it catches.
+pub trait Validate {
+    fn validate(&self) -> Result<()>;
+}
+
+/// Loads the config of a service in layers:
+/// - the fields left out fall back to their defaults,
+/// - the YAML file, `--config <path>` or `<PREFIX>_CONFIG` if given, the default file in the
+///   working directory otherwise, which may be missing,
+/// - environment variables, e.g. `CRM_SERVER__PORT=50010` for `server.port`; values are read as
+///   YAML, so strings reading as numbers need quotes, and those matching no field are rejected by
+///   configs denying unknown fields,
+/// - `<key>_file: <path>` in either sets `<key>` to the content of the file, for secrets
+///   mounted into containers, e.g. `CRM_AUTH__SK_FILE=/run/secrets/sk`.
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    file: PathBuf,
+    prefix: String,
+    excluded: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl ConfigLoader {
+    pub fn new(file: impl Into<PathBuf>, prefix: &str) -> Self {
+        Self {
+            file: file.into(),
+            prefix: format!("{}_", prefix),
+            excluded: vec![],
+            path: None,
+        }
+    }
+
+    /// Leave out the variables of the services whose prefixes start with this one, e.g.
+    /// `CRM_METADATA` for `CRM`.
+    pub fn excluding(mut self, prefixes: &[&str]) -> Self {
+        self.excluded = prefixes.iter().map(|v| format!("{}_", v)).collect();
+        self
+    }
+
+    /// Read the file at the path, which must exist.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Read the file given by `--config <path>` or `--config=<path>` among the arguments, if any.
+    pub fn with_args(self, args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == CONFIG_FLAG {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("{} requires a path", CONFIG_FLAG))?;
+                return Ok(self.with_path(path));
+            }
+            if let Some(path) = arg.strip_prefix("--config=") {
+                return Ok(self.with_path(path));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Read the file given by `--config` among the command line arguments of the process, if any.
+    pub fn with_process_args(self) -> Result<Self> {
+        self.with_args(env::args().skip(1))
+    }
+
+    /// The file the config is read from, which may be missing unless given explicitly.
+    pub fn path(&self) -> PathBuf {
+        self.config_path(env::vars())
+            .unwrap_or_else(|| self.file.clone())
+    }
+
+    /// Load the config with the environment variables of the process.
+    pub fn load<T: DeserializeOwned + Validate>(&self) -> Result<T> {
+        self.load_from(env::vars())
+    }
+
+    /// Load the config with the given environment variables.
+    pub fn load_from<T: DeserializeOwned + Validate>(
+        &self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<T> {
+        let vars: Vec<_> = vars
+            .into_iter()
+            .filter(|(k, _)| {
+                k.starts_with(&self.prefix) && !self.excluded.iter().any(|v| k.starts_with(v))
+            })
+            .collect();
+        let config_var = self.config_var();
+        let path = self.config_path(vars.iter().cloned());
+        let source = path.as_deref().unwrap_or(&self.file).display().to_string();
+
+        let mut value = match &path {
+            Some(path) => read_yaml(path)?,
+            None if self.file.exists() => read_yaml(&self.file)?,
+            None => Value::Mapping(Mapping::new()),
+        };
+        for (k, v) in vars.iter().filter(|(k, _)| *k != config_var) {
+            let keys: Vec<_> = k[self.prefix.len()..]
+                .split(ENV_SEPARATOR)
+                .map(|v| v.to_lowercase())
+                .collect();
+            // multi-line values, e.g. PEM keys, are taken as they are
+            let v = match v.contains('\n') {
+                true => Value::String(v.clone()),
+                false => serde_yaml::from_str(v).unwrap_or_else(|_| Value::String(v.clone())),
+            };
+            set(&mut value, &keys, v).with_context(|| format!("invalid override {}", k))?;
+        }
+        read_secrets(&mut value, "")?;
+
+        // going through the text gets the errors the path of the field
+        let text = serde_yaml::to_string(&value)?;
+        let config: T = serde_yaml::from_str(&text)
+            .map_err(|e| anyhow!("invalid config from {} and {}*: {}", source, self.prefix, e))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid config from {} and {}*", source, self.prefix))?;
+        Ok(config)
+    }
+}
+
+#[cfg(feature = "test_utils")]
+impl ConfigLoader {
+    /// Load the config as `load` does, with the overrides on top; their keys leave out the
+    /// prefix, e.g. `AUTH__PK`.
+    pub fn load_for_test<T: DeserializeOwned + Validate>(
+        &self,
+        overrides: &[(&str, &str)],
+    ) -> Result<T> {
+        let overrides = overrides
+            .iter()
+            .map(|(k, v)| (format!("{}{}", self.prefix, k), v.to_string()));
+        self.load_from(env::vars().chain(overrides))
+    }
+}
+
+impl ConfigLoader {
+    fn config_var(&self) -> String {
+        format!("{}CONFIG", self.prefix)
+    }
+
+    /// The file given explicitly, by the arguments or the environment.
+    fn config_path(&self, vars: impl IntoIterator<Item = (String, String)>) -> Option<PathBuf> {
+        let config_var = self.config_var();
+        self.path.clone().or_else(|| {
+            vars.into_iter()
+                .find(|(k, _)| *k == config_var)
+                .map(|(_, v)| PathBuf::from(v))
+        })
+    }
+}
+
+/// Name the field, a `.` separated path, in the error of its check.
+pub fn check<T>(field: &str, ret: Result<T>) -> Result<T> {
+    ret.with_context(|| format!("{} is invalid", field))
+}
+
+/// Check a database url.
+pub fn check_db_url(field: &str, url: &str) -> Result<()> {
+    if !url.starts_with("mysql://") {
+        bail!("{} must be a mysql:// url, got {:?}", field, url);
+    }
+    Ok(())
+}
+
+/// Check the address of a service.
+pub fn check_endpoint(field: &str, addr: &str) -> Result<()> {
+    if !addr.starts_with("http://") && !addr.starts_with("https://") {
+        bail!(
+            "{} must be an http:// or https:// url, got {:?}",
+            field,
+            addr
+        );
+    }
+    Ok(())
+}
+
+/// Check the address of a service called over gRPC, which may be a `unix:` socket.
+pub fn check_service(field: &str, addr: &str) -> Result<()> {
+    if unix_path(addr).is_none() && !addr.starts_with("http://") && !addr.starts_with("https://") {
+        bail!(
+            "{} must be an http://, https:// or unix: address, got {:?}",
+            field,
+            addr
+        );
+    }
+    Ok(())
+}
+
+/// Check PEM encoded public key tokens are verified with.
+pub fn check_pk(field: &str, pk: &str) -> Result<()> {
+    check(field, DecodingKey::load(pk).map(|_| ()))
+}
+
+/// Check the PEM encoded public keys of the services, by name.
+pub fn check_service_keys(field: &str, services: &HashMap<String, String>) -> Result<()> {
+    for (name, pk) in services {
+        check_pk(&format!("{}.{}", field, name), pk)?;
+    }
+    Ok(())
+}
+
+/// Check PEM encoded private key tokens are signed with.
+pub fn check_sk(field: &str, sk: &str) -> Result<()> {
+    check(field, EncodingKey::load(sk).map(|_| ()))
+}
+
+/// Check the certificates of the TLS config can be read.
+pub fn check_tls(field: &str, tls: Option<&TlsConfig>) -> Result<()> {
+    match tls {
+        Some(tls) => check(field, tls.server_config().map(|_| ())),
+        None => Ok(()),
+    }
+}
+
+fn read_yaml(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    serde_yaml::from_str(&text).with_context(|| format!("invalid YAML in {}", path.display()))
+}
+
+/// Set the value at the keys, adding the mappings missing on the way.
+fn set(value: &mut Value, keys: &[String], v: Value) -> Result<()> {
+    let Some((key, rest)) = keys.split_first() else {
+        *value = v;
+        return Ok(());
+    };
+    if value.is_null() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    let Value::Mapping(map) = value else {
+        bail!("{} is not a mapping", key);
+    };
+    let key = Value::String(key.clone());
+    let entry = map.entry(key).or_insert(Value::Null);
+    set(entry, rest, v)
+}
+
+/// Replace the `<key>_file` entries by `<key>` with the content of the file.
+fn read_secrets(value: &mut Value, path: &str) -> Result<()> {
+    let Value::Mapping(map) = value else {
+        return Ok(());
+    };
+    let secrets: Vec<_> = map
+        .iter()
+        .filter_map(|(k, v)| {
+            let name = k.as_str()?.strip_suffix(FILE_SUFFIX)?;
+            Some((k.clone(), name.to_string(), v.as_str()?.to_string()))
+        })
+        .collect();
+    for (key, name, file) in secrets {
+        let secret = fs::read_to_string(&file)
+            .with_context(|| format!("failed to read {}{}_file {}", path, name, file))?;
+        map.remove(&key);
+        map.insert(
+            Value::String(name),
+            Value::String(secret.trim_end().to_string()),
+        );
+    }
+    for (k, v) in map.iter_mut() {
+        let path = format!("{}{}.", path, k.as_str().unwrap_or_default());
+        read_secrets(v, &path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfig {
+        server: TestServer,
+        #[serde(default)]
+        secret: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct TestServer {
+        port: u16,
+        db_url: String,
+        #[serde(default)]
+        debug: bool,
+    }
+
+    impl Validate for TestConfig {
+        fn validate(&self) -> Result<()> {
+            check_db_url("server.db_url", &self.server.db_url)
+        }
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn write(name: &str, content: &str) -> Result<PathBuf> {
+        let path = env::temp_dir().join(format!("{}-{}", uuid::Uuid::new_v4(), name));
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn env_should_override_file() -> Result<()> {
+        let file = write(
+            "test.yml",
+            "server:\n  port: 50000\n  db_url: mysql://a/b\n",
+        )?;
+        let loader = ConfigLoader::new(&file, "TEST");
+        let config: TestConfig = loader.load_from(vars(&[
+            ("TEST_SERVER__PORT", "50010"),
+            ("TEST_SERVER__DEBUG", "true"),
+            ("OTHER_SERVER__PORT", "1"),
+        ]))?;
+        assert_eq!(config.server.port, 50010);
+        assert_eq!(config.server.db_url, "mysql://a/b");
+        assert!(config.server.debug);
+
+        // the variables of a service with a longer prefix are not overrides
+        let loader = ConfigLoader::new(&file, "TEST").excluding(&["TEST_OTHER"]);
+        let config: TestConfig = loader.load_from(vars(&[("TEST_OTHER_SERVER__PORT", "1")]))?;
+        assert_eq!(config.server.port, 50000);
+
+        let err = loader
+            .load_from::<TestConfig>(vars(&[("TEST_SERVER__PROT", "1")]))
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("unknown field `prot`"),
+            "{err:#}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn config_should_load_from_env_and_secret_files() -> Result<()> {
+        let secret = write("secret", "s3cret\n")?;
+        let loader = ConfigLoader::new("missing.yml", "TEST");
+        let config: TestConfig = loader.load_from(vars(&[
+            ("TEST_SERVER__PORT", "50010"),
+            ("TEST_SERVER__DB_URL", "mysql://a/b"),
+            ("TEST_SECRET_FILE", secret.to_str().unwrap()),
+        ]))?;
+        assert_eq!(config.secret, "s3cret");
+        Ok(())
+    }
+
+    #[test]
+    fn config_should_report_what_is_wrong() -> Result<()> {
+        let loader = ConfigLoader::new("missing.yml", "TEST");
+        let err = loader
+            .load_from::<TestConfig>(vars(&[("TEST_SERVER__PORT", "50010")]))
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("missing field `db_url`"),
+            "{err:#}"
+        );
+
+        let err = loader
+            .load_from::<TestConfig>(vars(&[
+                ("TEST_SERVER__PORT", "50010"),
+                ("TEST_SERVER__DB_URL", "postgres://a/b"),
+            ]))
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("server.db_url"), "{err:#}");
+
+        let loader = loader.with_args(vec!["--config".to_string(), "nope.yml".to_string()])?;
+        let err = loader.load_from::<TestConfig>(vec![]).unwrap_err();
+        assert!(format!("{:#}", err).contains("nope.yml"), "{err:#}");
+        Ok(())
+    }
+}
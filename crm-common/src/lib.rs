@@ -1,3 +1,23 @@
+mod auth;
+mod authz;
+mod config;
 mod mysql;
+mod reload;
+mod tls;
+mod transport;
 
+pub use auth::{
+    AuthChannel, AuthInterceptor, Claims, DecodingKey, EncodingKey, ServiceToken, TokenInterceptor,
+    SERVICE_ROLE,
+};
+#[cfg(feature = "test_utils")]
+pub use auth::{TEST_PK, TEST_SK};
+pub use authz::{authorize, AuthServer, Authorized, RolePolicy};
+pub use config::{
+    check, check_db_url, check_endpoint, check_pk, check_service, check_service_keys, check_sk,
+    check_tls, ConfigLoader, Validate,
+};
 pub use mysql::TestMysql;
+pub use reload::{watch_config, Reload, Reloadable};
+pub use tls::{endpoint, server_builder, TlsConfig};
+pub use transport::{connect, connect_lazy, serve, ListenAddr, ListenConfig};
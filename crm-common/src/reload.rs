@@ -0,0 +1,259 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use tracing::{info, warn};
+
+use crate::{ConfigLoader, Validate};
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A value replaced as a whole on reload; readers keep the snapshot they got until they are done.
+#[derive(Debug)]
+pub struct Reloadable<T> {
+    current: RwLock<Arc<T>>,
+}
+
+/// A config whose settings are partly read at start only, e.g. the listen address.
+pub trait Reload {
+    /// Whether the new config changes settings read at start only.
+    fn needs_restart(&self, new: &Self) -> bool;
+}
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(value)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.current
+            .read()
+            .expect("reloadable lock poisoned")
+            .clone()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.current.write().expect("reloadable lock poisoned") = Arc::new(value);
+    }
+}
+
+impl<T: Reload> Reloadable<T> {
+    /// The current config, if the new one changes none of the settings read at start only.
+    pub fn check_reload(&self, new: &T) -> Result<Arc<T>> {
+        let current = self.get();
+        if current.needs_restart(new) {
+            bail!(
+                "the listen address, tls, db_url and auth only change on restart, restart to apply"
+            );
+        }
+        Ok(current)
+    }
+
+    /// Swap in the new config, unless it changes settings read at start only.
+    pub fn reload(&self, new: T) -> Result<()> {
+        self.check_reload(&new)?;
+        self.set(new);
+        Ok(())
+    }
+}
+
+/// Reload the config when its file changes or the process gets SIGHUP, handing valid ones to
+/// `apply`. Configs failing to load, or rejected by `apply`, are logged and the running one kept.
+pub fn watch_config<T, F>(loader: ConfigLoader, apply: F)
+where
+    T: DeserializeOwned + Validate + Send + 'static,
+    F: Fn(T) -> Result<()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut watcher = Watcher::new(loader, apply);
+        let mut hangups = hangups();
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            let hangup = tokio::select! {
+                _ = interval.tick() => false,
+                _ = next_hangup(&mut hangups) => true,
+            };
+            watcher.check(hangup);
+        }
+    });
+}
+
+/// Reloads the config file when it changed, or when asked to.
+struct Watcher<F> {
+    loader: ConfigLoader,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    apply: F,
+}
+
+impl<F> Watcher<F> {
+    fn new(loader: ConfigLoader, apply: F) -> Self {
+        let path = loader.path();
+        Self {
+            modified: modified_at(&path),
+            loader,
+            path,
+            apply,
+        }
+    }
+
+    fn check<T>(&mut self, hangup: bool)
+    where
+        T: DeserializeOwned + Validate,
+        F: Fn(T) -> Result<()>,
+    {
+        let modified = modified_at(&self.path);
+        if !hangup && modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+
+        info!("reloading config from {}", self.path.display());
+        match self.loader.load::<T>().and_then(&self.apply) {
+            Ok(()) => info!("config reloaded"),
+            Err(e) => warn!("config rejected, keeping the running one: {:#}", e),
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|v| v.modified()).ok()
+}
+
+#[cfg(unix)]
+type Hangups = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type Hangups = ();
+
+/// SIGHUP of the process, if it can be listened for.
+#[cfg(unix)]
+fn hangups() -> Hangups {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    signal(SignalKind::hangup())
+        .map_err(|e| warn!("failed to listen for SIGHUP: {}", e))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn hangups() -> Hangups {}
+
+/// Wait for the next SIGHUP, forever if there is none to listen for.
+async fn next_hangup(hangups: &mut Hangups) {
+    #[cfg(unix)]
+    if let Some(signal) = hangups {
+        if signal.recv().await.is_some() {
+            return;
+        }
+    }
+    let _ = hangups;
+    std::future::pending().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use anyhow::bail;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfig {
+        sender: String,
+        #[serde(default)]
+        port: u16,
+    }
+
+    impl Validate for TestConfig {
+        fn validate(&self) -> Result<()> {
+            if self.sender.is_empty() {
+                bail!("sender is required");
+            }
+            Ok(())
+        }
+    }
+
+    impl Reload for TestConfig {
+        fn needs_restart(&self, new: &Self) -> bool {
+            self.port != new.port
+        }
+    }
+
+    #[test]
+    fn reloadable_should_keep_snapshots() {
+        let config = Reloadable::new(1);
+        let snapshot = config.get();
+        config.set(2);
+        assert_eq!((*snapshot, *config.get()), (1, 2));
+    }
+
+    #[test]
+    fn reload_should_keep_settings_read_at_start() -> Result<()> {
+        let config = Reloadable::new(TestConfig {
+            sender: "a@acme.org".to_string(),
+            port: 50000,
+        });
+        let new = |sender: &str, port| TestConfig {
+            sender: sender.to_string(),
+            port,
+        };
+        config.reload(new("b@acme.org", 50000))?;
+        assert_eq!(config.get().sender, "b@acme.org");
+
+        assert!(config.reload(new("c@acme.org", 50010)).is_err());
+        assert_eq!(
+            (config.get().sender.as_str(), config.get().port),
+            ("b@acme.org", 50000)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn config_should_reload_on_change_and_hangup() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("{}-reload.yml", uuid::Uuid::new_v4()));
+        fs::write(&path, "sender: a@acme.org")?;
+        let config = Arc::new(Reloadable::new(String::new()));
+        let applied = Arc::new(Mutex::new(0));
+
+        let (c, n) = (config.clone(), applied.clone());
+        let loader = ConfigLoader::new("missing.yml", "TEST_RELOAD").with_path(&path);
+        let mut watcher = Watcher::new(loader, move |v: TestConfig| {
+            *n.lock().unwrap() += 1;
+            c.set(v.sender);
+            Ok(())
+        });
+
+        watcher.check::<TestConfig>(false);
+        assert_eq!(*applied.lock().unwrap(), 0);
+
+        watcher.check::<TestConfig>(true);
+        assert_eq!(config.get().as_str(), "a@acme.org");
+
+        // an invalid config is not applied
+        fs::write(&path, "sender: ''")?;
+        watcher.check::<TestConfig>(true);
+        assert_eq!(*applied.lock().unwrap(), 1);
+        assert_eq!(config.get().as_str(), "a@acme.org");
+
+        // nor checked again until it changes
+        let modified = SystemTime::now() + Duration::from_secs(10);
+        fs::write(&path, "sender: b@acme.org")?;
+        fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+        watcher.check::<TestConfig>(false);
+        assert_eq!(config.get().as_str(), "b@acme.org");
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity, Server, ServerTlsConfig};
+
+/// TLS set up of a service. The service presents the same certificate to its callers and to the
+/// services it calls, and checks theirs against the CA.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// path of the PEM encoded certificate chain
+    pub cert: String,
+    /// path of the PEM encoded private key of the certificate
+    pub key: String,
+    /// path of the PEM encoded CA certificates the peers' certificates are checked against
+    #[serde(default)]
+    pub ca: Option<String>,
+    /// reject callers without a client certificate signed by the CA, which must be given
+    #[serde(default)]
+    pub require_client_cert: bool,
+    /// name the certificates of called services are checked against, the host of their
+    /// address by default
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn server_config(&self) -> Result<ServerTlsConfig> {
+        let mut config = ServerTlsConfig::new().identity(self.identity()?);
+        match self.ca()? {
+            Some(ca) => {
+                config = config
+                    .client_ca_root(ca)
+                    .client_auth_optional(!self.require_client_cert);
+            }
+            // without a CA the client certificates would go unchecked
+            None if self.require_client_cert => bail!("require_client_cert needs ca"),
+            None => {}
+        }
+        Ok(config)
+    }
+
+    pub fn client_config(&self) -> Result<ClientTlsConfig> {
+        let mut config = ClientTlsConfig::new().identity(self.identity()?);
+        if let Some(ca) = self.ca()? {
+            config = config.ca_certificate(ca);
+        }
+        if let Some(domain) = &self.domain {
+            config = config.domain_name(domain);
+        }
+        Ok(config)
+    }
+
+    fn identity(&self) -> Result<Identity> {
+        let cert = read(&self.cert)?;
+        let key = read(&self.key)?;
+        Ok(Identity::from_pem(cert, key))
+    }
+
+    fn ca(&self) -> Result<Option<Certificate>> {
+        let ca = self.ca.as_deref().map(read).transpose()?;
+        Ok(ca.map(Certificate::from_pem))
+    }
+}
+
+/// A server builder, serving TLS if configured.
+pub fn server_builder(tls: Option<&TlsConfig>) -> Result<Server> {
+    let builder = Server::builder();
+    match tls {
+        Some(tls) => Ok(builder.tls_config(tls.server_config()?)?),
+        None => Ok(builder),
+    }
+}
+
+/// The endpoint of a service, reached over TLS if configured.
+pub fn endpoint(addr: &str, tls: Option<&TlsConfig>) -> Result<Endpoint> {
+    let endpoint = Endpoint::from_shared(addr.to_string())?;
+    match tls {
+        Some(tls) => Ok(endpoint.tls_config(tls.client_config()?)?),
+        None => Ok(endpoint),
+    }
+}
+
+fn read(path: &str) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("failed to read {}", path))
+}
+
+#[cfg(feature = "test_utils")]
+impl TlsConfig {
+    /// The test certificate of the given name, `server` or `client`, checking peers against the
+    /// test CA.
+    pub fn for_test(name: &str) -> Self {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/tls");
+        Self {
+            cert: format!("{}/{}.pem", dir, name),
+            key: format!("{}/{}.key", dir, name),
+            ca: Some(format!("{}/ca.pem", dir)),
+            require_client_cert: true,
+            domain: Some("localhost".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tls_config_should_load_from_yaml() -> Result<()> {
+        let config: TlsConfig = serde_yaml::from_str("cert: a.pem\nkey: a.key")?;
+        assert_eq!(config.ca, None);
+        assert!(!config.require_client_cert);
+
+        let config = TlsConfig {
+            cert: "missing.pem".to_string(),
+            ..config
+        };
+        assert!(config.server_config().is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "test_utils")]
+    #[test]
+    fn client_certs_should_need_ca() -> Result<()> {
+        let config = TlsConfig::for_test("server");
+        assert!(config.server_config().is_ok());
+
+        let config = TlsConfig { ca: None, ..config };
+        let err = config.server_config().unwrap_err();
+        assert!(err.to_string().contains("needs ca"), "{err}");
+
+        let config = TlsConfig {
+            require_client_cert: false,
+            ..config
+        };
+        assert!(config.server_config().is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use tonic::transport::{server::Router, Channel, Endpoint};
+use tracing::warn;
+
+use crate::{endpoint, TlsConfig};
+
+/// Scheme of the addresses of services reached over a Unix domain socket, e.g.
+/// `unix:/run/crm/user-stat.sock`.
+const UNIX_SCHEME: &str = "unix:";
+
+/// Where a server listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+/// Where a server listens on top of its port: the host, or a Unix domain socket instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenConfig {
+    /// host name or IP address to listen on, loopback by default; `0.0.0.0` for the service to
+    /// be reached from other hosts, e.g. out of a container
+    #[serde(default = "default_host")]
+    pub host: String,
+    /// Unix domain socket to listen on instead of the host and port, if set
+    #[serde(default)]
+    pub uds: Option<PathBuf>,
+}
+
+impl ListenConfig {
+    /// The address to listen on with the port.
+    pub async fn resolve(&self, port: u16) -> Result<ListenAddr> {
+        ListenAddr::resolve(&self.host, port, self.uds.as_deref()).await
+    }
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            host: default_host(),
+            uds: None,
+        }
+    }
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+impl ListenAddr {
+    /// The Unix domain socket if given, the first address the host resolves to otherwise.
+    pub async fn resolve(host: &str, port: u16, uds: Option<&Path>) -> Result<Self> {
+        if let Some(path) = uds {
+            return Ok(Self::Unix(path.to_path_buf()));
+        }
+        let addr = tokio::net::lookup_host((host, port))
+            .await
+            .with_context(|| format!("failed to resolve {}", host))?
+            .next()
+            .ok_or_else(|| anyhow!("{} resolves to no address", host))?;
+        Ok(Self::Tcp(addr))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{}", addr),
+            Self::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+        }
+    }
+}
+
+/// Serve the services of the router at the address until the server fails.
+pub async fn serve(router: Router, addr: &ListenAddr) -> Result<()> {
+    match addr {
+        ListenAddr::Tcp(addr) => router.serve(*addr).await?,
+        ListenAddr::Unix(path) => serve_unix(router, path).await?,
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_unix(router: Router, path: &Path) -> Result<()> {
+    remove_stale_socket(path)?;
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("failed to listen on {}", path.display()))?;
+    let _socket = SocketFile(path.to_path_buf());
+    let incoming = stream::unfold(listener, |listener| async {
+        let conn = listener.accept().await.map(|(stream, _)| stream);
+        Some((conn, listener))
+    });
+    router
+        .serve_with_incoming_shutdown(incoming, shutdown())
+        .await?;
+    Ok(())
+}
+
+/// Remove the socket a previous run left behind; a socket still accepting connections, or
+/// anything else at the path, is kept.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("failed to stat {}", path.display())),
+    };
+    if !metadata.file_type().is_socket() {
+        bail!("{} exists and is not a socket", path.display());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        bail!("{} is already in use", path.display());
+    }
+    std::fs::remove_file(path)
+        .with_context(|| format!("failed to remove stale socket {}", path.display()))
+}
+
+/// The socket a server listens on, removed once it stops.
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            warn!("failed to remove socket {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Wait for SIGINT or SIGTERM, for the server to stop and clean up after itself.
+#[cfg(unix)]
+async fn shutdown() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_router: Router, path: &Path) -> Result<()> {
+    anyhow::bail!("unix domain sockets are not supported, {}", path.display())
+}
+
+/// A channel to the service at the address, `http(s)://` or `unix:<path>`, over TLS if
+/// configured.
+pub async fn connect(addr: &str, tls: Option<&TlsConfig>) -> Result<Channel> {
+    #[cfg(unix)]
+    if let Some(path) = unix_path(addr) {
+        let connector = unix_connector(path.to_path_buf());
+        return Ok(unix_endpoint(tls)?
+            .connect_with_connector(connector)
+            .await?);
+    }
+    Ok(endpoint(addr, tls)?.connect().await?)
+}
+
+/// Like `connect`, connecting on the first call.
+pub fn connect_lazy(addr: &str, tls: Option<&TlsConfig>) -> Result<Channel> {
+    #[cfg(unix)]
+    if let Some(path) = unix_path(addr) {
+        let connector = unix_connector(path.to_path_buf());
+        return Ok(unix_endpoint(tls)?.connect_with_connector_lazy(connector));
+    }
+    Ok(endpoint(addr, tls)?.connect_lazy())
+}
+
+/// The socket of a `unix:` address, with or without `//`.
+pub(crate) fn unix_path(addr: &str) -> Option<&Path> {
+    let path = addr.strip_prefix(UNIX_SCHEME)?;
+    Some(Path::new(path.strip_prefix("//").unwrap_or(path)))
+}
+
+/// The endpoint calls over a Unix domain socket go through; the host only names the
+/// certificate expected of the service when not configured.
+#[cfg(unix)]
+fn unix_endpoint(tls: Option<&TlsConfig>) -> Result<Endpoint> {
+    let uri = match tls {
+        Some(_) => "https://localhost",
+        None => "http://localhost",
+    };
+    endpoint(uri, tls)
+}
+
+#[cfg(unix)]
+fn unix_connector(
+    path: PathBuf,
+) -> impl tower::Service<
+    tonic::transport::Uri,
+    Response = hyper_util::rt::TokioIo<tokio::net::UnixStream>,
+    Error = std::io::Error,
+    Future = impl Send,
+> + Send
+       + 'static {
+    tower::service_fn(move |_| {
+        let path = path.clone();
+        async move {
+            let stream = tokio::net::UnixStream::connect(path).await?;
+            Ok(hyper_util::rt::TokioIo::new(stream))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_addresses_should_name_the_socket() {
+        let path = Some(Path::new("/run/crm/user-stat.sock"));
+        assert_eq!(unix_path("unix:/run/crm/user-stat.sock"), path);
+        assert_eq!(unix_path("unix:///run/crm/user-stat.sock"), path);
+        assert_eq!(unix_path("http://localhost:50001"), None);
+    }
+
+    #[tokio::test]
+    async fn listen_addr_should_resolve_host() -> Result<()> {
+        let addr = ListenAddr::resolve("0.0.0.0", 50000, None).await?;
+        assert_eq!(addr.to_string(), "0.0.0.0:50000");
+
+        let addr = ListenAddr::resolve("localhost", 50000, None).await?;
+        assert!(matches!(addr, ListenAddr::Tcp(v) if v.ip().is_loopback()));
+
+        let uds = Path::new("/tmp/crm.sock");
+        let addr = ListenAddr::resolve("0.0.0.0", 50000, Some(uds)).await?;
+        assert_eq!(addr.to_string(), "unix:/tmp/crm.sock");
+
+        let addr = ListenConfig::default().resolve(50000).await?;
+        assert_eq!(addr.to_string(), "127.0.0.1:50000");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_stale_sockets_should_be_removed() -> Result<()> {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("{}.sock", uuid::Uuid::new_v4()));
+        remove_stale_socket(&path)?;
+
+        std::os::unix::net::UnixListener::bind(&path)?;
+        remove_stale_socket(&path)?;
+        assert!(!path.exists());
+
+        let listener = std::os::unix::net::UnixListener::bind(&path)?;
+        let err = remove_stale_socket(&path).unwrap_err();
+        assert!(err.to_string().contains("already in use"), "{err}");
+        assert!(path.exists());
+        drop(listener);
+        std::fs::remove_file(&path)?;
+
+        let file = dir.join(format!("{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "keep me")?;
+        let err = remove_stale_socket(&file).unwrap_err();
+        assert!(err.to_string().contains("not a socket"), "{err}");
+        assert!(file.exists());
+        std::fs::remove_file(file)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+-- Add migration script here
+
+CREATE TABLE templates(
+    name varchar(64) NOT NULL COMMENT 'template name, e.g. welcome',
+    locale varchar(16) NOT NULL COMMENT 'template locale, e.g. en, zh-CN',
+    version int unsigned NOT NULL COMMENT 'version of the template per name and locale',
+    subject varchar(255) NOT NULL COMMENT 'subject template',
+    body text NOT NULL COMMENT 'body template',
+    active tinyint(1) NOT NULL DEFAULT 0 COMMENT 'whether the version is in use',
+    created_at datetime NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (name, locale, version),
+    KEY `idx_name_active` (name, active)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Message templates';
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use prost_types::Timestamp;
+
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Build the lookup chain for a locale, from the most specific tag to the default locale,
+/// e.g. `zh-CN` -> `[zh-CN, zh, en]`.
+pub fn fallback_chain(locale: &str) -> Vec<String> {
+    let locale = normalize(locale);
+    let mut chain = Vec::new();
+
+    let mut tag = locale.as_str();
+    while !tag.is_empty() {
+        chain.push(tag.to_string());
+        tag = match tag.rfind('-') {
+            Some(i) => &tag[..i],
+            None => "",
+        };
+    }
+
+    if !chain.iter().any(|v| v == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+
+    chain
+}
+
+/// Normalize a locale tag: `zh_cn` -> `zh-CN`, `EN` -> `en`.
+pub fn normalize(locale: &str) -> String {
+    locale
+        .trim()
+        .split(['-', '_'])
+        .filter(|v| !v.is_empty())
+        .enumerate()
+        .map(|(i, v)| match (i, v.len()) {
+            (0, _) => v.to_lowercase(),
+            (_, 2) => v.to_uppercase(),
+            _ => v.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn language(locale: &str) -> String {
+    normalize(locale)
+        .split('-')
+        .next()
+        .unwrap_or(DEFAULT_LOCALE)
+        .to_string()
+}
+
+/// Greet the recipient in the language of the locale, e.g. `Hi Alice` or `你好，Alice`.
+pub fn greeting(name: &str, locale: &str) -> String {
+    let name = name.trim();
+    let (hello, sep) = match language(locale).as_str() {
+        "zh" => ("你好", "，"),
+        "ja" => ("こんにちは", "、"),
+        "ko" => ("안녕하세요", " "),
+        "fr" => ("Bonjour", " "),
+        "de" => ("Hallo", " "),
+        "es" => ("Hola", " "),
+        _ => ("Hi", " "),
+    };
+
+    match name.is_empty() {
+        true => hello.to_string(),
+        false => format!("{hello}{sep}{name}"),
+    }
+}
+
+/// Format a number with the grouping separator of the locale, e.g. `1,234,567` for `en`
+/// and `1.234.567` for `de`.
+pub fn format_number(n: u64, locale: &str) -> String {
+    let sep = match language(locale).as_str() {
+        "de" | "es" | "it" | "pt" | "nl" | "id" | "tr" => ".",
+        "fr" | "ru" | "pl" | "sv" | "cs" | "uk" | "fi" | "nb" => "\u{a0}",
+        _ => ",",
+    };
+
+    let digits = n.to_string();
+    let mut ret = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            ret.push_str(sep);
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Format the date part of a timestamp the way the locale usually writes it.
+pub fn format_date(ts: &Timestamp, locale: &str) -> String {
+    let Some(dt) = Utc.timestamp_opt(ts.seconds, ts.nanos as _).single() else {
+        return String::new();
+    };
+
+    format_datetime(&dt, locale)
+}
+
+fn format_datetime(dt: &DateTime<Utc>, locale: &str) -> String {
+    let locale = normalize(locale);
+    match language(&locale).as_str() {
+        "zh" | "ja" => format!("{}年{}月{}日", dt.year(), dt.month(), dt.day()),
+        "ko" => format!("{}년 {}월 {}일", dt.year(), dt.month(), dt.day()),
+        "de" | "ru" | "pl" | "tr" => dt.format("%d.%m.%Y").to_string(),
+        "fr" | "es" | "it" | "pt" => dt.format("%d/%m/%Y").to_string(),
+        "en" if locale == "en-US" => dt.format("%b %-d, %Y").to_string(),
+        "en" => dt.format("%-d %b %Y").to_string(),
+        _ => dt.format("%Y-%m-%d").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_chain_should_work() {
+        assert_eq!(fallback_chain("zh-CN"), vec!["zh-CN", "zh", "en"]);
+        assert_eq!(fallback_chain("zh_cn"), vec!["zh-CN", "zh", "en"]);
+        assert_eq!(fallback_chain("en-US"), vec!["en-US", "en"]);
+        assert_eq!(fallback_chain(""), vec!["en"]);
+    }
+
+    #[test]
+    fn greeting_should_work() {
+        assert_eq!(greeting("Alice", "en-US"), "Hi Alice");
+        assert_eq!(greeting(" ", "en"), "Hi");
+        assert_eq!(greeting("张三", "zh-CN"), "你好，张三");
+        assert_eq!(greeting("Anna", "de"), "Hallo Anna");
+    }
+
+    #[test]
+    fn format_number_should_work() {
+        assert_eq!(format_number(0, "en"), "0");
+        assert_eq!(format_number(999, "en"), "999");
+        assert_eq!(format_number(1234567, "en"), "1,234,567");
+        assert_eq!(format_number(1234567, "de-DE"), "1.234.567");
+        assert_eq!(format_number(1234567, "fr"), "1\u{a0}234\u{a0}567");
+    }
+
+    #[test]
+    fn format_date_should_work() {
+        let ts = Timestamp {
+            seconds: 1704164645, // 2024-01-02T03:04:05Z
+            nanos: 0,
+        };
+        assert_eq!(format_date(&ts, "en-US"), "Jan 2, 2024");
+        assert_eq!(format_date(&ts, "en"), "2 Jan 2024");
+        assert_eq!(format_date(&ts, "zh-CN"), "2024年1月2日");
+        assert_eq!(format_date(&ts, "de"), "02.01.2024");
+        assert_eq!(format_date(&ts, "fr-FR"), "02/01/2024");
+    }
+}
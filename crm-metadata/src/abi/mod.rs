@@ -1,3 +1,7 @@
+mod locale;
+mod template;
+mod tpl;
+
 use std::{collections::HashSet, hash::Hash};
 
 use chrono::{DateTime, Days, Utc};
@@ -18,6 +22,9 @@ use crate::{
     MetadataService, ResponseStream, ServiceResult,
 };
 
+pub use locale::{fallback_chain, format_date, format_number, greeting, DEFAULT_LOCALE};
+pub use tpl::{MessageTemplate, Rendered, TemplateSet, Tpl};
+
 const CHANNEL_SIZE: usize = 1024;
 
 impl MetadataService {
@@ -66,14 +73,6 @@ impl Content {
     }
 }
 
-pub struct Tpl<'a>(pub &'a [Content]);
-
-impl<'a> Tpl<'a> {
-    pub fn to_body(&self) -> String {
-        format!("Tpl: {:?}", self.0)
-    }
-}
-
 impl Eq for MaterializeRequest {}
 impl Hash for MaterializeRequest {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
@@ -119,8 +118,8 @@ mod tests {
 
     #[tokio::test]
     async fn materialize_should_work() -> Result<()> {
-        let config = AppConfig::load()?;
-        let service = MetadataService::new(config);
+        let config = AppConfig::for_test()?;
+        let service = MetadataService::try_new(config)?;
 
         let stream = tokio_stream::iter(vec![
             Ok(MaterializeRequest { id: 1 }),
@@ -0,0 +1,408 @@
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use prost_types::Timestamp;
+use sqlx::{FromRow, MySql, Transaction};
+use tonic::{Response, Status};
+use tracing::warn;
+use user_stat::pb::QueryRequest;
+
+use crate::{
+    pb::{
+        ActivateTemplateRequest, Content, CreateTemplateRequest, GetTemplateRequest,
+        ListTemplatesRequest, PreviewRequest, PreviewResponse, RollbackTemplateRequest, Template,
+    },
+    MetadataService, ServiceResult, TemplateStream,
+};
+
+use super::{
+    locale::{fallback_chain, normalize},
+    tpl::{MessageTemplate, TemplateSet, Tpl},
+};
+
+const TEMPLATE_COLUMNS: &str = "name, locale, version, subject, body, active, created_at";
+
+#[derive(Debug, FromRow)]
+struct TemplateRow {
+    name: String,
+    locale: String,
+    version: u32,
+    subject: String,
+    body: String,
+    active: bool,
+    created_at: DateTime<Utc>,
+}
+
+impl MetadataService {
+    pub async fn create_template(&self, req: CreateTemplateRequest) -> ServiceResult<Template> {
+        let tpl = MessageTemplate::new(req.name, req.locale, req.subject, req.body);
+        if tpl.name.is_empty() || tpl.locale.is_empty() {
+            return Err(Status::invalid_argument("name and locale are required"));
+        }
+        tpl.validate()
+            .map_err(|e| Status::invalid_argument(format!("invalid template: {e}")))?;
+
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+        let version: u64 = sqlx::query_scalar(
+            "SELECT CAST(COALESCE(MAX(version), 0) + 1 AS UNSIGNED) FROM templates WHERE name = ? AND locale = ? FOR UPDATE",
+        )
+        .bind(&tpl.name)
+        .bind(&tpl.locale)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_err)?;
+        let version = version as u32;
+
+        sqlx::query(
+            "INSERT INTO templates(name, locale, version, subject, body) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&tpl.name)
+        .bind(&tpl.locale)
+        .bind(version)
+        .bind(&tpl.subject)
+        .bind(&tpl.body)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+        if req.activate {
+            activate(&mut tx, &tpl.name, &tpl.locale, version).await?;
+        }
+        tx.commit().await.map_err(db_err)?;
+
+        let tpl = self
+            .find_template(&tpl.name, &tpl.locale, version)
+            .await?
+            .ok_or_else(|| Status::internal("template not found after create"))?;
+        Ok(Response::new(tpl))
+    }
+
+    pub async fn get_template(&self, req: GetTemplateRequest) -> ServiceResult<Template> {
+        let tpl = if req.version == 0 {
+            self.find_active_template(&req.name, &req.locale).await?
+        } else {
+            self.find_template(&req.name, &normalize(&req.locale), req.version)
+                .await?
+        };
+
+        match tpl {
+            Some(tpl) => Ok(Response::new(tpl)),
+            None => Err(Status::not_found(format!(
+                "template {} ({}) not found",
+                req.name, req.locale
+            ))),
+        }
+    }
+
+    pub async fn list_templates(&self, req: ListTemplatesRequest) -> ServiceResult<TemplateStream> {
+        let sql = format!(
+            "SELECT {TEMPLATE_COLUMNS} FROM templates WHERE (? = '' OR name = ?) AND (? = FALSE OR active = TRUE) ORDER BY name, locale, version"
+        );
+        let rows = sqlx::query_as::<_, TemplateRow>(&sql)
+            .bind(&req.name)
+            .bind(&req.name)
+            .bind(req.active_only)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        let templates = rows.into_iter().map(Template::from).map(Ok);
+        Ok(Response::new(Box::pin(futures::stream::iter(templates))))
+    }
+
+    pub async fn activate_template(&self, req: ActivateTemplateRequest) -> ServiceResult<Template> {
+        let locale = normalize(&req.locale);
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+        activate(&mut tx, &req.name, &locale, req.version).await?;
+        tx.commit().await.map_err(db_err)?;
+
+        self.get_template(GetTemplateRequest {
+            name: req.name,
+            locale,
+            version: req.version,
+        })
+        .await
+    }
+
+    pub async fn rollback_template(&self, req: RollbackTemplateRequest) -> ServiceResult<Template> {
+        let locale = normalize(&req.locale);
+        let mut tx = self.pool.begin().await.map_err(db_err)?;
+
+        let version: Option<u32> = sqlx::query_scalar(
+            "SELECT MAX(version) FROM templates WHERE name = ? AND locale = ? AND version < (SELECT version FROM templates WHERE name = ? AND locale = ? AND active = TRUE) FOR UPDATE",
+        )
+        .bind(&req.name)
+        .bind(&locale)
+        .bind(&req.name)
+        .bind(&locale)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_err)?;
+
+        let Some(version) = version else {
+            return Err(Status::failed_precondition(format!(
+                "template {} ({}) has no previous version to roll back to",
+                req.name, locale
+            )));
+        };
+
+        activate(&mut tx, &req.name, &locale, version).await?;
+        tx.commit().await.map_err(db_err)?;
+
+        self.get_template(GetTemplateRequest {
+            name: req.name,
+            locale,
+            version,
+        })
+        .await
+    }
+
+    pub async fn preview(&self, req: PreviewRequest) -> ServiceResult<PreviewResponse> {
+        let query = QueryRequest {
+            emails: vec![req.email.clone()],
+            ..Default::default()
+        };
+        let mut users = self.user_stats().query(query).await?.into_inner();
+        let Some(user) = users.next().await.transpose()? else {
+            return Err(Status::not_found(format!("user {} not found", req.email)));
+        };
+
+        let locale = if req.locale.is_empty() {
+            user.locale
+        } else {
+            req.locale
+        };
+        let locale = normalize(&locale);
+
+        let template = self
+            .resolve_template(&req.name, &locale, req.version)
+            .await?
+            .ok_or_else(|| Status::not_found(format!("template {} not found", req.name)))?;
+
+        // placeholders like those of materialize, the preview is about the template
+        let contents: Vec<_> = req
+            .content_ids
+            .iter()
+            .map(|id| Content::materialize(*id))
+            .collect();
+        let tpl = MessageTemplate::from(template.clone());
+        let rendered = Tpl::new(&tpl, &locale, &contents)
+            .with_name(&user.name)
+            .render()
+            .map_err(|e| Status::failed_precondition(format!("failed to render: {e}")))?;
+
+        Ok(Response::new(PreviewResponse {
+            template: Some(template),
+            locale,
+            subject: rendered.subject,
+            body: rendered.body,
+        }))
+    }
+
+    /// Resolve the template the same way crm does: stored templates along the locale
+    /// fallback chain, then the built-in ones.
+    async fn resolve_template(
+        &self,
+        name: &str,
+        locale: &str,
+        version: u32,
+    ) -> Result<Option<Template>, Status> {
+        for l in fallback_chain(locale) {
+            let tpl = if version == 0 {
+                self.find_active_template_exact(name, &l).await?
+            } else {
+                self.find_template(name, &l, version).await?
+            };
+            if tpl.is_some() {
+                return Ok(tpl);
+            }
+        }
+
+        if version != 0 {
+            return Ok(None);
+        }
+        Ok(TemplateSet::builtin().lookup(name, locale).map(Into::into))
+    }
+
+    async fn find_active_template(
+        &self,
+        name: &str,
+        locale: &str,
+    ) -> Result<Option<Template>, Status> {
+        for l in fallback_chain(locale) {
+            if let Some(tpl) = self.find_active_template_exact(name, &l).await? {
+                return Ok(Some(tpl));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn find_active_template_exact(
+        &self,
+        name: &str,
+        locale: &str,
+    ) -> Result<Option<Template>, Status> {
+        let sql = format!(
+            "SELECT {TEMPLATE_COLUMNS} FROM templates WHERE name = ? AND locale = ? AND active = TRUE"
+        );
+        let row = sqlx::query_as::<_, TemplateRow>(&sql)
+            .bind(name)
+            .bind(locale)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+        Ok(row.map(Into::into))
+    }
+
+    async fn find_template(
+        &self,
+        name: &str,
+        locale: &str,
+        version: u32,
+    ) -> Result<Option<Template>, Status> {
+        let sql = format!(
+            "SELECT {TEMPLATE_COLUMNS} FROM templates WHERE name = ? AND locale = ? AND version = ?"
+        );
+        let row = sqlx::query_as::<_, TemplateRow>(&sql)
+            .bind(name)
+            .bind(locale)
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+        Ok(row.map(Into::into))
+    }
+}
+
+async fn activate(
+    tx: &mut Transaction<'_, MySql>,
+    name: &str,
+    locale: &str,
+    version: u32,
+) -> Result<(), Status> {
+    let found: Option<u32> = sqlx::query_scalar(
+        "SELECT version FROM templates WHERE name = ? AND locale = ? AND version = ? FOR UPDATE",
+    )
+    .bind(name)
+    .bind(locale)
+    .bind(version)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(db_err)?;
+
+    if found.is_none() {
+        return Err(Status::not_found(format!(
+            "template {name} ({locale}) version {version} not found"
+        )));
+    }
+
+    sqlx::query("UPDATE templates SET active = (version = ?) WHERE name = ? AND locale = ?")
+        .bind(version)
+        .bind(name)
+        .bind(locale)
+        .execute(&mut **tx)
+        .await
+        .map_err(db_err)?;
+    Ok(())
+}
+
+fn db_err(e: sqlx::Error) -> Status {
+    warn!("database error: {:?}", e);
+    Status::internal("database error")
+}
+
+impl From<TemplateRow> for Template {
+    fn from(row: TemplateRow) -> Self {
+        Template {
+            name: row.name,
+            locale: row.locale,
+            version: row.version,
+            subject: row.subject,
+            body: row.body,
+            active: row.active,
+            created_at: Some(Timestamp {
+                seconds: row.created_at.timestamp(),
+                nanos: row.created_at.timestamp_subsec_nanos() as i32,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::AppConfig;
+
+    fn create_req(body: &str, activate: bool) -> CreateTemplateRequest {
+        CreateTemplateRequest {
+            name: "welcome".to_string(),
+            locale: "zh_cn".to_string(),
+            subject: "欢迎".to_string(),
+            body: body.to_string(),
+            activate,
+        }
+    }
+
+    #[tokio::test]
+    async fn template_versions_should_activate_and_rollback() -> Result<()> {
+        let (_tdb, svc) = MetadataService::new_for_test().await?;
+
+        let v1 = svc
+            .create_template(create_req("v1", true))
+            .await?
+            .into_inner();
+        assert_eq!(
+            (v1.version, v1.locale.as_str(), v1.active),
+            (1, "zh-CN", true)
+        );
+
+        let v2 = svc
+            .create_template(create_req("v2", false))
+            .await?
+            .into_inner();
+        assert_eq!((v2.version, v2.active), (2, false));
+
+        let req = GetTemplateRequest {
+            name: "welcome".to_string(),
+            locale: "zh-CN".to_string(),
+            version: 0,
+        };
+        let active = svc.get_template(req.clone()).await?.into_inner();
+        assert_eq!(active.body, "v1");
+
+        let active = svc
+            .activate_template(ActivateTemplateRequest {
+                name: "welcome".to_string(),
+                locale: "zh-CN".to_string(),
+                version: 2,
+            })
+            .await?
+            .into_inner();
+        assert_eq!(active.body, "v2");
+
+        let rollback = RollbackTemplateRequest {
+            name: "welcome".to_string(),
+            locale: "zh-CN".to_string(),
+        };
+        let active = svc.rollback_template(rollback.clone()).await?.into_inner();
+        assert_eq!(active.version, 1);
+        assert_eq!(svc.get_template(req).await?.into_inner().body, "v1");
+
+        let err = svc.rollback_template(rollback).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_template_should_reject_invalid_syntax() -> Result<()> {
+        let svc = MetadataService::try_new(AppConfig::for_test()?)?;
+        let err = svc
+            .create_template(create_req("{% if %}", true))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        Ok(())
+    }
+}
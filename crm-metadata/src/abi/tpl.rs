@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::pb::{Content, ContentType, Template};
+
+use super::locale::{fallback_chain, format_date, format_number, greeting, normalize};
+
+const WELCOME_EN: &str = r#"{{ greeting }}, welcome! Here are some contents we picked for you:
+{% for c in contents %}
+- {{ c.name }}: {{ c.description }}
+  {{ c.views }} views, published on {{ c.created_at }}
+  {{ c.url }}
+{% endfor %}"#;
+
+const WELCOME_ZH: &str = r#"{{ greeting }}，欢迎加入！我们为你挑选了以下内容：
+{% for c in contents %}
+- {{ c.name }}：{{ c.description }}
+  {{ c.views }} 次观看，发布于 {{ c.created_at }}
+  {{ c.url }}
+{% endfor %}"#;
+
+const RECALL_EN: &str = r#"{{ greeting }}, we miss you! Take a look at what is new:
+{% for c in contents %}
+- {{ c.name }}: {{ c.description }}
+  {{ c.views }} views, published on {{ c.created_at }}
+  {{ c.url }}
+{% endfor %}"#;
+
+const RECALL_ZH: &str = r#"{{ greeting }}，好久不见！看看最近的新内容：
+{% for c in contents %}
+- {{ c.name }}：{{ c.description }}
+  {{ c.views }} 次观看，发布于 {{ c.created_at }}
+  {{ c.url }}
+{% endfor %}"#;
+
+const REMIND_EN: &str = r#"{{ greeting }}, you still have unfinished contents:
+{% for c in contents %}
+- {{ c.name }}
+  {{ c.url }}
+{% endfor %}"#;
+
+const REMIND_ZH: &str = r#"{{ greeting }}，你还有未看完的内容：
+{% for c in contents %}
+- {{ c.name }}
+  {{ c.url }}
+{% endfor %}"#;
+
+/// A message template for a campaign in one locale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageTemplate {
+    pub name: String,
+    pub locale: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// The rendered subject and body of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    pub subject: String,
+    pub body: String,
+}
+
+/// Templates indexed by name and locale.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateSet {
+    templates: HashMap<(String, String), MessageTemplate>,
+}
+
+/// A template bound to the contents to render and the locale of the recipient.
+pub struct Tpl<'a> {
+    pub template: &'a MessageTemplate,
+    pub locale: &'a str,
+    pub contents: &'a [Content],
+    /// name of the recipient, empty if unknown
+    pub name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ContentView<'a> {
+    id: u32,
+    name: &'a str,
+    description: &'a str,
+    url: &'a str,
+    image: &'a str,
+    r#type: &'a str,
+    publishers: Vec<&'a str>,
+    created_at: String,
+    views: String,
+    likes: String,
+    dislikes: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TplContext<'a> {
+    locale: &'a str,
+    name: &'a str,
+    greeting: String,
+    contents: Vec<ContentView<'a>>,
+}
+
+impl MessageTemplate {
+    pub fn new(
+        name: impl Into<String>,
+        locale: impl Into<String>,
+        subject: impl Into<String>,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            locale: normalize(&locale.into()),
+            subject: subject.into(),
+            body: body.into(),
+        }
+    }
+
+    /// Check the subject and body are valid template syntax.
+    pub fn validate(&self) -> Result<()> {
+        let env = Environment::new();
+        env.template_from_str(&self.subject)?;
+        env.template_from_str(&self.body)?;
+        Ok(())
+    }
+}
+
+impl From<Template> for MessageTemplate {
+    fn from(tpl: Template) -> Self {
+        Self::new(tpl.name, tpl.locale, tpl.subject, tpl.body)
+    }
+}
+
+impl From<&MessageTemplate> for Template {
+    fn from(tpl: &MessageTemplate) -> Self {
+        Template {
+            name: tpl.name.clone(),
+            locale: tpl.locale.clone(),
+            version: 0,
+            subject: tpl.subject.clone(),
+            body: tpl.body.clone(),
+            active: true,
+            created_at: None,
+        }
+    }
+}
+
+impl TemplateSet {
+    /// Templates shipped with the service for the built-in campaigns.
+    pub fn builtin() -> Self {
+        let mut set = Self::default();
+        set.insert(MessageTemplate::new("welcome", "en", "Welcome", WELCOME_EN));
+        set.insert(MessageTemplate::new("welcome", "zh", "欢迎", WELCOME_ZH));
+        set.insert(MessageTemplate::new("recall", "en", "Recall", RECALL_EN));
+        set.insert(MessageTemplate::new("recall", "zh", "好久不见", RECALL_ZH));
+        set.insert(MessageTemplate::new("remind", "en", "Remind", REMIND_EN));
+        set.insert(MessageTemplate::new("remind", "zh", "继续观看", REMIND_ZH));
+        set
+    }
+
+    /// The built-in templates overridden by the given ones.
+    pub fn with_templates(templates: impl IntoIterator<Item = MessageTemplate>) -> Self {
+        let mut set = Self::builtin();
+        for tpl in templates {
+            set.insert(tpl);
+        }
+        set
+    }
+
+    pub fn insert(&mut self, tpl: MessageTemplate) {
+        self.templates
+            .insert((tpl.name.clone(), tpl.locale.clone()), tpl);
+    }
+
+    /// Find the template for the locale, walking its fallback chain (e.g. zh-CN -> zh -> en).
+    pub fn lookup(&self, name: &str, locale: &str) -> Option<&MessageTemplate> {
+        fallback_chain(locale)
+            .into_iter()
+            .find_map(|l| self.templates.get(&(name.to_string(), l)))
+    }
+}
+
+impl<'a> Tpl<'a> {
+    pub fn new(template: &'a MessageTemplate, locale: &'a str, contents: &'a [Content]) -> Self {
+        Self {
+            template,
+            locale,
+            contents,
+            name: "",
+        }
+    }
+
+    /// Address the message to the recipient by name.
+    pub fn with_name(mut self, name: &'a str) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn render(&self) -> Result<Rendered> {
+        let ctx = TplContext {
+            locale: self.locale,
+            name: self.name,
+            greeting: greeting(self.name, self.locale),
+            contents: self
+                .contents
+                .iter()
+                .map(|c| ContentView::new(c, self.locale))
+                .collect(),
+        };
+
+        let env = Environment::new();
+        Ok(Rendered {
+            subject: env.render_str(&self.template.subject, &ctx)?,
+            body: env.render_str(&self.template.body, &ctx)?,
+        })
+    }
+}
+
+impl<'a> ContentView<'a> {
+    fn new(content: &'a Content, locale: &str) -> Self {
+        let r#type = ContentType::try_from(content.r#type)
+            .unwrap_or_default()
+            .as_str_name();
+        Self {
+            id: content.id,
+            name: &content.name,
+            description: &content.description,
+            url: &content.url,
+            image: &content.image,
+            r#type,
+            publishers: content.publishers.iter().map(|p| p.name.as_str()).collect(),
+            created_at: content
+                .created_at
+                .as_ref()
+                .map(|ts| format_date(ts, locale))
+                .unwrap_or_default(),
+            views: format_number(content.views, locale),
+            likes: format_number(content.likes, locale),
+            dislikes: format_number(content.dislikes, locale),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_types::Timestamp;
+
+    use super::*;
+
+    #[test]
+    fn template_lookup_should_fallback() {
+        let set = TemplateSet::builtin();
+        assert_eq!(set.lookup("welcome", "zh-CN").unwrap().locale, "zh");
+        assert_eq!(set.lookup("welcome", "fr-FR").unwrap().locale, "en");
+        assert_eq!(set.lookup("welcome", "en-US").unwrap().locale, "en");
+        assert!(set.lookup("unknown", "en").is_none());
+    }
+
+    #[test]
+    fn template_set_should_prefer_given_templates() {
+        let set =
+            TemplateSet::with_templates([MessageTemplate::new("welcome", "zh_cn", "你好", "body")]);
+        assert_eq!(set.lookup("welcome", "zh-CN").unwrap().subject, "你好");
+        assert_eq!(set.lookup("welcome", "zh-TW").unwrap().subject, "欢迎");
+    }
+
+    #[test]
+    fn template_validate_should_reject_bad_syntax() {
+        let tpl = MessageTemplate::new("welcome", "en", "Hi", "{% for c in contents %}");
+        assert!(tpl.validate().is_err());
+        let tpl = MessageTemplate::new("welcome", "en", "Hi", "{{ locale }}");
+        assert!(tpl.validate().is_ok());
+    }
+
+    #[test]
+    fn tpl_render_should_format_by_locale() -> Result<()> {
+        let mut content = Content::materialize(1);
+        content.name = "Rust".to_string();
+        content.views = 1234567;
+        content.created_at = Some(Timestamp {
+            seconds: 1704164645, // 2024-01-02T03:04:05Z
+            nanos: 0,
+        });
+        let contents = vec![content];
+
+        let set = TemplateSet::builtin();
+        let tpl = set.lookup("welcome", "de-DE").unwrap();
+        let ret = Tpl::new(tpl, "de-DE", &contents).render()?;
+        assert_eq!(ret.subject, "Welcome");
+        assert!(ret
+            .body
+            .contains("1.234.567 views, published on 02.01.2024"));
+
+        let tpl = set.lookup("welcome", "zh-CN").unwrap();
+        let ret = Tpl::new(tpl, "zh-CN", &contents).render()?;
+        assert_eq!(ret.subject, "欢迎");
+        assert!(ret.body.contains("1,234,567 次观看，发布于 2024年1月2日"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn tpl_render_should_address_recipient() -> Result<()> {
+        let contents = vec![Content::materialize(1)];
+        let set = TemplateSet::builtin();
+        let tpl = set.lookup("remind", "en").unwrap();
+
+        let ret = Tpl::new(tpl, "en", &contents).with_name("Alice").render()?;
+        assert!(ret.body.starts_with("Hi Alice, you still have"));
+        let ret = Tpl::new(tpl, "en", &contents).render()?;
+        assert!(ret.body.starts_with("Hi, you still have"));
+
+        let tpl = set.lookup("remind", "zh-CN").unwrap();
+        let ret = Tpl::new(tpl, "zh-CN", &contents)
+            .with_name("张三")
+            .render()?;
+        assert!(ret.body.starts_with("你好，张三，你还有未看完的内容"));
+
+        Ok(())
+    }
+}
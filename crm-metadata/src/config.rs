@@ -1,30 +1,97 @@
-use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+use anyhow::Result;
+use crm_common::{
+    check, check_db_url, check_pk, check_service, check_service_keys, check_sk, check_tls,
+    ConfigLoader, ListenConfig, Reload, RolePolicy, TlsConfig, Validate,
+};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AuthConfig {
     pub pk: String,
+    /// key of the service's own key pair, its tokens for downstream calls are signed with
+    pub sk: String,
+    /// PEM encoded public keys of the services calling with tokens of their own, by name
+    #[serde(default)]
+    pub services: HashMap<String, String>,
+    /// roles allowed to call each method, by full gRPC path
+    #[serde(default)]
+    pub policy: RolePolicy,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
+    #[serde(default = "default_port")]
     pub port: u16,
+    /// host or Unix domain socket to listen on, loopback by default
+    #[serde(default)]
+    pub listen: ListenConfig,
+    /// serve TLS, and call other services over TLS, if set
+    pub tls: Option<TlsConfig>,
+    pub db_url: String,
+    pub user_stats: String,
 }
 
 impl AppConfig {
+    /// Load `metadata.yml`, or the file given by `--config` or `CRM_METADATA_CONFIG`, with `CRM_METADATA_*` overrides.
     pub fn load() -> Result<Self> {
-        let config = match File::open("metadata.yml") {
-            Ok(reader) => serde_yaml::from_reader(reader),
-            _ => bail!("Config file not found"),
-        };
+        Self::loader()?.load()
+    }
+
+    /// The loader of the config, honouring `--config` among the command line arguments.
+    pub fn loader() -> Result<ConfigLoader> {
+        ConfigLoader::new("metadata.yml", "CRM_METADATA").with_process_args()
+    }
+}
 
-        Ok(config?)
+#[cfg(feature = "test_utils")]
+impl AppConfig {
+    /// The config of the tests, trusting and signing with the test key.
+    pub fn for_test() -> Result<Self> {
+        Self::loader()?.load_for_test(&[
+            ("AUTH__PK", crm_common::TEST_PK),
+            ("AUTH__SK", crm_common::TEST_SK),
+        ])
     }
 }
+
+impl Reload for AppConfig {
+    fn needs_restart(&self, new: &Self) -> bool {
+        let (old, new_server) = (&self.server, &new.server);
+        (old.port, &old.listen, &old.tls, &old.db_url)
+            != (
+                new_server.port,
+                &new_server.listen,
+                &new_server.tls,
+                &new_server.db_url,
+            )
+            || self.auth != new.auth
+    }
+}
+
+impl Validate for AppConfig {
+    fn validate(&self) -> Result<()> {
+        check_db_url("server.db_url", &self.server.db_url)?;
+        check_service("server.user_stats", &self.server.user_stats)?;
+        check_tls("server.tls", self.server.tls.as_ref())?;
+        check_pk("auth.pk", &self.auth.pk)?;
+        check_service_keys("auth.services", &self.auth.services)?;
+        check_sk("auth.sk", &self.auth.sk)?;
+        let services = ["metadata.Metadata"];
+        check("auth.policy", self.auth.policy.check(&services))
+    }
+}
+
+fn default_port() -> u16 {
+    50002
+}
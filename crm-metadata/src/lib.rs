@@ -2,29 +2,48 @@ mod abi;
 mod config;
 pub mod pb;
 
-use std::pin::Pin;
+use std::{ops::Deref, pin::Pin, sync::Arc};
 
-pub use abi::Tpl;
+pub use abi::{
+    fallback_chain, format_date, format_number, greeting, MessageTemplate, Rendered, TemplateSet,
+    Tpl, DEFAULT_LOCALE,
+};
 pub use config::*;
+use crm_common::{authorize, AuthChannel, AuthServer, Reloadable, ServiceToken};
 use futures::Stream;
 use pb::{
     metadata_server::{Metadata, MetadataServer},
-    Content, MaterializeRequest,
+    ActivateTemplateRequest, Content, CreateTemplateRequest, GetTemplateRequest,
+    ListTemplatesRequest, MaterializeRequest, PreviewRequest, PreviewResponse,
+    RollbackTemplateRequest, Template,
 };
+use sqlx::MySqlPool;
 use tonic::{async_trait, Request, Response, Status, Streaming};
+use user_stat::pb::user_stats_client::UserStatsClient;
 
-#[allow(unused)]
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct MetadataService {
-    config: AppConfig,
+    inner: Arc<MetadataServiceInner>,
+}
+
+#[allow(unused)]
+pub struct MetadataServiceInner {
+    config: Reloadable<AppConfig>,
+    pool: MySqlPool,
+    /// tokens of the calls to user-stat
+    token: ServiceToken,
+    /// replaced when the address of user-stat changes
+    user_stats: Reloadable<UserStatsClient<AuthChannel>>,
 }
 
 type ServiceResult<T> = Result<Response<T>, Status>;
 type ResponseStream = Pin<Box<dyn Stream<Item = Result<Content, Status>> + Send>>;
+type TemplateStream = Pin<Box<dyn Stream<Item = Result<Template, Status>> + Send>>;
 
 #[async_trait]
 impl Metadata for MetadataService {
     type MaterializeStream = ResponseStream;
+    type ListTemplatesStream = TemplateStream;
 
     async fn materialize(
         &self,
@@ -33,14 +52,138 @@ impl Metadata for MetadataService {
         let query = request.into_inner();
         self.materialize(query).await
     }
+
+    async fn create_template(
+        &self,
+        request: Request<CreateTemplateRequest>,
+    ) -> ServiceResult<Template> {
+        let request = request.into_inner();
+        self.create_template(request).await
+    }
+
+    async fn get_template(&self, request: Request<GetTemplateRequest>) -> ServiceResult<Template> {
+        let request = request.into_inner();
+        self.get_template(request).await
+    }
+
+    async fn list_templates(
+        &self,
+        request: Request<ListTemplatesRequest>,
+    ) -> ServiceResult<Self::ListTemplatesStream> {
+        let request = request.into_inner();
+        self.list_templates(request).await
+    }
+
+    async fn activate_template(
+        &self,
+        request: Request<ActivateTemplateRequest>,
+    ) -> ServiceResult<Template> {
+        let request = request.into_inner();
+        self.activate_template(request).await
+    }
+
+    async fn rollback_template(
+        &self,
+        request: Request<RollbackTemplateRequest>,
+    ) -> ServiceResult<Template> {
+        let request = request.into_inner();
+        self.rollback_template(request).await
+    }
+
+    async fn preview(&self, request: Request<PreviewRequest>) -> ServiceResult<PreviewResponse> {
+        let request = request.into_inner();
+        self.preview(request).await
+    }
 }
 
 impl MetadataService {
-    pub fn new(config: AppConfig) -> Self {
-        Self { config }
+    pub fn try_new(config: AppConfig) -> anyhow::Result<Self> {
+        let pool = MySqlPool::connect_lazy(&config.server.db_url)?;
+        let token = ServiceToken::load(&config.auth.sk, "crm-metadata")?;
+        let user_stats = connect_user_stats(&token, &config)?;
+        let inner = MetadataServiceInner {
+            config: Reloadable::new(config),
+            pool,
+            token,
+            user_stats: Reloadable::new(user_stats),
+        };
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Swap in a reloaded config, reconnecting to user-stat if its address changed. Configs
+    /// changing the listen address, TLS, database or auth, set at start, are rejected.
+    pub fn reload(&self, config: AppConfig) -> anyhow::Result<()> {
+        let current = self.config.check_reload(&config)?;
+        if current.server.user_stats != config.server.user_stats {
+            self.user_stats
+                .set(connect_user_stats(&self.token, &config)?);
+        }
+        self.config.set(config);
+        Ok(())
+    }
+
+    pub(crate) fn user_stats(&self) -> UserStatsClient<AuthChannel> {
+        self.user_stats.get().as_ref().clone()
+    }
+
+    /// The server letting through callers with a token signed with the key of the issuer or of a
+    /// trusted service, and the roles the policy asks for.
+    pub fn into_server(self) -> anyhow::Result<AuthServer<MetadataServer<Self>>> {
+        let auth = self.config.get().auth.clone();
+        authorize(
+            MetadataServer::new(self),
+            &auth.pk,
+            &auth.services,
+            auth.policy,
+        )
     }
+}
+
+impl Deref for MetadataService {
+    type Target = MetadataServiceInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+fn connect_user_stats(
+    token: &ServiceToken,
+    config: &AppConfig,
+) -> anyhow::Result<UserStatsClient<AuthChannel>> {
+    let channel = token.connect_lazy(&config.server.user_stats, config.server.tls.as_ref())?;
+    Ok(UserStatsClient::new(channel))
+}
+
+#[cfg(test)]
+mod test_utils {
+    use std::{env, path::Path, sync::Arc};
+
+    use crm_common::{Reloadable, ServiceToken, TestMysql};
+
+    use crate::{connect_user_stats, AppConfig, MetadataService, MetadataServiceInner};
+
+    impl MetadataService {
+        pub async fn new_for_test() -> anyhow::Result<(TestMysql, Self)> {
+            let config = AppConfig::for_test()?;
+            let p = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("migrations");
+            let tdb = TestMysql::new("localhost", 3306, "root", "123456", p);
+            let pool = tdb.get_pool().await;
 
-    pub fn into_server(self) -> MetadataServer<Self> {
-        MetadataServer::new(self)
+            let token = ServiceToken::load(&config.auth.sk, "crm-metadata")?;
+            let user_stats = connect_user_stats(&token, &config)?;
+            let inner = MetadataServiceInner {
+                config: Reloadable::new(config),
+                pool,
+                token,
+                user_stats: Reloadable::new(user_stats),
+            };
+            let svc = Self {
+                inner: Arc::new(inner),
+            };
+            Ok((tdb, svc))
+        }
     }
 }
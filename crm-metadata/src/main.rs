@@ -1,6 +1,6 @@
 use anyhow::Result;
+use crm_common::{serve, server_builder, watch_config};
 use crm_metadata::{AppConfig, MetadataService};
-use tonic::transport::Server;
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
 
@@ -9,14 +9,18 @@ async fn main() -> Result<()> {
     let layer = Layer::new().with_filter(LevelFilter::INFO);
     tracing_subscriber::registry().with(layer).init();
 
-    let config = AppConfig::load().expect("failed to load config");
-    let addr = config.server.port;
-    let addr = format!("[::1]:{}", addr).parse().unwrap();
+    let loader = AppConfig::loader()?;
+    let config: AppConfig = loader.load()?;
+    let server = &config.server;
+    let addr = server.listen.resolve(server.port).await?;
     info!("Metadata service listening on {}", addr);
 
-    let srv = MetadataService::new(config).into_server();
+    let tls = config.server.tls.clone();
+    let svc = MetadataService::try_new(config)?;
+    let reloaded = svc.clone();
+    watch_config(loader, move |config| reloaded.reload(config));
+    let srv = svc.into_server()?;
 
-    Server::builder().add_service(srv).serve(addr).await?;
-
-    Ok(())
+    let router = server_builder(tls.as_ref())?.add_service(srv);
+    serve(router, &addr).await
 }
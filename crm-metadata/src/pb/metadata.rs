@@ -41,6 +41,117 @@ pub struct MaterializeRequest {
     #[prost(uint32, tag = "1")]
     pub id: u32,
 }
+/// a versioned message template of a campaign in one locale
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Template {
+    /// name of the template, e.g. welcome, recall, remind
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// locale of the template, e.g. en, zh-CN
+    #[prost(string, tag = "2")]
+    pub locale: ::prost::alloc::string::String,
+    /// version of the template, starts from 1 for every name and locale
+    #[prost(uint32, tag = "3")]
+    pub version: u32,
+    /// subject of the message, in template syntax
+    #[prost(string, tag = "4")]
+    pub subject: ::prost::alloc::string::String,
+    /// body of the message, in template syntax
+    #[prost(string, tag = "5")]
+    pub body: ::prost::alloc::string::String,
+    /// whether this version is the one used to build messages
+    #[prost(bool, tag = "6")]
+    pub active: bool,
+    #[prost(message, optional, tag = "7")]
+    pub created_at: ::core::option::Option<::prost_types::Timestamp>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateTemplateRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub locale: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub subject: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub body: ::prost::alloc::string::String,
+    /// activate the new version right away
+    #[prost(bool, tag = "5")]
+    pub activate: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetTemplateRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub locale: ::prost::alloc::string::String,
+    /// 0 for the active version
+    #[prost(uint32, tag = "3")]
+    pub version: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListTemplatesRequest {
+    /// empty for all templates
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(bool, tag = "2")]
+    pub active_only: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActivateTemplateRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub locale: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub version: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RollbackTemplateRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub locale: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PreviewRequest {
+    /// template name to render
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// 0 for the active version
+    #[prost(uint32, tag = "2")]
+    pub version: u32,
+    /// the user to render the template for
+    #[prost(string, tag = "3")]
+    pub email: ::prost::alloc::string::String,
+    /// rendered as placeholder contents of these ids, not the stored ones
+    #[prost(uint32, repeated, tag = "4")]
+    pub content_ids: ::prost::alloc::vec::Vec<u32>,
+    /// override the locale of the user
+    #[prost(string, tag = "5")]
+    pub locale: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PreviewResponse {
+    /// the template used to render, version 0 is the built-in one
+    #[prost(message, optional, tag = "1")]
+    pub template: ::core::option::Option<Template>,
+    /// the locale the message is rendered in
+    #[prost(string, tag = "2")]
+    pub locale: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub subject: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub body: ::prost::alloc::string::String,
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum ContentType {
@@ -180,6 +291,117 @@ pub mod metadata_client {
                 .insert(GrpcMethod::new("metadata.Metadata", "Materialize"));
             self.inner.streaming(req, path, codec).await
         }
+        /// create a new version of a template
+        pub async fn create_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/metadata.Metadata/CreateTemplate");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("metadata.Metadata", "CreateTemplate"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// get a version of a template, the active one by default
+        pub async fn get_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/metadata.Metadata/GetTemplate");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("metadata.Metadata", "GetTemplate"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn list_templates(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListTemplatesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::Template>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/metadata.Metadata/ListTemplates");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("metadata.Metadata", "ListTemplates"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        /// make a version of a template the active one
+        pub async fn activate_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ActivateTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/metadata.Metadata/ActivateTemplate");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("metadata.Metadata", "ActivateTemplate"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// re-activate the version before the active one
+        pub async fn rollback_template(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RollbackTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/metadata.Metadata/RollbackTemplate");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("metadata.Metadata", "RollbackTemplate"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// render a template for a user the way crm does, the template alone: contents are
+        /// placeholders, and what crm-send adds on delivery, e.g. unsubscribe links, is left out
+        pub async fn preview(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PreviewRequest>,
+        ) -> std::result::Result<tonic::Response<super::PreviewResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/metadata.Metadata/Preview");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("metadata.Metadata", "Preview"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -198,6 +420,41 @@ pub mod metadata_server {
             &self,
             request: tonic::Request<tonic::Streaming<super::MaterializeRequest>>,
         ) -> std::result::Result<tonic::Response<Self::MaterializeStream>, tonic::Status>;
+        /// create a new version of a template
+        async fn create_template(
+            &self,
+            request: tonic::Request<super::CreateTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status>;
+        /// get a version of a template, the active one by default
+        async fn get_template(
+            &self,
+            request: tonic::Request<super::GetTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status>;
+        /// Server streaming response type for the ListTemplates method.
+        type ListTemplatesStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::Template, tonic::Status>,
+            > + Send
+            + 'static;
+        async fn list_templates(
+            &self,
+            request: tonic::Request<super::ListTemplatesRequest>,
+        ) -> std::result::Result<tonic::Response<Self::ListTemplatesStream>, tonic::Status>;
+        /// make a version of a template the active one
+        async fn activate_template(
+            &self,
+            request: tonic::Request<super::ActivateTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status>;
+        /// re-activate the version before the active one
+        async fn rollback_template(
+            &self,
+            request: tonic::Request<super::RollbackTemplateRequest>,
+        ) -> std::result::Result<tonic::Response<super::Template>, tonic::Status>;
+        /// render a template for a user the way crm does, the template alone: contents are
+        /// placeholders, and what crm-send adds on delivery, e.g. unsubscribe links, is left out
+        async fn preview(
+            &self,
+            request: tonic::Request<super::PreviewRequest>,
+        ) -> std::result::Result<tonic::Response<super::PreviewResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct MetadataServer<T: Metadata> {
@@ -312,6 +569,249 @@ pub mod metadata_server {
                     };
                     Box::pin(fut)
                 }
+                "/metadata.Metadata/CreateTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct CreateTemplateSvc<T: Metadata>(pub Arc<T>);
+                    impl<T: Metadata> tonic::server::UnaryService<super::CreateTemplateRequest>
+                        for CreateTemplateSvc<T>
+                    {
+                        type Response = super::Template;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Metadata>::create_template(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CreateTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/metadata.Metadata/GetTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetTemplateSvc<T: Metadata>(pub Arc<T>);
+                    impl<T: Metadata> tonic::server::UnaryService<super::GetTemplateRequest> for GetTemplateSvc<T> {
+                        type Response = super::Template;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Metadata>::get_template(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/metadata.Metadata/ListTemplates" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListTemplatesSvc<T: Metadata>(pub Arc<T>);
+                    impl<T: Metadata>
+                        tonic::server::ServerStreamingService<super::ListTemplatesRequest>
+                        for ListTemplatesSvc<T>
+                    {
+                        type Response = super::Template;
+                        type ResponseStream = T::ListTemplatesStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListTemplatesRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Metadata>::list_templates(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListTemplatesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/metadata.Metadata/ActivateTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct ActivateTemplateSvc<T: Metadata>(pub Arc<T>);
+                    impl<T: Metadata> tonic::server::UnaryService<super::ActivateTemplateRequest>
+                        for ActivateTemplateSvc<T>
+                    {
+                        type Response = super::Template;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ActivateTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Metadata>::activate_template(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ActivateTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/metadata.Metadata/RollbackTemplate" => {
+                    #[allow(non_camel_case_types)]
+                    struct RollbackTemplateSvc<T: Metadata>(pub Arc<T>);
+                    impl<T: Metadata> tonic::server::UnaryService<super::RollbackTemplateRequest>
+                        for RollbackTemplateSvc<T>
+                    {
+                        type Response = super::Template;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RollbackTemplateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Metadata>::rollback_template(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RollbackTemplateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/metadata.Metadata/Preview" => {
+                    #[allow(non_camel_case_types)]
+                    struct PreviewSvc<T: Metadata>(pub Arc<T>);
+                    impl<T: Metadata> tonic::server::UnaryService<super::PreviewRequest> for PreviewSvc<T> {
+                        type Response = super::PreviewResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PreviewRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Metadata>::preview(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = PreviewSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
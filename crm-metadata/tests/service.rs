@@ -1,19 +1,31 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{fs, net::SocketAddr, time::Duration};
 
 use anyhow::Result;
+use crm_common::{
+    connect, endpoint, serve, server_builder, ListenAddr, TlsConfig, TokenInterceptor,
+};
 use crm_metadata::{
-    pb::{metadata_client::MetadataClient, MaterializeRequest},
+    pb::{metadata_client::MetadataClient, CreateTemplateRequest, MaterializeRequest},
     AppConfig, MetadataService,
 };
 use futures::StreamExt;
 use tokio::time::sleep;
-use tonic::{transport::Server, Request};
+use tonic::{
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint},
+    Code, Request,
+};
+
+const PORT_BASE: u16 = 61000;
 
 #[tokio::test]
 async fn test_metadata_should_work() -> Result<()> {
-    let addr = start_server().await?;
+    let addr = start_server(PORT_BASE, None).await?;
 
-    let mut client = MetadataClient::connect(format!("http://{addr}")).await?;
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let auth = TokenInterceptor::for_test("crm", &["service"]);
+    let mut client = MetadataClient::with_interceptor(channel, auth);
 
     let stream = tokio_stream::iter(vec![
         MaterializeRequest { id: 1 },
@@ -30,17 +42,119 @@ async fn test_metadata_should_work() -> Result<()> {
     Ok(())
 }
 
-async fn start_server() -> Result<SocketAddr> {
-    let config = AppConfig::load()?;
-    let addr = format!("[::1]:{}", config.server.port).parse()?;
+#[tokio::test]
+async fn call_without_token_should_be_unauthenticated() -> Result<()> {
+    let addr = start_server(PORT_BASE + 1, None).await?;
+
+    let mut client = MetadataClient::connect(format!("http://{addr}")).await?;
+    let stream = tokio_stream::iter(vec![MaterializeRequest { id: 1 }]);
+    let err = client.materialize(Request::new(stream)).await.unwrap_err();
+    assert_eq!(err.code(), Code::Unauthenticated);
+    Ok(())
+}
+
+#[tokio::test]
+async fn call_without_role_should_be_denied() -> Result<()> {
+    let addr = start_server(PORT_BASE + 2, None).await?;
+
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let auth = TokenInterceptor::for_test("bob", &["marketer"]);
+    let mut client = MetadataClient::with_interceptor(channel, auth);
+    let err = client
+        .create_template(CreateTemplateRequest::default())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::PermissionDenied);
+
+    // methods are denied to callers without any of the roles of the policy
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let auth = TokenInterceptor::for_test("test", &[]);
+    let mut client = MetadataClient::with_interceptor(channel, auth);
+    let stream = tokio_stream::iter(vec![MaterializeRequest { id: 1 }]);
+    let err = client.materialize(Request::new(stream)).await.unwrap_err();
+    assert_eq!(err.code(), Code::PermissionDenied);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mutual_tls_should_require_client_cert() -> Result<()> {
+    let server_tls = TlsConfig::for_test("server");
+    let addr = start_server(PORT_BASE + 3, Some(server_tls.clone())).await?;
+    let url = format!("https://[::1]:{}", addr.port());
+    let auth = TokenInterceptor::for_test("crm", &["service"]);
+
+    let client_tls = TlsConfig::for_test("client");
+    let channel = endpoint(&url, Some(&client_tls))?.connect().await?;
+    let mut client = MetadataClient::with_interceptor(channel, auth.clone());
+    let stream = tokio_stream::iter(vec![MaterializeRequest { id: 1 }]);
+    let ret = client.materialize(Request::new(stream)).await?.into_inner();
+    assert_eq!(ret.collect::<Vec<_>>().await.len(), 1);
+
+    // trusting the server, without a certificate of its own
+    let ca = fs::read_to_string(server_tls.ca.as_deref().unwrap())?;
+    let tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(ca))
+        .domain_name("localhost");
+    let ret: Result<()> = async {
+        let channel = Endpoint::from_shared(url)?
+            .tls_config(tls)?
+            .connect()
+            .await?;
+        let mut client = MetadataClient::with_interceptor(channel, auth);
+        let stream = tokio_stream::iter(vec![MaterializeRequest { id: 1 }]);
+        client.materialize(Request::new(stream)).await?;
+        Ok(())
+    }
+    .await;
+    assert!(ret.is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn service_should_be_served_over_unix_socket() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("{}-metadata.sock", PORT_BASE + 4));
+    let svc = MetadataService::try_new(AppConfig::for_test()?)?.into_server()?;
+    let router = server_builder(None)?.add_service(svc);
+    let addr = ListenAddr::Unix(path.clone());
+    tokio::spawn(async move { serve(router, &addr).await.unwrap() });
+    sleep(Duration::from_millis(50)).await;
+
+    let channel = connect(&format!("unix:{}", path.display()), None).await?;
+    let auth = TokenInterceptor::for_test("crm", &["service"]);
+    let mut client = MetadataClient::with_interceptor(channel, auth);
+    let stream = tokio_stream::iter(vec![MaterializeRequest { id: 1 }]);
+    let ret = client.materialize(Request::new(stream)).await?.into_inner();
+    assert_eq!(ret.collect::<Vec<_>>().await.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_should_keep_settings_read_at_start() -> Result<()> {
+    let svc = MetadataService::try_new(AppConfig::for_test()?)?;
+
+    let mut config = AppConfig::for_test()?;
+    config.server.user_stats = "http://[::1]:50011".to_string();
+    svc.reload(config)?;
+
+    let mut config = AppConfig::for_test()?;
+    config.server.port += 1;
+    assert!(svc.reload(config).is_err());
+    Ok(())
+}
+
+async fn start_server(port: u16, tls: Option<TlsConfig>) -> Result<SocketAddr> {
+    let config = AppConfig::for_test()?;
+    let addr = format!("[::1]:{}", port).parse()?;
 
-    let svc = MetadataService::new(config).into_server();
+    let svc = MetadataService::try_new(config)?.into_server()?;
+    let mut builder = server_builder(tls.as_ref())?;
     tokio::spawn(async move {
-        Server::builder()
-            .add_service(svc)
-            .serve(addr)
-            .await
-            .unwrap();
+        builder.add_service(svc).serve(addr).await.unwrap();
     });
     sleep(Duration::from_micros(1)).await;
 
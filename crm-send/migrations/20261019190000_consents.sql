@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE consents(
+    recipient varchar(128) NOT NULL COMMENT 'email, phone number or device id',
+    channel int NOT NULL COMMENT 'Channel',
+    category varchar(64) NOT NULL DEFAULT '' COMMENT 'message category, empty for all',
+    subscribed tinyint(1) NOT NULL COMMENT 'whether the recipient accepts the messages',
+    updated_at datetime(3) NOT NULL COMMENT 'last changed time',
+    PRIMARY KEY (recipient, channel, category)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Opt-ins and opt-outs of recipients';
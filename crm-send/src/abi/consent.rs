@@ -0,0 +1,328 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{FromRow, MySql, QueryBuilder};
+use tonic::{Response, Status};
+
+use crate::{
+    config::ConsentConfig,
+    pb::{
+        unsubscribe_request::By, Channel, Consent, ConsentTarget, EmailMessage, SubscribeRequest,
+        UnsubscribeRequest,
+    },
+    NotificationService, ServiceResult,
+};
+
+use super::to_ts;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, FromRow)]
+struct ConsentRow {
+    recipient: String,
+    category: String,
+    subscribed: bool,
+    updated_at: DateTime<Utc>,
+}
+
+impl NotificationService {
+    pub async fn subscribe(&self, req: SubscribeRequest) -> ServiceResult<Consent> {
+        let target = req
+            .target
+            .ok_or_else(|| Status::invalid_argument("consent target is required"))?;
+        self.set_consent(target, true).await
+    }
+
+    pub async fn unsubscribe(&self, req: UnsubscribeRequest) -> ServiceResult<Consent> {
+        let target = match req.by {
+            Some(By::Target(target)) => target,
+            Some(By::Token(token)) => verify_token(&self.config().consent.secret, &token)
+                .map_err(|e| Status::invalid_argument(format!("invalid token: {}", e)))?,
+            None => return Err(Status::invalid_argument("target or token is required")),
+        };
+        self.set_consent(target, false).await
+    }
+
+    pub async fn get_consent(&self, target: ConsentTarget) -> ServiceResult<Consent> {
+        check_target(&target).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let rows = self
+            .consent_rows(
+                target.channel(),
+                std::slice::from_ref(&target.recipient),
+                &target.category,
+            )
+            .await?;
+
+        let consent = match resolve(&rows).get(target.recipient.as_str()) {
+            Some(row) => Consent {
+                target: Some(target),
+                subscribed: row.subscribed,
+                updated_at: Some(to_ts(row.updated_at)),
+            },
+            None => Consent {
+                target: Some(target),
+                subscribed: true,
+                updated_at: None,
+            },
+        };
+        Ok(Response::new(consent))
+    }
+
+    /// Recipients on the channel who opted out of the category, or of all categories.
+    pub(crate) async fn opted_out(
+        &self,
+        channel: Channel,
+        recipients: &[String],
+        category: &str,
+    ) -> Result<HashSet<String>, Status> {
+        let rows = self.consent_rows(channel, recipients, category).await?;
+        let opted_out = resolve(&rows)
+            .into_iter()
+            .filter(|(_, row)| !row.subscribed)
+            .map(|(recipient, _)| recipient.to_string())
+            .collect();
+        Ok(opted_out)
+    }
+
+    async fn set_consent(&self, target: ConsentTarget, subscribed: bool) -> ServiceResult<Consent> {
+        check_target(&target).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO consents(recipient, channel, category, subscribed, updated_at) VALUES (?, ?, ?, ?, ?) \
+             ON DUPLICATE KEY UPDATE subscribed = VALUES(subscribed), updated_at = VALUES(updated_at)",
+        )
+        .bind(&target.recipient)
+        .bind(target.channel)
+        .bind(&target.category)
+        .bind(subscribed)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        Ok(Response::new(Consent {
+            target: Some(target),
+            subscribed,
+            updated_at: Some(to_ts(now)),
+        }))
+    }
+
+    /// Consents of the recipients for the category and for all categories.
+    async fn consent_rows(
+        &self,
+        channel: Channel,
+        recipients: &[String],
+        category: &str,
+    ) -> Result<Vec<ConsentRow>, Status> {
+        if recipients.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+            "SELECT recipient, category, subscribed, updated_at FROM consents WHERE channel = ",
+        );
+        builder
+            .push_bind(channel as i32)
+            .push(" AND category IN ('', ")
+            .push_bind(category)
+            .push(") AND recipient IN (");
+        let mut separated = builder.separated(", ");
+        for recipient in recipients {
+            separated.push_bind(recipient);
+        }
+        separated.push_unseparated(")");
+
+        builder
+            .build_query_as::<ConsentRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)
+    }
+}
+
+impl EmailMessage {
+    /// A copy of the email per recipient, each ending with a link to unsubscribe from the
+    /// category.
+    pub(crate) fn with_unsubscribe_links(
+        self,
+        config: &ConsentConfig,
+        category: &str,
+    ) -> Vec<EmailMessage> {
+        self.recipients
+            .iter()
+            .map(|recipient| {
+                let target = ConsentTarget {
+                    recipient: recipient.clone(),
+                    channel: Channel::Email as i32,
+                    category: category.to_string(),
+                };
+                let link = unsubscribe_link(config, &target);
+                EmailMessage {
+                    recipients: vec![recipient.clone()],
+                    body: format!("{}\n\nUnsubscribe: {}", self.body, link),
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+fn unsubscribe_link(config: &ConsentConfig, target: &ConsentTarget) -> String {
+    let sep = match config.unsubscribe_url.contains('?') {
+        true => '&',
+        false => '?',
+    };
+    let token = sign_token(&config.secret, target);
+    format!("{}{}token={}", config.unsubscribe_url, sep, token)
+}
+
+/// Sign the target into a token: base64url of the payload and of its HMAC-SHA256.
+pub(crate) fn sign_token(secret: &str, target: &ConsentTarget) -> String {
+    let payload = format!(
+        "{}\n{}\n{}",
+        target.channel, target.category, target.recipient
+    );
+    let mut mac = new_mac(secret);
+    mac.update(payload.as_bytes());
+    let sig = mac.finalize().into_bytes();
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(sig)
+    )
+}
+
+/// The target a token was signed for, if the signature holds.
+pub(crate) fn verify_token(secret: &str, token: &str) -> Result<ConsentTarget> {
+    let (payload, sig) = token
+        .split_once('.')
+        .ok_or_else(|| anyhow!("malformed token"))?;
+    let payload = URL_SAFE_NO_PAD.decode(payload)?;
+    let sig = URL_SAFE_NO_PAD.decode(sig)?;
+
+    let mut mac = new_mac(secret);
+    mac.update(&payload);
+    mac.verify_slice(&sig)
+        .map_err(|_| anyhow!("signature mismatch"))?;
+
+    let payload = String::from_utf8(payload)?;
+    let mut parts = payload.splitn(3, '\n');
+    let (Some(channel), Some(category), Some(recipient)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("malformed payload");
+    };
+    Ok(ConsentTarget {
+        recipient: recipient.to_string(),
+        channel: channel.parse()?,
+        category: category.to_string(),
+    })
+}
+
+fn new_mac(secret: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes a key of any size")
+}
+
+fn check_target(target: &ConsentTarget) -> Result<()> {
+    if target.recipient.is_empty() {
+        bail!("recipient is required");
+    }
+    if target.channel() == Channel::Unspecified {
+        bail!("channel is required");
+    }
+    Ok(())
+}
+
+/// Effective consent of each recipient: the one for the category over the one for all.
+fn resolve(rows: &[ConsentRow]) -> HashMap<&str, &ConsentRow> {
+    let mut ret: HashMap<&str, &ConsentRow> = HashMap::new();
+    for row in rows {
+        let entry = ret.entry(row.recipient.as_str()).or_insert(row);
+        if entry.category.is_empty() && !row.category.is_empty() {
+            *entry = row;
+        }
+    }
+    ret
+}
+
+fn db_err(e: sqlx::Error) -> Status {
+    Status::internal(format!("database error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> ConsentTarget {
+        ConsentTarget {
+            recipient: "alice@acme.org".to_string(),
+            channel: Channel::Email as i32,
+            category: "recall".to_string(),
+        }
+    }
+
+    fn row(recipient: &str, category: &str, subscribed: bool) -> ConsentRow {
+        ConsentRow {
+            recipient: recipient.to_string(),
+            category: category.to_string(),
+            subscribed,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn token_should_round_trip_and_reject_tampering() -> Result<()> {
+        let token = sign_token("secret", &target());
+        assert_eq!(verify_token("secret", &token)?, target());
+
+        assert!(verify_token("other", &token).is_err());
+        let (_, sig) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", URL_SAFE_NO_PAD.encode("1\n\nbob@acme.org"), sig);
+        assert!(verify_token("secret", &forged).is_err());
+        assert!(verify_token("secret", "garbage").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_should_prefer_category_consent() {
+        let rows = vec![
+            row("a", "recall", true),
+            row("a", "", false),
+            row("b", "", false),
+            row("c", "recall", false),
+        ];
+        let resolved = resolve(&rows);
+        assert!(resolved["a"].subscribed);
+        assert!(!resolved["b"].subscribed);
+        assert!(!resolved["c"].subscribed);
+    }
+
+    #[test]
+    fn email_should_get_a_link_per_recipient() -> Result<()> {
+        let config = ConsentConfig {
+            secret: "secret".to_string(),
+            unsubscribe_url: "https://acme.org/unsubscribe".to_string(),
+        };
+        let email = EmailMessage {
+            recipients: vec!["alice@acme.org".to_string(), "bob@acme.org".to_string()],
+            body: "Hi".to_string(),
+            ..Default::default()
+        };
+
+        let emails = email.with_unsubscribe_links(&config, "recall");
+        assert_eq!(emails.len(), 2);
+        let token = emails[0]
+            .body
+            .split_once("https://acme.org/unsubscribe?token=")
+            .map(|(_, v)| v)
+            .unwrap();
+        assert_eq!(verify_token("secret", token)?, target());
+        assert!(emails[1].body.starts_with("Hi\n\nUnsubscribe: "));
+        Ok(())
+    }
+}
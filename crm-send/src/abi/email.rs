@@ -6,7 +6,7 @@ use crate::{
     NotificationService,
 };
 
-use super::{to_ts, Sender};
+use super::Sender;
 
 impl Sender for EmailMessage {
     async fn send(self, svc: NotificationService) -> Result<SendResponse, Status> {
@@ -20,10 +20,7 @@ impl Sender for EmailMessage {
 
         info!("email sender: {:?}, recipients: {}", sender, recipients);
 
-        Ok(SendResponse {
-            message_id,
-            timestamp: Some(to_ts()),
-        })
+        Ok(SendResponse::sent(message_id))
     }
 }
 
@@ -36,7 +33,10 @@ impl From<EmailMessage> for Msg {
 impl From<EmailMessage> for SendRequest {
     fn from(email: EmailMessage) -> Self {
         let msg: Msg = email.into();
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
 
@@ -3,7 +3,7 @@ use tracing::warn;
 
 use crate::pb::{send_request::Msg, InAppMessage, SendRequest, SendResponse};
 
-use super::{to_ts, Sender};
+use super::Sender;
 
 impl Sender for InAppMessage {
     async fn send(
@@ -16,10 +16,7 @@ impl Sender for InAppMessage {
             Status::internal("Failed to send in-app message")
         })?;
 
-        Ok(SendResponse {
-            message_id,
-            timestamp: Some(to_ts()),
-        })
+        Ok(SendResponse::sent(message_id))
     }
 }
 
@@ -45,6 +42,9 @@ impl From<InAppMessage> for Msg {
 impl From<InAppMessage> for SendRequest {
     fn from(in_app: InAppMessage) -> Self {
         let msg: Msg = in_app.into();
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
@@ -1,13 +1,17 @@
+mod consent;
 mod email;
 mod in_app;
 mod sms;
 
-use std::{ops::Deref, sync::Arc, time::Duration};
+use std::{collections::HashSet, ops::Deref, sync::Arc, time::Duration};
 
-use chrono::Utc;
-use crm_metadata::{pb::Content, Tpl};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use crm_common::{authorize, AuthServer, Reloadable};
+use crm_metadata::Rendered;
 use futures::{Stream, StreamExt};
 use prost_types::Timestamp;
+use sqlx::MySqlPool;
 use tokio::{sync::mpsc, time::sleep};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Response, Status};
@@ -16,8 +20,8 @@ use uuid::Uuid;
 
 use crate::{
     pb::{
-        notification_server::NotificationServer, send_request::Msg, EmailMessage, SendRequest,
-        SendResponse,
+        notification_server::NotificationServer, send_request::Msg, Channel, EmailMessage,
+        InAppMessage, SendRequest, SendResponse, SendStatus, SmsMessage,
     },
     AppConfig, NotificationService, NotificationServiceInner, ResponseStream, ServiceResult,
 };
@@ -29,16 +33,44 @@ pub trait Sender {
 }
 
 impl NotificationService {
-    pub fn new(config: AppConfig) -> Self {
+    pub async fn try_new(config: AppConfig) -> Result<Self> {
+        let pool = MySqlPool::connect(&config.server.db_url).await?;
         let sender = dummy_sender();
-        let inner = NotificationServiceInner { config, sender };
+        let inner = NotificationServiceInner {
+            config: Reloadable::new(config),
+            pool,
+            sender,
+        };
+        Ok(Self::from_inner(inner))
+    }
+
+    /// Swap in a reloaded config; messages already being delivered keep the one they started
+    /// with. Configs changing the listen address, TLS, database or auth, set at start, are
+    /// rejected.
+    pub fn reload(&self, config: AppConfig) -> Result<()> {
+        self.config.reload(config)
+    }
+
+    pub(crate) fn config(&self) -> Arc<AppConfig> {
+        self.config.get()
+    }
+
+    pub(crate) fn from_inner(inner: NotificationServiceInner) -> Self {
         Self {
             inner: Arc::new(inner),
         }
     }
 
-    pub fn into_server(self) -> NotificationServer<Self> {
-        NotificationServer::new(self)
+    /// The server letting through callers with a token signed with the key of the issuer or of a
+    /// trusted service, and the roles the policy asks for.
+    pub fn into_server(self) -> Result<AuthServer<NotificationServer<Self>>> {
+        let auth = self.config().auth.clone();
+        authorize(
+            NotificationServer::new(self),
+            &auth.pk,
+            &auth.services,
+            auth.policy,
+        )
     }
 
     pub async fn send(
@@ -50,24 +82,54 @@ impl NotificationService {
 
         tokio::spawn(async move {
             while let Some(Ok(req)) = stream.next().await {
-                let notification_svc_clone = notification_svc.clone();
+                let message_id = req.msg.as_ref().map(|v| v.message_id().to_string());
                 let res = match req.msg {
-                    Some(Msg::Email(email)) => email.send(notification_svc_clone).await,
-                    Some(Msg::Sms(sms)) => sms.send(notification_svc_clone).await,
-                    Some(Msg::InApp(in_app)) => in_app.send(notification_svc_clone).await,
+                    Some(msg) => notification_svc.deliver(msg, &req.category).await,
                     None => {
                         warn!("invalid request");
                         Err(Status::invalid_argument("Invalid request"))
                     }
                 };
+                let res = res.unwrap_or_else(|e| {
+                    SendResponse::failed(message_id.unwrap_or_default(), e.message())
+                });
 
-                tx.send(res).await.unwrap();
+                tx.send(Ok(res)).await.unwrap();
             }
         });
 
         let stream = ReceiverStream::new(rx);
         Ok(Response::new(Box::pin(stream)))
     }
+
+    /// Deliver the message to the recipients who have not opted out of its category. Every
+    /// email recipient gets a copy of their own with a link to unsubscribe.
+    async fn deliver(&self, msg: Msg, category: &str) -> Result<SendResponse, Status> {
+        let recipients = msg.recipients();
+        if recipients.is_empty() {
+            return Err(Status::invalid_argument("recipients are required"));
+        }
+        let opted_out = self.opted_out(msg.channel(), &recipients, category).await?;
+        let message_id = msg.message_id().to_string();
+        let Some(msg) = msg.without(&opted_out) else {
+            return Ok(SendResponse::rejected(
+                message_id,
+                "all recipients opted out",
+            ));
+        };
+
+        match msg {
+            Msg::Email(email) => {
+                let config = self.config();
+                for email in email.with_unsubscribe_links(&config.consent, category) {
+                    email.send(self.clone()).await?;
+                }
+                Ok(SendResponse::sent(message_id))
+            }
+            Msg::Sms(sms) => sms.send(self.clone()).await,
+            Msg::InApp(in_app) => in_app.send(self.clone()).await,
+        }
+    }
 }
 
 impl Deref for NotificationService {
@@ -79,26 +141,119 @@ impl Deref for NotificationService {
 }
 
 impl SendRequest {
-    pub fn new(
-        subject: String,
-        sender: String,
-        recipients: &[String],
-        contents: &[Content],
-    ) -> Self {
-        let tpl = Tpl(contents);
+    pub fn new(sender: String, recipients: &[String], rendered: Rendered) -> Self {
         let msg = Msg::Email(EmailMessage {
             message_id: Uuid::new_v4().to_string(),
-            subject,
+            subject: rendered.subject,
             sender,
             recipients: recipients.to_vec(),
-            body: tpl.to_body(),
+            body: rendered.body,
         });
 
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            category: String::new(),
+        }
+    }
+
+    /// Tag the request with the category recipients consent to, e.g. the kind of campaign.
+    pub fn with_category(mut self, category: impl Into<String>) -> Self {
+        self.category = category.into();
+        self
+    }
+
+    /// An sms carries the body only.
+    pub fn sms(sender: String, recipients: &[String], rendered: Rendered) -> Self {
+        SmsMessage {
+            message_id: Uuid::new_v4().to_string(),
+            sender,
+            recipients: recipients.to_vec(),
+            body: rendered.body,
+        }
+        .into()
+    }
+
+    pub fn in_app(device_id: String, rendered: Rendered) -> Self {
+        InAppMessage {
+            message_id: Uuid::new_v4().to_string(),
+            device_id,
+            title: rendered.subject,
+            body: rendered.body,
+        }
+        .into()
+    }
+}
+
+impl SendResponse {
+    pub fn sent(message_id: String) -> Self {
+        SendResponse {
+            message_id,
+            timestamp: Some(to_ts(Utc::now())),
+            status: SendStatus::Sent as _,
+            error: String::new(),
+        }
+    }
+
+    pub fn failed(message_id: String, error: impl Into<String>) -> Self {
+        SendResponse {
+            message_id,
+            timestamp: Some(to_ts(Utc::now())),
+            status: SendStatus::Failed as _,
+            error: error.into(),
+        }
+    }
+
+    pub fn rejected(message_id: String, error: impl Into<String>) -> Self {
+        SendResponse {
+            message_id,
+            timestamp: Some(to_ts(Utc::now())),
+            status: SendStatus::Rejected as _,
+            error: error.into(),
+        }
     }
 }
 
-fn dummy_sender() -> mpsc::Sender<Msg> {
+impl Msg {
+    pub fn message_id(&self) -> &str {
+        match self {
+            Msg::Email(email) => &email.message_id,
+            Msg::Sms(sms) => &sms.message_id,
+            Msg::InApp(in_app) => &in_app.message_id,
+        }
+    }
+
+    pub fn channel(&self) -> Channel {
+        match self {
+            Msg::Email(_) => Channel::Email,
+            Msg::Sms(_) => Channel::Sms,
+            Msg::InApp(_) => Channel::InApp,
+        }
+    }
+
+    /// Email addresses, phone numbers or the device the message goes to.
+    pub fn recipients(&self) -> Vec<String> {
+        match self {
+            Msg::Email(email) => email.recipients.clone(),
+            Msg::Sms(sms) => sms.recipients.clone(),
+            Msg::InApp(in_app) => vec![in_app.device_id.clone()],
+        }
+    }
+
+    /// The message without the excluded recipients, none if nobody is left.
+    fn without(mut self, excluded: &HashSet<String>) -> Option<Self> {
+        let recipients = match &mut self {
+            Msg::Email(email) => &mut email.recipients,
+            Msg::Sms(sms) => &mut sms.recipients,
+            Msg::InApp(in_app) => {
+                return (!excluded.contains(&in_app.device_id)).then_some(self);
+            }
+        };
+        recipients.retain(|v| !excluded.contains(v));
+        (!recipients.is_empty()).then_some(self)
+    }
+}
+
+pub(crate) fn dummy_sender() -> mpsc::Sender<Msg> {
     let (tx, mut rx) = mpsc::channel(CHANNEL_SIZE * 100);
     tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
@@ -109,11 +264,10 @@ fn dummy_sender() -> mpsc::Sender<Msg> {
     tx
 }
 
-fn to_ts() -> Timestamp {
-    let now = Utc::now();
+fn to_ts(dt: DateTime<Utc>) -> Timestamp {
     Timestamp {
-        seconds: now.timestamp(),
-        nanos: now.timestamp_subsec_nanos() as i32,
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
     }
 }
 
@@ -123,14 +277,16 @@ mod tests {
     use anyhow::Result;
 
     use crate::{
-        pb::{EmailMessage, InAppMessage, SmsMessage},
-        AppConfig, NotificationService,
+        pb::{
+            unsubscribe_request::By, ConsentTarget, EmailMessage, InAppMessage, SmsMessage,
+            UnsubscribeRequest,
+        },
+        NotificationService,
     };
 
     #[tokio::test]
     async fn send_should_work() -> Result<()> {
-        let config = AppConfig::load()?;
-        let service = NotificationService::new(config);
+        let (_tdb, service) = NotificationService::new_for_test().await?;
         let stream = tokio_stream::iter(vec![
             Ok(EmailMessage::fake().into()),
             Ok(SmsMessage::fake().into()),
@@ -143,4 +299,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn send_invalid_request_should_report_failure() -> Result<()> {
+        let (_tdb, service) = NotificationService::new_for_test().await?;
+        let stream = tokio_stream::iter(vec![
+            Ok(SendRequest::default()),
+            Ok(EmailMessage::fake().into()),
+        ]);
+
+        let response = service.send(stream).await?;
+        let ret = response.into_inner().collect::<Vec<_>>().await;
+        assert_eq!(ret.len(), 2);
+        let statuses: Vec<_> = ret.into_iter().map(|v| v.unwrap().status()).collect();
+        assert_eq!(statuses, vec![SendStatus::Failed, SendStatus::Sent]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_should_skip_opted_out_recipients() -> Result<()> {
+        let (_tdb, service) = NotificationService::new_for_test().await?;
+        let mut email = EmailMessage::fake();
+        email.recipients.push("bob@acme.org".to_string());
+        let opted_out = email.recipients[0].clone();
+        service
+            .unsubscribe(UnsubscribeRequest {
+                by: Some(By::Target(ConsentTarget {
+                    recipient: opted_out.clone(),
+                    channel: Channel::Email as i32,
+                    category: "recall".to_string(),
+                })),
+            })
+            .await?;
+
+        let mut alone = EmailMessage::fake();
+        alone.recipients = vec![opted_out.clone()];
+        let stream = tokio_stream::iter(vec![
+            Ok(SendRequest::from(email).with_category("recall")),
+            Ok(SendRequest::from(alone.clone()).with_category("recall")),
+            Ok(SendRequest::from(alone).with_category("welcome")),
+        ]);
+
+        let response = service.send(stream).await?;
+        let ret = response.into_inner().collect::<Vec<_>>().await;
+        let statuses: Vec<_> = ret.into_iter().map(|v| v.unwrap().status()).collect();
+        assert_eq!(
+            statuses,
+            vec![SendStatus::Sent, SendStatus::Rejected, SendStatus::Sent]
+        );
+
+        let consent = service
+            .get_consent(ConsentTarget {
+                recipient: opted_out,
+                channel: Channel::Email as i32,
+                category: "recall".to_string(),
+            })
+            .await?
+            .into_inner();
+        assert!(!consent.subscribed);
+        Ok(())
+    }
 }
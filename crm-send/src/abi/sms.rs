@@ -3,7 +3,7 @@ use tracing::warn;
 
 use crate::pb::{send_request::Msg, SendRequest, SendResponse, SmsMessage};
 
-use super::{to_ts, Sender};
+use super::Sender;
 
 impl Sender for SmsMessage {
     async fn send(
@@ -16,10 +16,7 @@ impl Sender for SmsMessage {
             Status::internal("Failed to send sms message")
         })?;
 
-        Ok(SendResponse {
-            message_id,
-            timestamp: Some(to_ts()),
-        })
+        Ok(SendResponse::sent(message_id))
     }
 }
 
@@ -47,6 +44,9 @@ impl From<SmsMessage> for Msg {
 impl From<SmsMessage> for SendRequest {
     fn from(sms: SmsMessage) -> Self {
         let msg: Msg = sms.into();
-        SendRequest { msg: Some(msg) }
+        SendRequest {
+            msg: Some(msg),
+            ..Default::default()
+        }
     }
 }
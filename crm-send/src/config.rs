@@ -1,30 +1,143 @@
+use std::collections::HashMap;
+
 use anyhow::{bail, Result};
+use crm_common::{
+    check, check_db_url, check_endpoint, check_pk, check_service_keys, check_tls, ConfigLoader,
+    ListenConfig, Reload, RolePolicy, TlsConfig, Validate,
+};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
+    pub consent: ConsentConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AuthConfig {
     pub pk: String,
+    /// PEM encoded public keys of the services calling with tokens of their own, by name
+    #[serde(default)]
+    pub services: HashMap<String, String>,
+    /// roles allowed to call each method, by full gRPC path
+    #[serde(default)]
+    pub policy: RolePolicy,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
+    #[serde(default = "default_port")]
     pub port: u16,
+    /// host or Unix domain socket to listen on, loopback by default
+    #[serde(default)]
+    pub listen: ListenConfig,
+    /// serve TLS, and call other services over TLS, if set
+    pub tls: Option<TlsConfig>,
+    pub db_url: String,
+}
+
+/// Shortest consent secret accepted, the tokens signed with it are handed out to anyone.
+const MIN_SECRET_LEN: usize = 32;
+/// The secret of the example config of earlier versions.
+const EXAMPLE_SECRET: &str = "change-me-to-a-long-random-string";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConsentConfig {
+    /// key the unsubscribe tokens are signed with, at least `MIN_SECRET_LEN` characters
+    pub secret: String,
+    /// page the unsubscribe links in emails point to, the token is appended as a query
+    pub unsubscribe_url: String,
 }
 
 impl AppConfig {
+    /// Load `send.yml`, or the file given by `--config` or `CRM_SEND_CONFIG`, with `CRM_SEND_*` overrides.
     pub fn load() -> Result<Self> {
-        let config = match File::open("send.yml") {
-            Ok(reader) => serde_yaml::from_reader(reader),
-            _ => bail!("Config file not found"),
-        };
+        Self::loader()?.load()
+    }
+
+    /// The loader of the config, honouring `--config` among the command line arguments.
+    pub fn loader() -> Result<ConfigLoader> {
+        ConfigLoader::new("send.yml", "CRM_SEND").with_process_args()
+    }
+}
+
+#[cfg(feature = "test_utils")]
+const TEST_SECRET: &str = "test-secret-of-unsubscribe-tokens-0123456789";
+
+#[cfg(feature = "test_utils")]
+impl AppConfig {
+    /// The config of the tests, trusting the test key.
+    pub fn for_test() -> Result<Self> {
+        Self::loader()?.load_for_test(&[
+            ("AUTH__PK", crm_common::TEST_PK),
+            ("CONSENT__SECRET", TEST_SECRET),
+        ])
+    }
+}
+
+impl Reload for AppConfig {
+    fn needs_restart(&self, new: &Self) -> bool {
+        let (old, new_server) = (&self.server, &new.server);
+        (old.port, &old.listen, &old.tls, &old.db_url)
+            != (
+                new_server.port,
+                &new_server.listen,
+                &new_server.tls,
+                &new_server.db_url,
+            )
+            || self.auth != new.auth
+    }
+}
+
+impl Validate for AppConfig {
+    fn validate(&self) -> Result<()> {
+        check_db_url("server.db_url", &self.server.db_url)?;
+        check_tls("server.tls", self.server.tls.as_ref())?;
+        check_pk("auth.pk", &self.auth.pk)?;
+        check_service_keys("auth.services", &self.auth.services)?;
+        let services = ["notification.Notification"];
+        check("auth.policy", self.auth.policy.check(&services))?;
+        let secret = &self.consent.secret;
+        if secret == EXAMPLE_SECRET {
+            bail!("consent.secret is the example one, set a random secret of your own");
+        }
+        if secret.chars().count() < MIN_SECRET_LEN {
+            bail!(
+                "consent.secret must be at least {} characters",
+                MIN_SECRET_LEN
+            );
+        }
+        check_endpoint("consent.unsubscribe_url", &self.consent.unsubscribe_url)
+    }
+}
+
+fn default_port() -> u16 {
+    50003
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(secret: &str) -> Result<AppConfig> {
+        AppConfig::loader()?.load_for_test(&[
+            ("AUTH__PK", crm_common::TEST_PK),
+            ("CONSENT__SECRET", secret),
+        ])
+    }
 
-        Ok(config?)
+    #[test]
+    fn consent_secret_should_be_long_and_not_the_example() -> Result<()> {
+        load(TEST_SECRET)?;
+        let err = load(EXAMPLE_SECRET).unwrap_err();
+        assert!(format!("{err:#}").contains("example"), "{err:#}");
+        let err = load("secret").unwrap_err();
+        assert!(format!("{err:#}").contains("at least 32"), "{err:#}");
+        Ok(())
     }
 }
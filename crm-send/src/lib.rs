@@ -5,8 +5,13 @@ pub mod pb;
 use std::{pin::Pin, sync::Arc};
 
 pub use config::AppConfig;
+use crm_common::Reloadable;
 use futures::Stream;
-use pb::{notification_server::Notification, send_request::Msg, SendRequest, SendResponse};
+use pb::{
+    notification_server::Notification, send_request::Msg, Consent, ConsentTarget, SendRequest,
+    SendResponse, SubscribeRequest, UnsubscribeRequest,
+};
+use sqlx::MySqlPool;
 use tokio::sync::mpsc;
 use tonic::{async_trait, Request, Response, Status, Streaming};
 
@@ -20,7 +25,8 @@ pub struct NotificationService {
 
 #[allow(unused)]
 pub struct NotificationServiceInner {
-    config: AppConfig,
+    config: Reloadable<AppConfig>,
+    pool: MySqlPool,
     sender: mpsc::Sender<Msg>,
 }
 
@@ -35,4 +41,41 @@ impl Notification for NotificationService {
         let stream = request.into_inner();
         self.send(stream).await
     }
+
+    async fn subscribe(&self, request: Request<SubscribeRequest>) -> ServiceResult<Consent> {
+        self.subscribe(request.into_inner()).await
+    }
+
+    async fn unsubscribe(&self, request: Request<UnsubscribeRequest>) -> ServiceResult<Consent> {
+        self.unsubscribe(request.into_inner()).await
+    }
+
+    async fn get_consent(&self, request: Request<ConsentTarget>) -> ServiceResult<Consent> {
+        self.get_consent(request.into_inner()).await
+    }
+}
+
+#[cfg(feature = "test_utils")]
+pub mod test_utils {
+    use std::{env, path::Path};
+
+    use anyhow::Result;
+    use crm_common::{Reloadable, TestMysql};
+
+    use crate::{abi::dummy_sender, AppConfig, NotificationService, NotificationServiceInner};
+
+    impl NotificationService {
+        pub async fn new_for_test() -> Result<(TestMysql, Self)> {
+            let config = AppConfig::for_test()?;
+            let p = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("migrations");
+            let tdb = TestMysql::new("localhost", 3306, "root", "123456", p);
+            let pool = tdb.get_pool().await;
+            let inner = NotificationServiceInner {
+                config: Reloadable::new(config),
+                pool,
+                sender: dummy_sender(),
+            };
+            Ok((tdb, Self::from_inner(inner)))
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use anyhow::Result;
+use crm_common::{serve, server_builder, watch_config};
 use crm_send::{AppConfig, NotificationService};
-use tonic::transport::Server;
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
 
@@ -9,14 +9,18 @@ async fn main() -> Result<()> {
     let layer = Layer::new().with_filter(LevelFilter::INFO);
     tracing_subscriber::registry().with(layer).init();
 
-    let config = AppConfig::load()?;
-    let addr = config.server.port;
-    let addr = format!("[::1]:{}", addr).parse().unwrap();
+    let loader = AppConfig::loader()?;
+    let config: AppConfig = loader.load()?;
+    let server = &config.server;
+    let addr = server.listen.resolve(server.port).await?;
 
     info!("Send service listening on {}", addr);
 
-    let svc = NotificationService::new(config).into_server();
-    Server::builder().add_service(svc).serve(addr).await?;
-
-    Ok(())
+    let tls = config.server.tls.clone();
+    let svc = NotificationService::try_new(config).await?;
+    let reloaded = svc.clone();
+    watch_config(loader, move |config| reloaded.reload(config));
+    let svc = svc.into_server()?;
+    let router = server_builder(tls.as_ref())?.add_service(svc);
+    serve(router, &addr).await
 }
@@ -57,6 +57,9 @@ pub struct InAppMessage {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SendRequest {
+    /// category of the message recipients can unsubscribe from, e.g. recall
+    #[prost(string, tag = "5")]
+    pub category: ::prost::alloc::string::String,
     /// one of the message types to send
     #[prost(oneof = "send_request::Msg", tags = "2, 3, 4")]
     pub msg: ::core::option::Option<send_request::Msg>,
@@ -85,6 +88,130 @@ pub struct SendResponse {
     /// timestamp of when the message was sent
     #[prost(message, optional, tag = "2")]
     pub timestamp: ::core::option::Option<::prost_types::Timestamp>,
+    /// whether the message is sent
+    #[prost(enumeration = "SendStatus", tag = "3")]
+    pub status: i32,
+    /// reason of the failure, empty if the message is sent
+    #[prost(string, tag = "4")]
+    pub error: ::prost::alloc::string::String,
+}
+/// what a consent applies to
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ConsentTarget {
+    /// the address the channel delivers to: email, phone number or device id
+    #[prost(string, tag = "1")]
+    pub recipient: ::prost::alloc::string::String,
+    #[prost(enumeration = "Channel", tag = "2")]
+    pub channel: i32,
+    /// empty for all categories
+    #[prost(string, tag = "3")]
+    pub category: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscribeRequest {
+    #[prost(message, optional, tag = "1")]
+    pub target: ::core::option::Option<ConsentTarget>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UnsubscribeRequest {
+    #[prost(oneof = "unsubscribe_request::By", tags = "1, 2")]
+    pub by: ::core::option::Option<unsubscribe_request::By>,
+}
+/// Nested message and enum types in `UnsubscribeRequest`.
+pub mod unsubscribe_request {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum By {
+        #[prost(message, tag = "1")]
+        Target(super::ConsentTarget),
+        /// token of the unsubscribe link in an email
+        #[prost(string, tag = "2")]
+        Token(::prost::alloc::string::String),
+    }
+}
+/// whether the recipient accepts messages of the target, subscribed unless opted out
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Consent {
+    #[prost(message, optional, tag = "1")]
+    pub target: ::core::option::Option<ConsentTarget>,
+    #[prost(bool, tag = "2")]
+    pub subscribed: bool,
+    /// empty if never changed
+    #[prost(message, optional, tag = "3")]
+    pub updated_at: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// delivery status of a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SendStatus {
+    Unspecified = 0,
+    /// the message is accepted for delivery
+    Sent = 1,
+    /// the message could not be delivered
+    Failed = 2,
+    /// all the recipients opted out
+    Rejected = 3,
+}
+impl SendStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SendStatus::Unspecified => "SEND_STATUS_UNSPECIFIED",
+            SendStatus::Sent => "SEND_STATUS_SENT",
+            SendStatus::Failed => "SEND_STATUS_FAILED",
+            SendStatus::Rejected => "SEND_STATUS_REJECTED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SEND_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "SEND_STATUS_SENT" => Some(Self::Sent),
+            "SEND_STATUS_FAILED" => Some(Self::Failed),
+            "SEND_STATUS_REJECTED" => Some(Self::Rejected),
+            _ => None,
+        }
+    }
+}
+/// channel a message is delivered on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Channel {
+    Unspecified = 0,
+    Email = 1,
+    Sms = 2,
+    InApp = 3,
+}
+impl Channel {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Channel::Unspecified => "CHANNEL_UNSPECIFIED",
+            Channel::Email => "CHANNEL_EMAIL",
+            Channel::Sms => "CHANNEL_SMS",
+            Channel::InApp => "CHANNEL_IN_APP",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CHANNEL_UNSPECIFIED" => Some(Self::Unspecified),
+            "CHANNEL_EMAIL" => Some(Self::Email),
+            "CHANNEL_SMS" => Some(Self::Sms),
+            "CHANNEL_IN_APP" => Some(Self::InApp),
+            _ => None,
+        }
+    }
 }
 /// Generated client implementations.
 pub mod notification_client {
@@ -192,6 +319,62 @@ pub mod notification_client {
                 .insert(GrpcMethod::new("notification.Notification", "Send"));
             self.inner.streaming(req, path, codec).await
         }
+        /// opt the recipient back in
+        pub async fn subscribe(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SubscribeRequest>,
+        ) -> std::result::Result<tonic::Response<super::Consent>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/notification.Notification/Subscribe");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("notification.Notification", "Subscribe"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// opt the recipient out, by target or by the token of an unsubscribe link
+        pub async fn unsubscribe(
+            &mut self,
+            request: impl tonic::IntoRequest<super::UnsubscribeRequest>,
+        ) -> std::result::Result<tonic::Response<super::Consent>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/notification.Notification/Unsubscribe");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("notification.Notification", "Unsubscribe"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// effective consent of the recipient for the target
+        pub async fn get_consent(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ConsentTarget>,
+        ) -> std::result::Result<tonic::Response<super::Consent>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/notification.Notification/GetConsent");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("notification.Notification", "GetConsent"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -211,6 +394,21 @@ pub mod notification_server {
             &self,
             request: tonic::Request<tonic::Streaming<super::SendRequest>>,
         ) -> std::result::Result<tonic::Response<Self::SendStream>, tonic::Status>;
+        /// opt the recipient back in
+        async fn subscribe(
+            &self,
+            request: tonic::Request<super::SubscribeRequest>,
+        ) -> std::result::Result<tonic::Response<super::Consent>, tonic::Status>;
+        /// opt the recipient out, by target or by the token of an unsubscribe link
+        async fn unsubscribe(
+            &self,
+            request: tonic::Request<super::UnsubscribeRequest>,
+        ) -> std::result::Result<tonic::Response<super::Consent>, tonic::Status>;
+        /// effective consent of the recipient for the target
+        async fn get_consent(
+            &self,
+            request: tonic::Request<super::ConsentTarget>,
+        ) -> std::result::Result<tonic::Response<super::Consent>, tonic::Status>;
     }
     /// The Notification service provides a way to send notifications to users.
     #[derive(Debug)]
@@ -326,6 +524,123 @@ pub mod notification_server {
                     };
                     Box::pin(fut)
                 }
+                "/notification.Notification/Subscribe" => {
+                    #[allow(non_camel_case_types)]
+                    struct SubscribeSvc<T: Notification>(pub Arc<T>);
+                    impl<T: Notification> tonic::server::UnaryService<super::SubscribeRequest> for SubscribeSvc<T> {
+                        type Response = super::Consent;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubscribeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::subscribe(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SubscribeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/notification.Notification/Unsubscribe" => {
+                    #[allow(non_camel_case_types)]
+                    struct UnsubscribeSvc<T: Notification>(pub Arc<T>);
+                    impl<T: Notification> tonic::server::UnaryService<super::UnsubscribeRequest> for UnsubscribeSvc<T> {
+                        type Response = super::Consent;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::UnsubscribeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::unsubscribe(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = UnsubscribeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/notification.Notification/GetConsent" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetConsentSvc<T: Notification>(pub Arc<T>);
+                    impl<T: Notification> tonic::server::UnaryService<super::ConsentTarget> for GetConsentSvc<T> {
+                        type Response = super::Consent;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ConsentTarget>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Notification>::get_consent(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetConsentSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
@@ -1,6 +1,7 @@
 use std::{net::SocketAddr, time::Duration};
 
 use anyhow::Result;
+use crm_common::{TestMysql, TokenInterceptor};
 use crm_send::{
     pb::{
         notification_client::NotificationClient, EmailMessage, InAppMessage, SendRequest,
@@ -10,22 +11,23 @@ use crm_send::{
 };
 use futures::StreamExt;
 use tokio::time::sleep;
-use tonic::{transport::Server, Request};
+use tonic::{
+    transport::{Channel, Server},
+    Request,
+};
 
 #[tokio::test]
 async fn test_send_should_work() -> Result<()> {
-    let addr = start_server().await?;
-    let mut client = NotificationClient::connect(format!("http://{addr}")).await?;
+    let (_tdb, addr) = start_server().await?;
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let auth = TokenInterceptor::for_test("crm", &["service"]);
+    let mut client = NotificationClient::with_interceptor(channel, auth);
     let stream = tokio_stream::iter(vec![
-        SendRequest {
-            msg: Some(EmailMessage::fake().into()),
-        },
-        SendRequest {
-            msg: Some(SmsMessage::fake().into()),
-        },
-        SendRequest {
-            msg: Some(InAppMessage::fake().into()),
-        },
+        SendRequest::from(EmailMessage::fake()),
+        SendRequest::from(SmsMessage::fake()),
+        SendRequest::from(InAppMessage::fake()),
     ]);
     let response = client.send(Request::new(stream)).await?.into_inner();
     let result = response
@@ -36,11 +38,12 @@ async fn test_send_should_work() -> Result<()> {
     Ok(())
 }
 
-async fn start_server() -> Result<SocketAddr> {
-    let config = AppConfig::load()?;
+async fn start_server() -> Result<(TestMysql, SocketAddr)> {
+    let config = AppConfig::for_test()?;
     let addr = format!("[::1]:{}", config.server.port).parse()?;
 
-    let svc = NotificationService::new(config).into_server();
+    let (tdb, svc) = NotificationService::new_for_test().await?;
+    let svc = svc.into_server()?;
     tokio::spawn(async move {
         Server::builder()
             .add_service(svc)
@@ -50,5 +53,5 @@ async fn start_server() -> Result<SocketAddr> {
     });
     sleep(Duration::from_micros(1)).await;
 
-    Ok(addr)
+    Ok((tdb, addr))
 }
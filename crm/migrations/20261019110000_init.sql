@@ -0,0 +1,16 @@
+-- Add migration script here
+
+CREATE TABLE campaigns(
+    id varchar(64) NOT NULL PRIMARY KEY COMMENT 'id of the campaign request',
+    kind int NOT NULL COMMENT 'CampaignKind',
+    params blob NOT NULL COMMENT 'protobuf encoded CampaignParams',
+    status int NOT NULL COMMENT 'CampaignStatus',
+    started_at datetime(3) NOT NULL COMMENT 'started time',
+    finished_at datetime(3) COMMENT 'finished time',
+    users_matched bigint unsigned NOT NULL DEFAULT 0 COMMENT 'users returned by user-stat',
+    messages_sent bigint unsigned NOT NULL DEFAULT 0 COMMENT 'messages accepted by crm-send',
+    messages_failed bigint unsigned NOT NULL DEFAULT 0 COMMENT 'messages failed to build or send',
+    error text COMMENT 'why the campaign failed',
+    KEY `idx_started_at` (started_at),
+    KEY `idx_kind_started_at` (kind, started_at)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Campaign runs';
@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table campaigns add column messages_queued bigint unsigned NOT NULL DEFAULT 0 COMMENT 'messages handed over to crm-send' after users_matched;
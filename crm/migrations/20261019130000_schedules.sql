@@ -0,0 +1,14 @@
+-- Add migration script here
+
+CREATE TABLE schedules(
+    id varchar(64) NOT NULL PRIMARY KEY COMMENT 'id of the schedule',
+    params blob NOT NULL COMMENT 'protobuf encoded CampaignParams',
+    cron varchar(128) COMMENT 'cron expression, either cron or interval is set',
+    `interval` int unsigned COMMENT 'seconds between two runs',
+    status int NOT NULL COMMENT 'ScheduleStatus',
+    next_run_at datetime(3) NOT NULL COMMENT 'when the next run is due',
+    last_run_at datetime(3) COMMENT 'when the last run started',
+    last_campaign_id varchar(64) COMMENT 'campaign id of the last run',
+    created_at datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'created time',
+    KEY `idx_status_next_run_at` (status, next_run_at)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Campaign schedules';
@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table campaigns add column output varchar(255) COMMENT 'file the messages of a dry run are written to' after error;
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE experiment_assignments(
+    campaign_id varchar(64) NOT NULL COMMENT 'id of the campaign',
+    email varchar(128) NOT NULL COMMENT 'user email',
+    variant varchar(64) NOT NULL COMMENT 'name of the variant, holdout for the holdout group',
+    assigned_at datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'assigned time',
+    PRIMARY KEY (campaign_id, email),
+    KEY `idx_campaign_variant` (campaign_id, variant)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Experiment variant of each user of a campaign';
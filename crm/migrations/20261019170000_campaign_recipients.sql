@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE campaign_recipients(
+    campaign_id varchar(64) NOT NULL COMMENT 'id of the campaign',
+    email varchar(128) NOT NULL COMMENT 'user email',
+    variant varchar(64) NOT NULL DEFAULT '' COMMENT 'experiment variant, empty without experiment',
+    content_ids text COMMENT 'promoted content id list, split by comma',
+    messaged_at datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'time the message was queued',
+    PRIMARY KEY (campaign_id, email)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Users messaged by each campaign';
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TABLE segments(
+    name varchar(64) NOT NULL PRIMARY KEY COMMENT 'name campaigns refer to the segment by',
+    description text COMMENT 'what the segment is for',
+    segment blob NOT NULL COMMENT 'protobuf encoded Segment',
+    updated_at datetime(3) NOT NULL COMMENT 'last saved time'
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Saved segments';
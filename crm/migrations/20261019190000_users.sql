@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE users(
+    id bigint unsigned NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    name varchar(64) NOT NULL COMMENT 'user name',
+    email varchar(128) NOT NULL COMMENT 'user email, lower cased',
+    created_at datetime(3) NOT NULL COMMENT 'created time',
+    updated_at datetime(3) NOT NULL COMMENT 'last updated time',
+    UNIQUE KEY `uk_email` (email)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Registered users';
@@ -0,0 +1,14 @@
+-- Add migration script here
+
+CREATE TABLE triggered_actions(
+    id bigint unsigned NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    rule varchar(64) NOT NULL COMMENT 'name of the trigger rule',
+    email varchar(128) NOT NULL COMMENT 'user email',
+    content_id int unsigned NOT NULL DEFAULT 0 COMMENT 'content of the event, 0 if none',
+    status int NOT NULL COMMENT 'TriggeredActionStatus',
+    due_at datetime(3) NOT NULL COMMENT 'when the campaign is due',
+    campaign_id varchar(64) COMMENT 'campaign started once due',
+    created_at datetime(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'created time',
+    KEY `idx_status_due_at` (status, due_at),
+    KEY `idx_email_status` (email, status)
+) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci COMMENT 'Pending and past campaigns of trigger rules';
@@ -0,0 +1,4 @@
+-- Add migration script here
+alter table campaigns add column owner varchar(64) COMMENT 'crm instance running the campaign' after output;
+alter table campaigns add column heartbeat_at datetime(3) COMMENT 'last time the owner reported the campaign alive' after owner;
+alter table campaigns add key `idx_status_heartbeat_at` (status, heartbeat_at);
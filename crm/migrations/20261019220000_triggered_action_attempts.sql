@@ -0,0 +1,4 @@
+-- Add migration script here
+alter table triggered_actions add column attempts int unsigned NOT NULL DEFAULT 0 COMMENT 'times its campaign was tried to start' after campaign_id;
+alter table triggered_actions add column claimed_at datetime(3) COMMENT 'when an instance started firing it' after attempts;
+alter table triggered_actions add column error varchar(1024) COMMENT 'why its campaign failed to start last' after claimed_at;
@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table campaign_recipients add index `idx_email_messaged_at` (email, messaged_at);
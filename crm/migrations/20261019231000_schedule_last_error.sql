@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table schedules add column last_error varchar(1024) COMMENT 'why the campaign of the last run failed to start' after last_campaign_id;
@@ -0,0 +1,540 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use prost::Message;
+use prost_types::Timestamp;
+use sqlx::FromRow;
+use tonic::{Response, Status};
+use tracing::warn;
+
+use crate::{
+    pb::{
+        campaign_params::Request, Campaign, CampaignKind, CampaignParams, CampaignProgress,
+        CampaignStatus, GetCampaignRequest, ListCampaignsRequest, RecallRequest, RemindRequest,
+        WelcomeRequest,
+    },
+    CampaignStream, CrmService,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+/// Longest campaign id, the size of campaigns.id.
+pub(crate) const MAX_CAMPAIGN_ID_LEN: usize = 64;
+/// How often an instance reports the campaigns it runs alive.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Campaigns not reported alive for this long are left behind by an instance that died.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+const CAMPAIGN_COLUMNS: &str = "id, kind, params, status, started_at, finished_at, users_matched, messages_queued, messages_sent, messages_failed, error, output";
+
+/// Counters of a campaign run, shared by the stages of the pipeline.
+#[derive(Debug, Default)]
+pub struct CampaignStats {
+    pub users_matched: AtomicU64,
+    pub messages_queued: AtomicU64,
+    pub messages_sent: AtomicU64,
+    pub messages_failed: AtomicU64,
+}
+
+#[derive(Debug, FromRow)]
+struct CampaignRow {
+    id: String,
+    kind: i32,
+    params: Vec<u8>,
+    status: i32,
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    users_matched: u64,
+    messages_queued: u64,
+    messages_sent: u64,
+    messages_failed: u64,
+    error: Option<String>,
+    output: Option<String>,
+}
+
+impl CrmService {
+    pub async fn get_campaign(
+        &self,
+        request: GetCampaignRequest,
+    ) -> Result<Response<Campaign>, Status> {
+        let sql = format!("SELECT {CAMPAIGN_COLUMNS} FROM campaigns WHERE id = ?");
+        let row = sqlx::query_as::<_, CampaignRow>(&sql)
+            .bind(&request.id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        match row {
+            Some(row) => Ok(Response::new(row.into())),
+            None => Err(Status::not_found(format!(
+                "campaign {} not found",
+                request.id
+            ))),
+        }
+    }
+
+    pub async fn list_campaigns(
+        &self,
+        request: ListCampaignsRequest,
+    ) -> Result<Response<CampaignStream>, Status> {
+        let limit = match request.limit {
+            0 => DEFAULT_PAGE_SIZE,
+            v => v,
+        };
+        let sql = format!(
+            "SELECT {CAMPAIGN_COLUMNS} FROM campaigns WHERE (? = 0 OR kind = ?) ORDER BY started_at DESC LIMIT ? OFFSET ?"
+        );
+        let rows = sqlx::query_as::<_, CampaignRow>(&sql)
+            .bind(request.kind)
+            .bind(request.kind)
+            .bind(limit)
+            .bind(request.offset)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        let campaigns = rows.into_iter().map(Campaign::from).map(Ok);
+        Ok(Response::new(Box::pin(futures::stream::iter(campaigns))))
+    }
+
+    /// Record a new campaign run in accepted status, with the file a dry run writes to.
+    pub(crate) async fn create_campaign(
+        &self,
+        params: &CampaignParams,
+        output: Option<&Path>,
+    ) -> Result<(), Status> {
+        if params.id().is_empty() {
+            return Err(Status::invalid_argument("campaign id is required"));
+        }
+        if params.id().len() > MAX_CAMPAIGN_ID_LEN {
+            return Err(Status::invalid_argument(format!(
+                "campaign id is longer than {} bytes",
+                MAX_CAMPAIGN_ID_LEN
+            )));
+        }
+        self.check_params(params).await?;
+
+        let output = output.map(|v| v.display().to_string());
+        sqlx::query(
+            "INSERT INTO campaigns(id, kind, params, status, started_at, output, owner, heartbeat_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(params.id())
+        .bind(params.kind() as i32)
+        .bind(params.encode_to_vec())
+        .bind(CampaignStatus::Accepted as i32)
+        .bind(Utc::now())
+        .bind(output)
+        .bind(&self.instance)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(err) if err.is_unique_violation() => {
+                Status::already_exists(format!("campaign {} already exists", params.id()))
+            }
+            _ => db_err(e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Check the experiment and the segment of a campaign, before it is run or scheduled.
+    pub(crate) async fn check_params(&self, params: &CampaignParams) -> Result<(), Status> {
+        if let Some(experiment) = params.experiment() {
+            experiment
+                .validate()
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        }
+        if let Some(segment) = params.segment() {
+            self.compile_segment(segment).await?;
+        }
+        Ok(())
+    }
+
+    /// Status of a campaign, `None` if it doesn't exist.
+    pub(crate) async fn campaign_status(&self, id: &str) -> Result<Option<CampaignStatus>, Status> {
+        let status: Option<i32> = sqlx::query_scalar("SELECT status FROM campaigns WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        Ok(status.map(|v| CampaignStatus::try_from(v).unwrap_or_default()))
+    }
+
+    /// Mark an accepted campaign as running.
+    pub(crate) async fn start_campaign(&self, id: &str) -> Result<(), Status> {
+        sqlx::query("UPDATE campaigns SET status = ? WHERE id = ?")
+            .bind(CampaignStatus::Running as i32)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Record the result of a campaign run.
+    pub(crate) async fn finish_campaign(
+        &self,
+        id: &str,
+        stats: &CampaignStats,
+        status: CampaignStatus,
+        error: Option<&Status>,
+    ) -> Result<(), Status> {
+        sqlx::query(
+            "UPDATE campaigns SET status = ?, finished_at = ?, users_matched = ?, messages_queued = ?, messages_sent = ?, messages_failed = ?, error = ? WHERE id = ?",
+        )
+        .bind(status as i32)
+        .bind(Utc::now())
+        .bind(stats.users_matched.load(Ordering::Relaxed))
+        .bind(stats.messages_queued.load(Ordering::Relaxed))
+        .bind(stats.messages_sent.load(Ordering::Relaxed))
+        .bind(stats.messages_failed.load(Ordering::Relaxed))
+        .bind(error.map(|e| e.message().to_string()))
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Report the campaigns of this instance alive, and abort those of dead instances,
+    /// periodically.
+    pub(crate) fn start_heartbeat(&self) {
+        let svc = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = svc.heartbeat_campaigns().await {
+                    warn!("failed to report campaigns alive: {:?}", e);
+                }
+                if let Err(e) = svc.abort_stale_campaigns().await {
+                    warn!("failed to abort stale campaigns: {:?}", e);
+                }
+            }
+        });
+    }
+
+    async fn heartbeat_campaigns(&self) -> Result<(), Status> {
+        sqlx::query("UPDATE campaigns SET heartbeat_at = ? WHERE owner = ? AND status IN (?, ?)")
+            .bind(Utc::now())
+            .bind(&self.instance)
+            .bind(CampaignStatus::Accepted as i32)
+            .bind(CampaignStatus::Running as i32)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        Ok(())
+    }
+
+    /// Campaigns still accepted or running whose owner stopped reporting them alive were left
+    /// behind by an instance that died; they will never finish, so record them as failed.
+    pub(crate) async fn abort_stale_campaigns(&self) -> Result<(), Status> {
+        let timeout = chrono::Duration::from_std(HEARTBEAT_TIMEOUT).unwrap_or_default();
+        let ret = sqlx::query(
+            "UPDATE campaigns SET status = ?, finished_at = ?, error = ? WHERE status IN (?, ?) AND (heartbeat_at IS NULL OR heartbeat_at < ?)",
+        )
+        .bind(CampaignStatus::Failed as i32)
+        .bind(Utc::now())
+        .bind("interrupted, the crm instance running it stopped")
+        .bind(CampaignStatus::Accepted as i32)
+        .bind(CampaignStatus::Running as i32)
+        .bind(Utc::now() - timeout)
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        if ret.rows_affected() > 0 {
+            warn!("aborted {} stale campaigns", ret.rows_affected());
+        }
+        Ok(())
+    }
+}
+
+impl CampaignStats {
+    pub fn progress(&self, id: &str, status: CampaignStatus) -> CampaignProgress {
+        CampaignProgress {
+            id: id.to_string(),
+            status: status as i32,
+            users_scanned: self.users_matched.load(Ordering::Relaxed),
+            messages_queued: self.messages_queued.load(Ordering::Relaxed),
+            messages_delivered: self.messages_sent.load(Ordering::Relaxed),
+            messages_failed: self.messages_failed.load(Ordering::Relaxed),
+            error: String::new(),
+        }
+    }
+}
+
+impl CampaignStatus {
+    /// Whether the campaign has stopped and its counters are final.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            CampaignStatus::Completed | CampaignStatus::Failed | CampaignStatus::Cancelled
+        )
+    }
+}
+
+impl From<Campaign> for CampaignProgress {
+    fn from(c: Campaign) -> Self {
+        CampaignProgress {
+            id: c.id,
+            status: c.status,
+            users_scanned: c.users_matched,
+            messages_queued: c.messages_queued,
+            messages_delivered: c.messages_sent,
+            messages_failed: c.messages_failed,
+            error: c.error,
+        }
+    }
+}
+
+impl CampaignParams {
+    pub fn id(&self) -> &str {
+        match &self.request {
+            Some(Request::Welcome(req)) => &req.id,
+            Some(Request::Recall(req)) => &req.id,
+            Some(Request::Remind(req)) => &req.id,
+            None => "",
+        }
+    }
+
+    /// The same params for a campaign with another id.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        let id = id.into();
+        match &mut self.request {
+            Some(Request::Welcome(req)) => req.id = id,
+            Some(Request::Recall(req)) => req.id = id,
+            Some(Request::Remind(req)) => req.id = id,
+            None => {}
+        }
+        self
+    }
+
+    /// Whether the messages are rendered into a file instead of being sent.
+    pub fn dry_run(&self) -> bool {
+        match &self.request {
+            Some(Request::Welcome(req)) => req.dry_run,
+            Some(Request::Recall(req)) => req.dry_run,
+            Some(Request::Remind(req)) => req.dry_run,
+            None => false,
+        }
+    }
+
+    pub fn kind(&self) -> CampaignKind {
+        match &self.request {
+            Some(Request::Welcome(_)) => CampaignKind::Welcome,
+            Some(Request::Recall(_)) => CampaignKind::Recall,
+            Some(Request::Remind(_)) => CampaignKind::Remind,
+            None => CampaignKind::Unspecified,
+        }
+    }
+}
+
+impl CampaignKind {
+    /// Name of the template the campaign renders its messages with.
+    pub fn template_name(&self) -> &'static str {
+        match self {
+            CampaignKind::Welcome => "welcome",
+            CampaignKind::Recall => "recall",
+            CampaignKind::Remind => "remind",
+            CampaignKind::Unspecified => "",
+        }
+    }
+}
+
+impl From<WelcomeRequest> for CampaignParams {
+    fn from(req: WelcomeRequest) -> Self {
+        CampaignParams {
+            request: Some(Request::Welcome(req)),
+        }
+    }
+}
+
+impl From<RecallRequest> for CampaignParams {
+    fn from(req: RecallRequest) -> Self {
+        CampaignParams {
+            request: Some(Request::Recall(req)),
+        }
+    }
+}
+
+impl From<RemindRequest> for CampaignParams {
+    fn from(req: RemindRequest) -> Self {
+        CampaignParams {
+            request: Some(Request::Remind(req)),
+        }
+    }
+}
+
+impl From<CampaignRow> for Campaign {
+    fn from(row: CampaignRow) -> Self {
+        let params = CampaignParams::decode(row.params.as_slice())
+            .inspect_err(|e| warn!("failed to decode params of campaign {}: {:?}", row.id, e))
+            .ok();
+
+        Campaign {
+            id: row.id,
+            kind: row.kind,
+            params,
+            status: row.status,
+            started_at: Some(dt_to_ts(row.started_at)),
+            finished_at: row.finished_at.map(dt_to_ts),
+            users_matched: row.users_matched,
+            messages_queued: row.messages_queued,
+            messages_sent: row.messages_sent,
+            messages_failed: row.messages_failed,
+            error: row.error.unwrap_or_default(),
+            output: row.output.unwrap_or_default(),
+        }
+    }
+}
+
+pub(crate) fn db_err(e: sqlx::Error) -> Status {
+    warn!("database error: {:?}", e);
+    Status::internal("database error")
+}
+
+pub(crate) fn dt_to_ts(dt: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn welcome(id: &str) -> CampaignParams {
+        WelcomeRequest {
+            id: id.to_string(),
+            interval: 30,
+            content_ids: vec![1, 2],
+            channels: None,
+            dry_run: false,
+            experiment: None,
+            segment: None,
+            emails: vec![],
+        }
+        .into()
+    }
+
+    #[test]
+    fn campaign_params_should_expose_id_and_kind() {
+        let params = welcome("c1");
+        assert_eq!(params.id(), "c1");
+        assert_eq!(params.kind(), CampaignKind::Welcome);
+        assert_eq!(params.kind().template_name(), "welcome");
+        assert_eq!(params.with_id("c3").id(), "c3");
+
+        let params: CampaignParams = RemindRequest {
+            id: "c2".to_string(),
+            last_visit_interval: 7,
+            channels: None,
+            dry_run: false,
+            experiment: None,
+            segment: None,
+            emails: vec![],
+        }
+        .into();
+        assert_eq!(params.kind().template_name(), "remind");
+    }
+
+    #[test]
+    fn campaign_stats_should_report_progress() {
+        let stats = CampaignStats::default();
+        stats.users_matched.store(5, Ordering::Relaxed);
+        stats.messages_queued.store(4, Ordering::Relaxed);
+        stats.messages_sent.store(3, Ordering::Relaxed);
+        stats.messages_failed.store(1, Ordering::Relaxed);
+
+        let progress = stats.progress("c1", CampaignStatus::Running);
+        assert_eq!(progress.status(), CampaignStatus::Running);
+        assert_eq!(
+            (
+                progress.users_scanned,
+                progress.messages_queued,
+                progress.messages_delivered,
+                progress.messages_failed
+            ),
+            (5, 4, 3, 1)
+        );
+        assert!(!CampaignStatus::Running.is_terminal());
+        assert!(CampaignStatus::Cancelled.is_terminal());
+    }
+
+    #[tokio::test]
+    async fn campaign_should_be_persisted() -> Result<()> {
+        let (_tdb, svc) = CrmService::new_for_test().await?;
+        let params = welcome("c1");
+        svc.create_campaign(&params, None).await?;
+
+        let campaign = svc
+            .get_campaign(GetCampaignRequest {
+                id: "c1".to_string(),
+            })
+            .await?
+            .into_inner();
+        assert_eq!(campaign.status(), CampaignStatus::Accepted);
+
+        let err = svc.create_campaign(&params, None).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        let stats = CampaignStats::default();
+        stats.users_matched.store(3, Ordering::Relaxed);
+        stats.messages_sent.store(2, Ordering::Relaxed);
+        stats.messages_failed.store(1, Ordering::Relaxed);
+        svc.finish_campaign("c1", &stats, CampaignStatus::Completed, None)
+            .await?;
+
+        let req = GetCampaignRequest {
+            id: "c1".to_string(),
+        };
+        let campaign = svc.get_campaign(req).await?.into_inner();
+        assert_eq!(campaign.status(), CampaignStatus::Completed);
+        assert_eq!(campaign.params, Some(params));
+        assert_eq!(
+            (
+                campaign.users_matched,
+                campaign.messages_sent,
+                campaign.messages_failed
+            ),
+            (3, 2, 1)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_campaigns_of_dead_instances_should_be_aborted() -> Result<()> {
+        let (_tdb, svc) = CrmService::new_for_test().await?;
+        svc.create_campaign(&welcome("alive"), None).await?;
+        svc.create_campaign(&welcome("dead"), None).await?;
+        let long_ago = Utc::now() - chrono::Duration::minutes(10);
+        sqlx::query("UPDATE campaigns SET owner = 'other', heartbeat_at = ? WHERE id = 'dead'")
+            .bind(long_ago)
+            .execute(&svc.pool)
+            .await?;
+
+        svc.heartbeat_campaigns().await?;
+        svc.abort_stale_campaigns().await?;
+        assert_eq!(
+            svc.campaign_status("alive").await?,
+            Some(CampaignStatus::Accepted)
+        );
+        assert_eq!(
+            svc.campaign_status("dead").await?,
+            Some(CampaignStatus::Failed)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use crm_metadata::Rendered;
+use crm_send::pb::SendRequest;
+use user_stat::pb::User;
+
+use crate::{
+    pb::{campaign_params::Request, CampaignParams, Channel, ChannelPolicy, DeliveryMode},
+    ServerConfig,
+};
+
+impl ChannelPolicy {
+    /// Channels to send the message on for the user, in order.
+    pub fn select(&self, user: &User) -> Vec<Channel> {
+        let channels: Vec<Channel> = match self.channels().next() {
+            Some(_) => self.channels().collect(),
+            None => vec![Channel::Email],
+        };
+
+        match self.mode() {
+            DeliveryMode::Unspecified | DeliveryMode::Single => channels
+                .into_iter()
+                .take(1)
+                .filter(|c| c.reaches(user))
+                .collect(),
+            DeliveryMode::Fallback => channels
+                .into_iter()
+                .find(|c| c.reaches(user))
+                .into_iter()
+                .collect(),
+            DeliveryMode::All => channels.into_iter().filter(|c| c.reaches(user)).collect(),
+        }
+    }
+}
+
+impl Channel {
+    /// Whether the user has the contact data the channel needs.
+    pub fn reaches(&self, user: &User) -> bool {
+        match self {
+            Channel::Email => !user.email.is_empty(),
+            Channel::Sms => !user.phone.is_empty(),
+            Channel::InApp => !user.device_id.is_empty(),
+            Channel::Unspecified => false,
+        }
+    }
+
+    pub fn build_request(
+        &self,
+        config: &ServerConfig,
+        user: &User,
+        rendered: Rendered,
+    ) -> Option<SendRequest> {
+        match self {
+            Channel::Email => Some(SendRequest::new(
+                config.sender_email.clone(),
+                std::slice::from_ref(&user.email),
+                rendered,
+            )),
+            Channel::Sms => Some(SendRequest::sms(
+                config.sender_phone.clone(),
+                std::slice::from_ref(&user.phone),
+                rendered,
+            )),
+            Channel::InApp => Some(SendRequest::in_app(user.device_id.clone(), rendered)),
+            Channel::Unspecified => None,
+        }
+    }
+}
+
+impl CampaignParams {
+    pub fn channel_policy(&self) -> ChannelPolicy {
+        let policy = match &self.request {
+            Some(Request::Welcome(req)) => req.channels.clone(),
+            Some(Request::Recall(req)) => req.channels.clone(),
+            Some(Request::Remind(req)) => req.channels.clone(),
+            None => None,
+        };
+        policy.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mode: DeliveryMode, channels: &[Channel]) -> ChannelPolicy {
+        ChannelPolicy {
+            mode: mode as i32,
+            channels: channels.iter().map(|c| *c as i32).collect(),
+        }
+    }
+
+    fn user(phone: &str, device_id: &str) -> User {
+        User {
+            email: "a@example.com".to_string(),
+            phone: phone.to_string(),
+            device_id: device_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn channel_policy_should_select_channels() {
+        let channels = [Channel::InApp, Channel::Sms, Channel::Email];
+        let with_phone = user("+1555", "");
+        let with_all = user("+1555", "d1");
+
+        assert_eq!(
+            ChannelPolicy::default().select(&with_phone),
+            vec![Channel::Email]
+        );
+
+        let single = policy(DeliveryMode::Single, &channels);
+        assert_eq!(single.select(&with_phone), vec![]);
+        assert_eq!(single.select(&with_all), vec![Channel::InApp]);
+
+        let fallback = policy(DeliveryMode::Fallback, &channels);
+        assert_eq!(fallback.select(&with_phone), vec![Channel::Sms]);
+
+        let all = policy(DeliveryMode::All, &channels);
+        assert_eq!(all.select(&with_phone), vec![Channel::Sms, Channel::Email]);
+        assert_eq!(all.select(&with_all), channels.to_vec());
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crm_common::AuthChannel;
+use crm_metadata::pb::{metadata_client::MetadataClient, Content, MaterializeRequest};
+use futures::StreamExt;
+use tracing::{info, warn};
+use user_stat::pb::User;
+
+/// Unfinished contents listed in a remind message at most.
+const MAX_UNFINISHED_CONTENTS: usize = 5;
+/// Materialized contents kept by a campaign at most, the cache is reset beyond it.
+const MAX_CACHED_CONTENTS: usize = 10000;
+
+/// Contents of the messages of a campaign.
+pub(crate) enum CampaignContents {
+    /// the same contents for every user
+    Fixed(Arc<Vec<Content>>),
+    /// contents each user started but not finished
+    Unfinished(Box<ContentCache>),
+}
+
+/// Contents materialized by crm-metadata, loaded batch by batch.
+pub(crate) struct ContentCache {
+    metadata: MetadataClient<AuthChannel>,
+    contents: HashMap<u32, Content>,
+}
+
+impl CampaignContents {
+    /// Load what the users of a batch need.
+    pub async fn prepare(&mut self, users: &[User]) {
+        if let CampaignContents::Unfinished(cache) = self {
+            let ids: Vec<u32> = users.iter().flat_map(unfinished_ids).copied().collect();
+            cache.load(&ids).await;
+        }
+    }
+
+    /// Contents to put in the message to the user, leaving out what the user has finished.
+    pub fn for_user(&self, user: &User) -> Vec<Content> {
+        match self {
+            CampaignContents::Fixed(contents) => contents
+                .iter()
+                .filter(|c| !user.finished.contains(&c.id))
+                .cloned()
+                .collect(),
+            CampaignContents::Unfinished(cache) => cache.get(unfinished_ids(user)),
+        }
+    }
+
+    /// Whether a user without contents gets no message at all.
+    pub fn requires_contents(&self) -> bool {
+        matches!(self, CampaignContents::Unfinished(_))
+    }
+}
+
+impl ContentCache {
+    pub fn new(metadata: MetadataClient<AuthChannel>) -> Self {
+        Self {
+            metadata,
+            contents: HashMap::new(),
+        }
+    }
+
+    async fn load(&mut self, ids: &[u32]) {
+        let mut missing: Vec<u32> = ids
+            .iter()
+            .filter(|id| !self.contents.contains_key(id))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        if self.contents.len() + missing.len() > MAX_CACHED_CONTENTS {
+            self.contents.clear();
+            missing = ids.to_vec();
+        }
+        let contents = materialize(&mut self.metadata, &missing).await;
+        self.contents
+            .extend(contents.into_iter().map(|c| (c.id, c)));
+    }
+
+    /// Contents of the ids that could be materialized, in order.
+    fn get(&self, ids: &[u32]) -> Vec<Content> {
+        ids.iter()
+            .filter_map(|id| self.contents.get(id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Materialize contents via crm-metadata, nothing on failure.
+pub(crate) async fn materialize(
+    metadata: &mut MetadataClient<AuthChannel>,
+    content_ids: &[u32],
+) -> Vec<Content> {
+    let contents = metadata
+        .materialize(MaterializeRequest::new_with_ids(content_ids))
+        .await;
+
+    match contents {
+        Ok(c) => {
+            let contents: Vec<Content> = c
+                .into_inner()
+                .filter_map(|v| async move { v.ok() })
+                .collect()
+                .await;
+            info!("contents size: {}", contents.len());
+            contents
+        }
+        Err(e) => {
+            warn!("failed to get contents {:?}: {:?}", content_ids, e);
+            vec![]
+        }
+    }
+}
+
+fn unfinished_ids(user: &User) -> &[u32] {
+    let ids = &user.started_but_not_finished;
+    &ids[..ids.len().min(MAX_UNFINISHED_CONTENTS)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crm_common::ServiceToken;
+
+    #[tokio::test]
+    async fn unfinished_contents_should_be_limited_and_ordered() {
+        let channel = ServiceToken::for_test("crm")
+            .connect_lazy("http://localhost:50002", None)
+            .unwrap();
+        let mut cache = ContentCache::new(MetadataClient::new(channel));
+        cache
+            .contents
+            .extend((1..=10).map(|id| (id, Content::materialize(id))));
+        let contents = CampaignContents::Unfinished(Box::new(cache));
+
+        let user = User {
+            started_but_not_finished: vec![9, 42, 3, 1, 2, 5, 6],
+            ..Default::default()
+        };
+        let ids: Vec<u32> = contents.for_user(&user).iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![9, 3, 1, 2]);
+    }
+
+    #[test]
+    fn fixed_contents_should_leave_out_finished() {
+        let contents =
+            CampaignContents::Fixed(Arc::new((1..=3).map(Content::materialize).collect()));
+        let user = User {
+            finished: vec![2, 7],
+            ..Default::default()
+        };
+        let ids: Vec<u32> = contents.for_user(&user).iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+}
@@ -1,143 +1,191 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+};
 
 use chrono::Utc;
-use crm_metadata::pb::{Content, MaterializeRequest};
-use crm_send::pb::SendRequest;
+use crm_metadata::{
+    pb::{ListTemplatesRequest, Template},
+    MessageTemplate, TemplateSet,
+};
+use crm_send::pb::SendStatus;
 use futures::StreamExt;
 use prost_types::Timestamp;
-use tokio::sync::mpsc::{self, Receiver};
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Response, Status, Streaming};
+use tokio_util::sync::CancellationToken;
+use tonic::{Response, Status};
 use tracing::{info, warn};
-use user_stat::pb::{QueryRequest, TimeQuery, User};
 
 use crate::{
     pb::{
-        RecallRequest, RecallResponse, RemindRequest, RemindResponse, WelcomeRequest,
-        WelcomeResponse,
+        campaign_params::Request, CampaignKind, CampaignParams, RecallRequest, RecallResponse,
+        RemindRequest, RemindResponse, Segment, WelcomeRequest, WelcomeResponse,
     },
     CrmService,
 };
 
+use super::{
+    campaign::CampaignStats,
+    contents::{materialize, CampaignContents, ContentCache},
+    dry_run::write_dry_run,
+    pipeline::{build_send_stream, Personalizer},
+    report::RecipientLog,
+};
+
 impl CrmService {
     pub async fn welcome(
         &self,
         request: WelcomeRequest,
     ) -> Result<Response<WelcomeResponse>, Status> {
-        let req_id = request.id;
-
-        let query = self.new_user_stat_query(request.interval, "created_at".to_string());
-        info!("query user stats: {:?}", query);
-        let user_stat_res = self.user_stats.clone().query(query).await?.into_inner();
+        let req_id = request.id.clone();
+        let status = self.submit_campaign(request.into()).await?;
 
-        let contents = self.get_contents(&request.content_ids).await;
-        let rx = self.build_send_stream(user_stat_res, contents.clone(), "Welcome".to_string());
-
-        info!("call notification");
-        let reqs = ReceiverStream::new(rx);
-        self.notification.clone().send(reqs).await?;
-
-        Ok(Response::new(WelcomeResponse { id: req_id }))
+        Ok(Response::new(WelcomeResponse {
+            id: req_id,
+            status: status as i32,
+        }))
     }
 
     pub async fn recall(&self, request: RecallRequest) -> Result<Response<RecallResponse>, Status> {
-        let req_id = request.id;
-
-        let query =
-            self.new_user_stat_query(request.last_visit_interval, "last_visited_at".to_string());
-        info!("query user stats: {:?}", query);
-        let user_stat_res = self.user_stats.clone().query(query).await?.into_inner();
+        let req_id = request.id.clone();
+        let status = self.submit_campaign(request.into()).await?;
 
-        let contents = self.get_contents(&request.content_ids).await;
-        let rx = self.build_send_stream(user_stat_res, contents.clone(), "Recall".to_string());
-
-        let reqs = ReceiverStream::new(rx);
-        self.notification.clone().send(reqs).await?;
-
-        Ok(Response::new(RecallResponse { id: req_id }))
+        Ok(Response::new(RecallResponse {
+            id: req_id,
+            status: status as i32,
+        }))
     }
 
     pub async fn remind(&self, request: RemindRequest) -> Result<Response<RemindResponse>, Status> {
-        let req_id = request.id;
-
-        let query =
-            self.new_user_stat_query(request.last_visit_interval, "last_visited_at".to_string());
-        info!("query user stats: {:?}", query);
-        let user_stat_res = self.user_stats.clone().query(query).await?.into_inner();
-
-        let contents = Arc::new(vec![]);
-        let rx = self.build_send_stream(user_stat_res, contents, "Remind".to_string());
+        let req_id = request.id.clone();
+        let status = self.submit_campaign(request.into()).await?;
 
-        let reqs = ReceiverStream::new(rx);
-        self.notification.clone().send(reqs).await?;
-
-        Ok(Response::new(RemindResponse { id: req_id }))
+        Ok(Response::new(RemindResponse {
+            id: req_id,
+            status: status as i32,
+        }))
     }
 
-    fn build_send_stream(
+    pub(crate) async fn execute_campaign(
         &self,
-        mut user_stat_res: Streaming<User>,
-        contents: Arc<Vec<Content>>,
-        subject: String,
-    ) -> Receiver<SendRequest> {
-        let (tx, rx) = mpsc::channel(1024);
-        let sender = self.config.server.sender_email.clone();
-        tokio::spawn(async move {
-            while let Some(Ok(user)) = user_stat_res.next().await {
-                let contents = contents.clone();
-                let sender = sender.clone();
-                let tx = tx.clone();
-
-                let req = SendRequest::new(subject.clone(), sender, &[user.email], &contents);
-                if let Err(e) = tx.send(req).await {
-                    warn!("Failed to send message: {:?}", e);
-                }
-            }
-        });
-
-        rx
-    }
+        params: &CampaignParams,
+        output: Option<&Path>,
+        stats: Arc<CampaignStats>,
+        cancel: CancellationToken,
+    ) -> Result<(), Status> {
+        let (base, content_ids, emails) = match &params.request {
+            Some(Request::Welcome(req)) => (
+                Segment::days("created_at", Some(req.interval), Some(0)),
+                req.content_ids.clone(),
+                req.emails.clone(),
+            ),
+            Some(Request::Recall(req)) => (
+                Segment::days("last_visited_at", Some(req.last_visit_interval), Some(0)),
+                req.content_ids.clone(),
+                req.emails.clone(),
+            ),
+            Some(Request::Remind(req)) => (
+                Segment::days("last_visited_at", Some(req.last_visit_interval), Some(0)),
+                vec![],
+                req.emails.clone(),
+            ),
+            None => return Err(Status::invalid_argument("campaign request is required")),
+        };
+        let segment = Segment::all([base].into_iter().chain(params.segment().cloned()));
 
-    fn new_user_stat_query(&self, interval: u32, query_key: String) -> QueryRequest {
-        let d1 = to_ts(interval as _);
-        let d2 = to_ts(0);
+        let mut queries = self.compile_segment(&segment).await?;
+        if !emails.is_empty() {
+            queries.iter_mut().for_each(|q| q.emails = emails.clone());
+        }
+        info!("query user stats: {:?}", queries);
+        let user_stat_res = self.query_users(queries).await?;
 
-        let mut timestamps = HashMap::new();
-        let time_query = TimeQuery {
-            lower: Some(d1),
-            upper: Some(d2),
+        let contents = match params.kind() {
+            CampaignKind::Remind => {
+                CampaignContents::Unfinished(Box::new(ContentCache::new(self.metadata())))
+            }
+            _ if content_ids.is_empty() => CampaignContents::Fixed(Arc::new(vec![])),
+            _ => {
+                let contents = materialize(&mut self.metadata(), &content_ids).await;
+                CampaignContents::Fixed(Arc::new(contents))
+            }
+        };
+        let tpl_name = params.kind().template_name();
+        let experiment = self.plan_experiment(params).await;
+        let mut tpl_names = vec![tpl_name];
+        if let Some(plan) = &experiment {
+            tpl_names.extend(plan.template_names());
+        }
+        let templates = self.get_templates(&tpl_names).await;
+        let personalizer = Personalizer {
+            config: self.config().server.clone(),
+            templates,
+            tpl_name: tpl_name.to_string(),
+            category: tpl_name.to_string(),
+            policy: params.channel_policy(),
+            requires_contents: contents.requires_contents(),
+            experiment,
         };
-        timestamps.insert(query_key, time_query);
+        let pool = (!params.dry_run()).then(|| self.pool.clone());
+        let recipients = RecipientLog::new(params.id(), pool);
+        let rx = build_send_stream(
+            user_stat_res,
+            contents,
+            personalizer,
+            recipients,
+            stats.clone(),
+            cancel,
+        );
+
+        if let Some(path) = output {
+            return write_dry_run(rx, path, stats).await;
+        }
 
-        QueryRequest {
-            timestamps,
-            ids: HashMap::new(),
+        info!("call notification");
+        let reqs = ReceiverStream::new(rx);
+        let mut responses = self.notification().send(reqs).await?.into_inner();
+        while let Some(res) = responses.next().await {
+            let res = res?;
+            match res.status() {
+                SendStatus::Sent => {
+                    stats.messages_sent.fetch_add(1, Ordering::Relaxed);
+                }
+                SendStatus::Rejected => {
+                    info!("message {} not sent: {}", res.message_id, res.error);
+                }
+                _ => {
+                    warn!("failed to send message {}: {}", res.message_id, res.error);
+                    stats.messages_failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
         }
+
+        Ok(())
     }
 
-    async fn get_contents(&self, content_ids: &[u32]) -> Arc<Vec<Content>> {
-        let contents = self
-            .metadata
-            .clone()
-            .materialize(MaterializeRequest::new_with_ids(content_ids))
-            .await;
-
-        match contents {
-            Ok(c) => {
-                let contents: Vec<Content> = c
-                    .into_inner()
-                    .filter_map(|v| async move { v.ok() })
-                    .collect()
-                    .await;
-                let contents = Arc::new(contents);
-                info!("contents size: {}", contents.clone().len());
-                contents
-            }
-            Err(_) => {
-                warn!("failed to get contents: {:?}", content_ids);
-                Arc::new(vec![])
+    /// Active templates from crm-metadata on top of the built-in ones.
+    async fn get_templates(&self, names: &[&str]) -> Arc<TemplateSet> {
+        let mut templates = vec![];
+        for name in names {
+            let req = ListTemplatesRequest {
+                name: name.to_string(),
+                active_only: true,
+            };
+            match self.metadata().list_templates(req).await {
+                Ok(res) => {
+                    let found = res
+                        .into_inner()
+                        .filter_map(|v| async move { v.ok() })
+                        .collect::<Vec<Template>>()
+                        .await;
+                    templates.extend(found);
+                }
+                Err(e) => warn!("failed to get templates {}: {:?}", name, e),
             }
         }
+
+        let templates = templates.into_iter().map(MessageTemplate::from);
+        Arc::new(TemplateSet::with_templates(templates))
     }
 }
 
@@ -0,0 +1,171 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+
+use crm_send::pb::{send_request::Msg, SendRequest};
+use serde::Serialize;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc::Receiver,
+};
+use tonic::Status;
+use tracing::{info, warn};
+
+use crate::CrmService;
+
+use super::campaign::CampaignStats;
+
+/// A message rendered by a dry run.
+#[derive(Debug, Serialize)]
+struct DryRunRecord<'a> {
+    message_id: &'a str,
+    channel: &'static str,
+    /// email addresses, phone numbers or the device id the message would go to
+    recipients: Vec<&'a str>,
+    subject: &'a str,
+    body: &'a str,
+}
+
+impl CrmService {
+    /// Where the messages of a dry run are written to.
+    pub(crate) fn dry_run_output(&self, id: &str) -> PathBuf {
+        self.config()
+            .server
+            .dry_run_dir
+            .join(format!("{}.jsonl", file_name(id)))
+    }
+}
+
+/// The id percent-encoded, so that it can't escape the directory and distinct ids get
+/// distinct files.
+fn file_name(id: &str) -> String {
+    let mut name = String::with_capacity(id.len());
+    for b in id.bytes() {
+        match b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            true => name.push(b as char),
+            false => name.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    name
+}
+
+/// Write the messages into the output file instead of sending them.
+pub(crate) async fn write_dry_run(
+    mut rx: Receiver<SendRequest>,
+    path: &Path,
+    stats: Arc<CampaignStats>,
+) -> Result<(), Status> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await.map_err(io_err)?;
+    }
+    let mut writer = BufWriter::new(File::create(path).await.map_err(io_err)?);
+
+    let mut total = 0;
+    while let Some(req) = rx.recv().await {
+        let Some(record) = req.msg.as_ref().map(DryRunRecord::from) else {
+            continue;
+        };
+        let mut line = serde_json::to_vec(&record)
+            .map_err(|e| Status::internal(format!("failed to encode message: {e}")))?;
+        line.push(b'\n');
+        writer.write_all(&line).await.map_err(io_err)?;
+        total += 1;
+    }
+    writer.flush().await.map_err(io_err)?;
+
+    // messages_queued already counts what was rendered; nothing is delivered by a dry run
+    info!(
+        "dry run wrote {} messages to {} ({} users scanned)",
+        total,
+        path.display(),
+        stats.users_matched.load(Ordering::Relaxed)
+    );
+    Ok(())
+}
+
+impl<'a> From<&'a Msg> for DryRunRecord<'a> {
+    fn from(msg: &'a Msg) -> Self {
+        match msg {
+            Msg::Email(email) => DryRunRecord {
+                message_id: &email.message_id,
+                channel: "email",
+                recipients: email.recipients.iter().map(String::as_str).collect(),
+                subject: &email.subject,
+                body: &email.body,
+            },
+            Msg::Sms(sms) => DryRunRecord {
+                message_id: &sms.message_id,
+                channel: "sms",
+                recipients: sms.recipients.iter().map(String::as_str).collect(),
+                subject: "",
+                body: &sms.body,
+            },
+            Msg::InApp(in_app) => DryRunRecord {
+                message_id: &in_app.message_id,
+                channel: "in_app",
+                recipients: vec![&in_app.device_id],
+                subject: &in_app.title,
+                body: &in_app.body,
+            },
+        }
+    }
+}
+
+fn io_err(e: std::io::Error) -> Status {
+    warn!("dry run output error: {:?}", e);
+    Status::internal("failed to write dry run output")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use crm_metadata::Rendered;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn file_name_should_keep_ids_apart() {
+        assert_eq!(file_name("welcome-2024_01"), "welcome-2024_01");
+        assert_eq!(file_name("../a/b"), "%2E%2E%2Fa%2Fb");
+        assert_ne!(file_name("a/b"), file_name("a_b"));
+        assert_ne!(file_name("a%2Fb"), file_name("a/b"));
+    }
+
+    #[tokio::test]
+    async fn dry_run_should_write_messages() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join("crm-dry-run-test")
+            .join(format!("{}.jsonl", uuid::Uuid::new_v4()));
+        let rendered = Rendered {
+            subject: "Hi".to_string(),
+            body: "Hello".to_string(),
+        };
+        let (tx, rx) = mpsc::channel(4);
+        tx.send(SendRequest::new(
+            "crm@acme.org".to_string(),
+            &["alice@acme.org".to_string()],
+            rendered.clone(),
+        ))
+        .await?;
+        tx.send(SendRequest::in_app("d1".to_string(), rendered))
+            .await?;
+        drop(tx);
+
+        write_dry_run(rx, &path, Arc::new(CampaignStats::default())).await?;
+        let output = std::fs::read_to_string(&path)?;
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["channel"], "email");
+        assert_eq!(lines[0]["recipients"][0], "alice@acme.org");
+        assert_eq!(lines[1]["recipients"][0], "d1");
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::{bail, Result};
+use sqlx::{MySql, MySqlPool, QueryBuilder};
+use tracing::warn;
+
+use crate::{
+    pb::{campaign_params::Request, CampaignKind, CampaignParams, Experiment},
+    CrmService,
+};
+
+use super::contents::{materialize, CampaignContents};
+
+/// Variant name recorded for users in the holdout.
+pub const HOLDOUT: &str = "holdout";
+
+/// Variant of a user in a campaign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Assignment {
+    /// the campaign runs no experiment
+    Control,
+    /// the user receives nothing
+    Holdout,
+    /// index of the variant in the experiment
+    Variant(usize),
+}
+
+/// An experiment ready to assign users of a campaign.
+pub(crate) struct ExperimentPlan {
+    campaign_id: String,
+    salt: String,
+    holdout_weight: u64,
+    total_weight: u64,
+    variants: Vec<VariantPlan>,
+    /// where assignments are recorded, `None` for dry runs
+    pool: Option<MySqlPool>,
+}
+
+pub(crate) struct VariantPlan {
+    pub name: String,
+    /// template to render, the template of the campaign if `None`
+    pub tpl_name: Option<String>,
+    /// contents to promote, the contents of the campaign if `None`
+    pub contents: Option<CampaignContents>,
+    weight: u64,
+}
+
+impl Experiment {
+    pub fn validate(&self) -> Result<()> {
+        if self.variants.is_empty() {
+            bail!("experiment needs at least one variant");
+        }
+
+        let mut names = HashSet::new();
+        for v in &self.variants {
+            if v.name.is_empty() || v.name == HOLDOUT {
+                bail!("invalid variant name: {:?}", v.name);
+            }
+            if !names.insert(v.name.as_str()) {
+                bail!("duplicated variant name: {}", v.name);
+            }
+            if v.weight == 0 {
+                bail!("variant {} needs a positive weight", v.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CampaignParams {
+    pub fn experiment(&self) -> Option<&Experiment> {
+        match &self.request {
+            Some(Request::Welcome(req)) => req.experiment.as_ref(),
+            Some(Request::Recall(req)) => req.experiment.as_ref(),
+            Some(Request::Remind(req)) => req.experiment.as_ref(),
+            None => None,
+        }
+    }
+}
+
+impl CrmService {
+    /// Prepare the experiment of a campaign, materializing the contents of its variants.
+    pub(crate) async fn plan_experiment(&self, params: &CampaignParams) -> Option<ExperimentPlan> {
+        let experiment = params.experiment()?;
+
+        let mut variants = Vec::with_capacity(experiment.variants.len());
+        for v in &experiment.variants {
+            // each user is reminded of their own unfinished contents
+            let own_contents = params.kind() == CampaignKind::Remind;
+            let contents = match v.content_ids.is_empty() || own_contents {
+                true => None,
+                false => {
+                    let contents = materialize(&mut self.metadata(), &v.content_ids).await;
+                    Some(CampaignContents::Fixed(Arc::new(contents)))
+                }
+            };
+            variants.push(VariantPlan {
+                name: v.name.clone(),
+                tpl_name: (!v.template.is_empty()).then(|| v.template.clone()),
+                contents,
+                weight: v.weight as u64,
+            });
+        }
+
+        let salt = match experiment.name.is_empty() {
+            true => params.id().to_string(),
+            false => experiment.name.clone(),
+        };
+        let plan = ExperimentPlan::new(params.id(), salt, experiment.holdout_weight, variants)
+            .with_pool((!params.dry_run()).then(|| self.pool.clone()));
+        Some(plan)
+    }
+}
+
+impl ExperimentPlan {
+    fn new(
+        campaign_id: impl Into<String>,
+        salt: impl Into<String>,
+        holdout_weight: u32,
+        variants: Vec<VariantPlan>,
+    ) -> Self {
+        let holdout_weight = holdout_weight as u64;
+        let total_weight = holdout_weight + variants.iter().map(|v| v.weight).sum::<u64>();
+        Self {
+            campaign_id: campaign_id.into(),
+            salt: salt.into(),
+            holdout_weight,
+            total_weight,
+            variants,
+            pool: None,
+        }
+    }
+
+    fn with_pool(mut self, pool: Option<MySqlPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Assign the user to a variant; the same email and salt always get the same variant.
+    pub fn assign(&self, email: &str) -> Assignment {
+        if self.total_weight == 0 {
+            return Assignment::Holdout;
+        }
+
+        let key = format!("{}:{}", self.salt, email.trim().to_lowercase());
+        let mut bucket = fnv1a(key.as_bytes()) % self.total_weight;
+        if bucket < self.holdout_weight {
+            return Assignment::Holdout;
+        }
+        bucket -= self.holdout_weight;
+
+        for (i, v) in self.variants.iter().enumerate() {
+            if bucket < v.weight {
+                return Assignment::Variant(i);
+            }
+            bucket -= v.weight;
+        }
+        unreachable!("bucket is less than the total weight")
+    }
+
+    pub fn variant(&self, assignment: Assignment) -> Option<&VariantPlan> {
+        match assignment {
+            Assignment::Variant(i) => self.variants.get(i),
+            _ => None,
+        }
+    }
+
+    /// Names of the templates the variants render.
+    pub fn template_names(&self) -> impl Iterator<Item = &str> {
+        self.variants.iter().filter_map(|v| v.tpl_name.as_deref())
+    }
+
+    /// Persist the assignments of a batch of users.
+    pub async fn record(&self, assignments: &[(&str, Assignment)]) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        if assignments.is_empty() {
+            return;
+        }
+
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+            "INSERT IGNORE INTO experiment_assignments(campaign_id, email, variant) ",
+        );
+        builder.push_values(assignments, |mut b, (email, assignment)| {
+            let variant = match self.variant(*assignment) {
+                Some(v) => v.name.as_str(),
+                None => HOLDOUT,
+            };
+            b.push_bind(&self.campaign_id)
+                .push_bind(*email)
+                .push_bind(variant);
+        });
+        if let Err(e) = builder.build().execute(pool).await {
+            warn!(
+                "failed to record assignments of campaign {}: {:?}",
+                self.campaign_id, e
+            );
+        }
+    }
+}
+
+/// 64-bit FNV-1a, stable across processes and releases unlike the std hasher.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes
+        .iter()
+        .fold(OFFSET, |hash, b| (hash ^ *b as u64).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::Variant;
+
+    fn variant(name: &str, weight: u64) -> VariantPlan {
+        VariantPlan {
+            name: name.to_string(),
+            tpl_name: None,
+            contents: None,
+            weight,
+        }
+    }
+
+    #[test]
+    fn fnv1a_should_match_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn assign_should_be_deterministic_and_weighted() {
+        let plan = ExperimentPlan::new(
+            "c1",
+            "recall-2024",
+            20,
+            vec![variant("a", 40), variant("b", 40)],
+        );
+
+        let a = plan.assign("alice@acme.org");
+        assert_eq!(plan.assign("Alice@acme.org "), a);
+
+        let mut counts = [0; 3];
+        for i in 0..10000 {
+            match plan.assign(&format!("user{i}@acme.org")) {
+                Assignment::Holdout => counts[0] += 1,
+                Assignment::Variant(i) => counts[i + 1] += 1,
+                Assignment::Control => unreachable!(),
+            }
+        }
+        assert!((1800..2200).contains(&counts[0]), "{counts:?}");
+        assert!((3800..4200).contains(&counts[1]), "{counts:?}");
+        assert!((3800..4200).contains(&counts[2]), "{counts:?}");
+    }
+
+    #[test]
+    fn experiment_validate_should_reject_bad_variants() {
+        let v = |name: &str, weight| Variant {
+            name: name.to_string(),
+            weight,
+            ..Default::default()
+        };
+        let experiment = |variants| Experiment {
+            variants,
+            ..Default::default()
+        };
+
+        assert!(experiment(vec![v("a", 1), v("b", 2)]).validate().is_ok());
+        assert!(experiment(vec![]).validate().is_err());
+        assert!(experiment(vec![v("a", 1), v("a", 1)]).validate().is_err());
+        assert!(experiment(vec![v(HOLDOUT, 1)]).validate().is_err());
+        assert!(experiment(vec![v("a", 0)]).validate().is_err());
+    }
+}
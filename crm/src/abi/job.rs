@@ -0,0 +1,189 @@
+use std::{future, path::PathBuf, sync::Arc, time::Duration};
+
+use futures::StreamExt;
+use tokio::sync::watch;
+use tokio_stream::wrappers::WatchStream;
+use tokio_util::sync::CancellationToken;
+use tonic::{Response, Status};
+use tracing::{info, warn};
+
+use crate::{
+    pb::{
+        CampaignParams, CampaignProgress, CampaignStatus, CancelCampaignRequest,
+        GetCampaignRequest, WatchCampaignRequest,
+    },
+    CrmService, ProgressStream,
+};
+
+use super::campaign::CampaignStats;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A campaign running in background.
+pub(crate) struct CampaignJob {
+    progress: watch::Sender<CampaignProgress>,
+    cancel: CancellationToken,
+}
+
+impl CrmService {
+    /// Record the campaign and run it in background.
+    pub(crate) async fn submit_campaign(
+        &self,
+        params: CampaignParams,
+    ) -> Result<CampaignStatus, Status> {
+        // the dry run directory may be reloaded meanwhile, the recorded file is the one written
+        let output = params.dry_run().then(|| self.dry_run_output(params.id()));
+        self.create_campaign(&params, output.as_deref()).await?;
+
+        let id = params.id().to_string();
+        let stats = Arc::new(CampaignStats::default());
+        let (progress, _) = watch::channel(stats.progress(&id, CampaignStatus::Accepted));
+        let cancel = CancellationToken::new();
+        let job = CampaignJob {
+            progress: progress.clone(),
+            cancel: cancel.clone(),
+        };
+        self.jobs.lock().unwrap().insert(id.clone(), job);
+
+        let svc = self.clone();
+        tokio::spawn(async move {
+            svc.run_campaign(params, output, stats, progress, cancel)
+                .await;
+            svc.jobs.lock().unwrap().remove(&id);
+        });
+
+        Ok(CampaignStatus::Accepted)
+    }
+
+    pub async fn watch_campaign(
+        &self,
+        request: WatchCampaignRequest,
+    ) -> Result<Response<ProgressStream>, Status> {
+        let rx = self
+            .jobs
+            .lock()
+            .unwrap()
+            .get(&request.id)
+            .map(|job| job.progress.subscribe());
+
+        let Some(rx) = rx else {
+            // not running here, the stored record is all there is to report
+            let campaign = self
+                .get_campaign(GetCampaignRequest { id: request.id })
+                .await?
+                .into_inner();
+            let progress = CampaignProgress::from(campaign);
+            return Ok(Response::new(Box::pin(futures::stream::iter([Ok(
+                progress,
+            )]))));
+        };
+
+        let stream = WatchStream::new(rx).scan(false, |done, progress| {
+            if *done {
+                return future::ready(None);
+            }
+            *done = progress.status().is_terminal();
+            future::ready(Some(Ok(progress)))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// Ask a running campaign to stop. Messages already handed over to crm-send are still
+    /// delivered; watch the campaign to see its final counters.
+    pub async fn cancel_campaign(
+        &self,
+        request: CancelCampaignRequest,
+    ) -> Result<Response<CampaignProgress>, Status> {
+        let progress = self.jobs.lock().unwrap().get(&request.id).map(|job| {
+            job.cancel.cancel();
+            job.progress.borrow().clone()
+        });
+
+        match progress {
+            Some(progress) => {
+                info!("campaign {} cancelled", request.id);
+                Ok(Response::new(progress))
+            }
+            None => {
+                let campaign = self
+                    .get_campaign(GetCampaignRequest {
+                        id: request.id.clone(),
+                    })
+                    .await?
+                    .into_inner();
+                Err(Status::failed_precondition(format!(
+                    "campaign {} is not running: {}",
+                    request.id,
+                    campaign.status().as_str_name()
+                )))
+            }
+        }
+    }
+
+    async fn run_campaign(
+        &self,
+        params: CampaignParams,
+        output: Option<PathBuf>,
+        stats: Arc<CampaignStats>,
+        progress: watch::Sender<CampaignProgress>,
+        cancel: CancellationToken,
+    ) {
+        let id = params.id();
+        let ret = match self.start_campaign(id).await {
+            Ok(()) => {
+                progress.send_replace(stats.progress(id, CampaignStatus::Running));
+                let reporter = report_progress(id, stats.clone(), progress.clone());
+                let ret = self
+                    .execute_campaign(&params, output.as_deref(), stats.clone(), cancel.clone())
+                    .await;
+                reporter.abort();
+                ret
+            }
+            Err(e) => Err(e),
+        };
+
+        let status = match &ret {
+            Err(e) => {
+                warn!("campaign {} failed: {:?}", id, e);
+                CampaignStatus::Failed
+            }
+            Ok(()) if cancel.is_cancelled() => CampaignStatus::Cancelled,
+            Ok(()) => CampaignStatus::Completed,
+        };
+        if let Err(e) = self
+            .finish_campaign(id, &stats, status, ret.as_ref().err())
+            .await
+        {
+            warn!("failed to record result of campaign {}: {:?}", id, e);
+        }
+
+        let mut last = stats.progress(id, status);
+        if let Err(e) = ret {
+            last.error = e.message().to_string();
+        }
+        progress.send_replace(last);
+    }
+}
+
+/// Publish the counters of a running campaign periodically, until aborted.
+fn report_progress(
+    id: &str,
+    stats: Arc<CampaignStats>,
+    progress: watch::Sender<CampaignProgress>,
+) -> tokio::task::JoinHandle<()> {
+    let id = id.to_string();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let next = stats.progress(&id, CampaignStatus::Running);
+            progress.send_if_modified(|cur| {
+                if *cur == next {
+                    return false;
+                }
+                *cur = next;
+                true
+            });
+        }
+    })
+}
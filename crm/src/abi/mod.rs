@@ -1,2 +1,15 @@
+mod campaign;
+mod channel;
+mod contents;
 mod crm;
+mod dry_run;
+mod experiment;
+mod job;
+mod pipeline;
+mod report;
+mod schedule;
+mod segment;
+mod trigger;
 mod user;
+
+pub(crate) use job::CampaignJob;
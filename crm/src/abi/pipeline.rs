@@ -0,0 +1,282 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use crm_metadata::{pb::Content, TemplateSet, Tpl};
+use crm_send::pb::SendRequest;
+use futures::{stream::BoxStream, StreamExt};
+use tokio::sync::mpsc::{self, Receiver};
+use tokio_util::sync::CancellationToken;
+use tonic::Status;
+use tracing::warn;
+use user_stat::pb::User;
+
+use crate::{pb::ChannelPolicy, ServerConfig};
+
+use super::{
+    campaign::CampaignStats,
+    contents::CampaignContents,
+    experiment::{Assignment, ExperimentPlan},
+    report::{Recipient, RecipientLog},
+};
+
+/// Users read from user-stat at most before preparing their contents.
+const USER_BATCH_SIZE: usize = 256;
+
+/// What it takes to turn a matched user into the messages to send.
+pub(crate) struct Personalizer {
+    pub config: ServerConfig,
+    pub templates: Arc<TemplateSet>,
+    pub tpl_name: String,
+    /// category recipients consent to, crm-send drops those who opted out of it
+    pub category: String,
+    pub policy: ChannelPolicy,
+    /// users without contents get no message
+    pub requires_contents: bool,
+    /// variants and holdout the users are split into
+    pub experiment: Option<ExperimentPlan>,
+}
+
+/// Outcome of personalising the message of a user.
+#[derive(Debug)]
+pub(crate) enum Personalized {
+    /// one message per selected channel
+    Messages(Vec<SendRequest>),
+    /// the user has nothing to be messaged about
+    Skipped,
+    /// the user can't be messaged
+    Failed,
+}
+
+impl Personalizer {
+    /// Name of the variant recorded for the recipients, empty without experiment.
+    fn variant_name(&self, assignment: Assignment) -> String {
+        let variant = self.experiment.as_ref().and_then(|p| p.variant(assignment));
+        variant.map(|v| v.name.clone()).unwrap_or_default()
+    }
+
+    fn assign(&self, user: &User) -> Assignment {
+        match &self.experiment {
+            Some(plan) => plan.assign(&user.email),
+            None => Assignment::Control,
+        }
+    }
+
+    /// Contents of the variant of the user, the campaign's own if the variant has none.
+    fn contents_for(
+        &self,
+        user: &User,
+        assignment: Assignment,
+        contents: &CampaignContents,
+    ) -> Vec<Content> {
+        let variant = self.experiment.as_ref().and_then(|p| p.variant(assignment));
+        match variant.and_then(|v| v.contents.as_ref()) {
+            Some(contents) => contents.for_user(user),
+            None => contents.for_user(user),
+        }
+    }
+
+    fn personalize(
+        &self,
+        user: &User,
+        contents: &[Content],
+        assignment: Assignment,
+    ) -> Personalized {
+        if assignment == Assignment::Holdout {
+            return Personalized::Skipped;
+        }
+        if contents.is_empty() && self.requires_contents {
+            return Personalized::Skipped;
+        }
+
+        let channels = self.policy.select(user);
+        if channels.is_empty() {
+            warn!("user {} is not reachable on any channel", user.email);
+            return Personalized::Failed;
+        }
+
+        let tpl_name = self
+            .experiment
+            .as_ref()
+            .and_then(|p| p.variant(assignment))
+            .and_then(|v| v.tpl_name.as_deref())
+            .unwrap_or(&self.tpl_name);
+        let Some(tpl) = self.templates.lookup(tpl_name, &user.locale) else {
+            warn!("template {} not found for locale {}", tpl_name, user.locale);
+            return Personalized::Failed;
+        };
+        let rendered = match Tpl::new(tpl, &user.locale, contents)
+            .with_name(&user.name)
+            .render()
+        {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Failed to render template {}: {:?}", tpl_name, e);
+                return Personalized::Failed;
+            }
+        };
+
+        let reqs = channels
+            .iter()
+            .filter_map(|c| c.build_request(&self.config, user, rendered.clone()))
+            .map(|req| req.with_category(&self.category))
+            .collect();
+        Personalized::Messages(reqs)
+    }
+}
+
+/// Personalise the messages of the users, up to `config.concurrency` users at a time, and
+/// feed them to the returned channel.
+pub(crate) fn build_send_stream(
+    user_stat_res: BoxStream<'static, Result<User, Status>>,
+    mut contents: CampaignContents,
+    personalizer: Personalizer,
+    recipients: RecipientLog,
+    stats: Arc<CampaignStats>,
+    cancel: CancellationToken,
+) -> Receiver<SendRequest> {
+    let (tx, rx) = mpsc::channel(1024);
+    let concurrency = personalizer.config.concurrency.max(1);
+    let personalizer = Arc::new(personalizer);
+    tokio::spawn(async move {
+        let mut batches = user_stat_res.ready_chunks(USER_BATCH_SIZE);
+        loop {
+            // stop feeding crm-send once cancelled, the queued messages still go out
+            let batch = tokio::select! {
+                _ = cancel.cancelled() => break,
+                batch = batches.next() => batch,
+            };
+            let Some(batch) = batch else {
+                break;
+            };
+            let size = batch.len();
+            let users: Vec<User> = batch.into_iter().map_while(Result::ok).collect();
+            let broken = users.len() < size;
+            stats
+                .users_matched
+                .fetch_add(users.len() as u64, Ordering::Relaxed);
+
+            let assignments: Vec<Assignment> =
+                users.iter().map(|u| personalizer.assign(u)).collect();
+            if let Some(plan) = &personalizer.experiment {
+                let recorded: Vec<_> = users
+                    .iter()
+                    .zip(&assignments)
+                    .map(|(u, a)| (u.email.as_str(), *a))
+                    .collect();
+                plan.record(&recorded).await;
+            }
+
+            contents.prepare(&users).await;
+            let mut results = futures::stream::iter(users.into_iter().zip(assignments))
+                .map(|(user, assignment)| {
+                    let personalizer = personalizer.clone();
+                    let contents = personalizer.contents_for(&user, assignment, &contents);
+                    tokio::spawn(async move {
+                        let ret = personalizer.personalize(&user, &contents, assignment);
+                        let recipient = Recipient {
+                            email: user.email,
+                            variant: personalizer.variant_name(assignment),
+                            content_ids: contents.iter().map(|c| c.id).collect(),
+                        };
+                        (recipient, ret)
+                    })
+                })
+                .buffer_unordered(concurrency);
+
+            let mut messaged = vec![];
+            let mut closed = false;
+            while let Some(ret) = results.next().await {
+                let (recipient, reqs) = match ret {
+                    Ok((recipient, Personalized::Messages(reqs))) => (recipient, reqs),
+                    Ok((_, Personalized::Skipped)) => continue,
+                    Ok((_, Personalized::Failed)) => {
+                        stats.messages_failed.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("failed to personalise message: {:?}", e);
+                        stats.messages_failed.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                // the receiver only goes away when the send stream is dropped, nothing after
+                // this message can be delivered either
+                for req in reqs {
+                    if let Err(e) = tx.send(req).await {
+                        warn!("Failed to send message: {:?}", e);
+                        closed = true;
+                        break;
+                    }
+                    stats.messages_queued.fetch_add(1, Ordering::Relaxed);
+                }
+                if closed {
+                    break;
+                }
+                messaged.push(recipient);
+            }
+
+            recipients.record(&messaged).await;
+
+            if closed {
+                break;
+            }
+            if broken {
+                warn!("user stream from user-stat broke off");
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use crm_send::pb::send_request::Msg;
+
+    use super::*;
+    use crate::AppConfig;
+
+    fn personalizer(requires_contents: bool) -> anyhow::Result<Personalizer> {
+        Ok(Personalizer {
+            config: AppConfig::for_test()?.server,
+            templates: Arc::new(TemplateSet::builtin()),
+            tpl_name: "remind".to_string(),
+            category: "remind".to_string(),
+            policy: ChannelPolicy::default(),
+            requires_contents,
+            experiment: None,
+        })
+    }
+
+    #[test]
+    fn personalize_should_address_user() -> anyhow::Result<()> {
+        let user = User {
+            email: "alice@acme.org".to_string(),
+            name: "Alice".to_string(),
+            locale: "en".to_string(),
+            ..Default::default()
+        };
+        let contents = vec![Content::materialize(1)];
+
+        let Personalized::Messages(reqs) =
+            personalizer(true)?.personalize(&user, &contents, Assignment::Control)
+        else {
+            panic!("expect messages");
+        };
+        let Some(Msg::Email(email)) = &reqs[0].msg else {
+            panic!("expect an email");
+        };
+        assert_eq!(email.recipients, vec!["alice@acme.org"]);
+        assert!(email.body.starts_with("Hi Alice, you still have"));
+        assert_eq!(reqs[0].category, "remind");
+
+        let ret = personalizer(true)?.personalize(&user, &[], Assignment::Control);
+        assert!(matches!(ret, Personalized::Skipped));
+        let ret = personalizer(false)?.personalize(&user, &contents, Assignment::Holdout);
+        assert!(matches!(ret, Personalized::Skipped));
+        let user = User::default();
+        let ret = personalizer(false)?.personalize(&user, &contents, Assignment::Control);
+        assert!(matches!(ret, Personalized::Failed));
+        Ok(())
+    }
+}
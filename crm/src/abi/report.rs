@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::Utc;
+use futures::StreamExt;
+use itertools::Itertools;
+use prost_types::Timestamp;
+use sqlx::{FromRow, MySql, MySqlPool, QueryBuilder};
+use tonic::{Response, Status};
+use tracing::warn;
+use user_stat::pb::{QueryRequest, User};
+
+use crate::{
+    pb::{
+        CampaignReport, CampaignReportRequest, ContentConversion, GetCampaignRequest, GroupReport,
+    },
+    CrmService,
+};
+
+use super::{campaign::db_err, experiment::HOLDOUT};
+
+/// Emails asked to user-stat in one query at most.
+const ACTIVITY_BATCH_SIZE: usize = 500;
+
+/// A user messaged by a campaign.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Recipient {
+    pub email: String,
+    /// experiment variant, empty without experiment
+    pub variant: String,
+    pub content_ids: Vec<u32>,
+}
+
+/// Where the recipients of a campaign are recorded for its report.
+pub(crate) struct RecipientLog {
+    campaign_id: String,
+    /// `None` for dry runs
+    pool: Option<MySqlPool>,
+}
+
+#[derive(Debug, FromRow)]
+struct RecipientRow {
+    email: String,
+    variant: String,
+    content_ids: Option<String>,
+}
+
+/// Seconds since epoch activity is attributed to the campaign within, inclusive.
+///
+/// user-stat only keeps the last visit and watch of a user, which a later visit overwrites, so
+/// any activity from `start` on counts: `end` is when the activity was read, not a cut-off.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    start: i64,
+    end: i64,
+}
+
+impl CrmService {
+    pub async fn get_campaign_report(
+        &self,
+        request: CampaignReportRequest,
+    ) -> Result<Response<CampaignReport>, Status> {
+        let campaign = self
+            .get_campaign(GetCampaignRequest {
+                id: request.id.clone(),
+            })
+            .await?
+            .into_inner();
+        let start = campaign.started_at.unwrap_or_default().seconds;
+
+        let recipients: Vec<Recipient> = sqlx::query_as::<_, RecipientRow>(
+            "SELECT email, variant, content_ids FROM campaign_recipients WHERE campaign_id = ?",
+        )
+        .bind(&request.id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_err)?
+        .into_iter()
+        .map(Recipient::from)
+        .collect();
+        let holdout: Vec<String> = sqlx::query_scalar(
+            "SELECT email FROM experiment_assignments WHERE campaign_id = ? AND variant = ?",
+        )
+        .bind(&request.id)
+        .bind(HOLDOUT)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        let emails: Vec<&str> = recipients
+            .iter()
+            .map(|r| r.email.as_str())
+            .chain(holdout.iter().map(String::as_str))
+            .collect();
+        let activity = self.fetch_activity(&emails).await?;
+        let window = Window {
+            start,
+            end: Utc::now().timestamp(),
+        };
+
+        let report = compute_report(&request.id, window, &recipients, &holdout, &activity);
+        Ok(Response::new(report))
+    }
+
+    /// Visits and watches of the users from user-stat, by email.
+    async fn fetch_activity(&self, emails: &[&str]) -> Result<HashMap<String, User>, Status> {
+        let mut activity = HashMap::new();
+        for chunk in emails.chunks(ACTIVITY_BATCH_SIZE) {
+            let query = QueryRequest {
+                emails: chunk.iter().map(|v| v.to_string()).collect(),
+                ..Default::default()
+            };
+            let mut users = self.user_stats().query(query).await?.into_inner();
+            while let Some(user) = users.next().await {
+                let user = user?;
+                activity.insert(user.email.clone(), user);
+            }
+        }
+        Ok(activity)
+    }
+}
+
+impl RecipientLog {
+    pub fn new(campaign_id: impl Into<String>, pool: Option<MySqlPool>) -> Self {
+        Self {
+            campaign_id: campaign_id.into(),
+            pool,
+        }
+    }
+
+    pub async fn record(&self, recipients: &[Recipient]) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        if recipients.is_empty() {
+            return;
+        }
+
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+            "INSERT IGNORE INTO campaign_recipients(campaign_id, email, variant, content_ids) ",
+        );
+        builder.push_values(recipients, |mut b, r| {
+            b.push_bind(&self.campaign_id)
+                .push_bind(&r.email)
+                .push_bind(&r.variant)
+                .push_bind(r.content_ids.iter().join(","));
+        });
+        if let Err(e) = builder.build().execute(pool).await {
+            warn!(
+                "failed to record recipients of campaign {}: {:?}",
+                self.campaign_id, e
+            );
+        }
+    }
+}
+
+impl From<RecipientRow> for Recipient {
+    fn from(row: RecipientRow) -> Self {
+        let content_ids = row
+            .content_ids
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect();
+        Self {
+            email: row.email,
+            variant: row.variant,
+            content_ids,
+        }
+    }
+}
+
+impl Window {
+    fn contains(&self, ts: Option<&Timestamp>) -> bool {
+        ts.is_some_and(|ts| ts.seconds >= self.start)
+    }
+
+    fn visited(&self, user: Option<&User>) -> bool {
+        user.is_some_and(|u| self.contains(u.last_visited_at.as_ref()))
+    }
+
+    fn watched(&self, user: Option<&User>) -> bool {
+        user.is_some_and(|u| self.contains(u.last_watched_at.as_ref()))
+    }
+
+    fn watched_content(&self, user: Option<&User>, id: u32) -> bool {
+        self.watched(user) && user.is_some_and(|u| u.recent_watched.contains(&id))
+    }
+}
+
+fn compute_report(
+    id: &str,
+    window: Window,
+    recipients: &[Recipient],
+    holdout: &[String],
+    activity: &HashMap<String, User>,
+) -> CampaignReport {
+    let group = |variant: &str, emails: &mut dyn Iterator<Item = &str>| {
+        let mut report = GroupReport {
+            variant: variant.to_string(),
+            ..Default::default()
+        };
+        for email in emails {
+            let user = activity.get(email);
+            report.users += 1;
+            report.visited += window.visited(user) as u64;
+            report.watched += window.watched(user) as u64;
+        }
+        report.visit_rate = rate(report.visited, report.users);
+        report.watch_rate = rate(report.watched, report.users);
+        report
+    };
+
+    let holdout_report = group(HOLDOUT, &mut holdout.iter().map(String::as_str));
+    let with_lift = |mut report: GroupReport| {
+        if holdout_report.users > 0 {
+            report.visit_lift = lift(report.visit_rate, holdout_report.visit_rate);
+            report.watch_lift = lift(report.watch_rate, holdout_report.watch_rate);
+        }
+        report
+    };
+
+    let all = with_lift(group("", &mut recipients.iter().map(|r| r.email.as_str())));
+
+    let mut groups = vec![];
+    let by_variant = recipients.iter().into_group_map_by(|r| r.variant.as_str());
+    if by_variant.keys().any(|v| !v.is_empty()) || !holdout.is_empty() {
+        for (variant, members) in by_variant.into_iter().sorted_by_key(|(v, _)| *v) {
+            let report = group(variant, &mut members.iter().map(|r| r.email.as_str()));
+            groups.push(with_lift(report));
+        }
+        if !holdout.is_empty() {
+            groups.push(holdout_report.clone());
+        }
+    }
+
+    let mut contents: BTreeMap<u32, ContentConversion> = BTreeMap::new();
+    for r in recipients {
+        let user = activity.get(&r.email);
+        for id in &r.content_ids {
+            let c = contents.entry(*id).or_insert_with(|| ContentConversion {
+                content_id: *id,
+                ..Default::default()
+            });
+            c.recipients += 1;
+            c.watched += window.watched_content(user, *id) as u64;
+        }
+    }
+    let contents = contents
+        .into_values()
+        .map(|mut c| {
+            c.watch_rate = rate(c.watched, c.recipients);
+            c
+        })
+        .collect();
+
+    CampaignReport {
+        id: id.to_string(),
+        window_start: Some(Timestamp {
+            seconds: window.start,
+            nanos: 0,
+        }),
+        window_end: Some(Timestamp {
+            seconds: window.end,
+            nanos: 0,
+        }),
+        recipients: Some(all),
+        groups,
+        contents,
+    }
+}
+
+fn rate(n: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => n as f64 / total as f64,
+    }
+}
+
+fn lift(rate: f64, baseline: f64) -> f64 {
+    if baseline == 0.0 {
+        return 0.0;
+    }
+    (rate - baseline) / baseline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(seconds: i64) -> Option<Timestamp> {
+        Some(Timestamp { seconds, nanos: 0 })
+    }
+
+    fn user(email: &str, visited: i64, watched: i64, recent_watched: &[u32]) -> (String, User) {
+        let user = User {
+            email: email.to_string(),
+            last_visited_at: ts(visited),
+            last_watched_at: ts(watched),
+            recent_watched: recent_watched.to_vec(),
+            ..Default::default()
+        };
+        (email.to_string(), user)
+    }
+
+    fn recipient(email: &str, variant: &str, content_ids: &[u32]) -> Recipient {
+        Recipient {
+            email: email.to_string(),
+            variant: variant.to_string(),
+            content_ids: content_ids.to_vec(),
+        }
+    }
+
+    #[test]
+    fn report_should_attribute_activity_since_start() {
+        let window = Window {
+            start: 100,
+            end: 200,
+        };
+        let recipients = vec![
+            recipient("a", "v1", &[1, 2]),
+            recipient("b", "v1", &[1]),
+            recipient("c", "v2", &[2]),
+            recipient("d", "v2", &[2]),
+        ];
+        let holdout = vec![
+            "h1".to_string(),
+            "h2".to_string(),
+            "h3".to_string(),
+            "h4".to_string(),
+        ];
+        let activity: HashMap<_, _> = [
+            user("a", 150, 150, &[2]),
+            // active before the campaign only
+            user("b", 50, 50, &[1]),
+            user("c", 120, 0, &[]),
+            // last visited after the report was computed, which still counts
+            user("d", 300, 0, &[]),
+            user("h1", 150, 0, &[]),
+        ]
+        .into_iter()
+        .collect();
+
+        let report = compute_report("c1", window, &recipients, &holdout, &activity);
+
+        let all = report.recipients.unwrap();
+        assert_eq!((all.users, all.visited, all.watched), (4, 3, 1));
+        assert_eq!(all.visit_rate, 0.75);
+        assert_eq!(all.visit_lift, 2.0);
+        assert_eq!(all.watch_lift, 0.0);
+
+        let variants: Vec<_> = report.groups.iter().map(|g| g.variant.as_str()).collect();
+        assert_eq!(variants, vec!["v1", "v2", HOLDOUT]);
+        assert_eq!(report.groups[2].visit_rate, 0.25);
+
+        let contents: Vec<_> = report
+            .contents
+            .iter()
+            .map(|c| (c.content_id, c.recipients, c.watched))
+            .collect();
+        assert_eq!(contents, vec![(1, 2, 0), (2, 3, 1)]);
+    }
+}
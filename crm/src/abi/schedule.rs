@@ -0,0 +1,429 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use prost::Message;
+use sqlx::FromRow;
+use tonic::{Response, Status};
+use tracing::{info, warn};
+
+use crate::{
+    pb::{
+        create_schedule_request, schedule::Trigger, CampaignParams, CreateScheduleRequest,
+        ListSchedulesRequest, PauseScheduleRequest, ResumeScheduleRequest, Schedule,
+        ScheduleStatus,
+    },
+    CrmService, ScheduleStream,
+};
+
+use super::{
+    campaign::{db_err, dt_to_ts, MAX_CAMPAIGN_ID_LEN},
+    trigger::{truncate, MAX_ERROR_LEN},
+};
+
+const SCHEDULER_TICK: Duration = Duration::from_secs(10);
+/// Suffix of the campaigns of a schedule, the time of the run.
+const RUN_ID_FORMAT: &str = "%Y%m%d%H%M%S";
+/// Longest schedule id, leaving room in the campaign id for `-` and the time of the run.
+const MAX_SCHEDULE_ID_LEN: usize = MAX_CAMPAIGN_ID_LEN - 15;
+const SCHEDULE_COLUMNS: &str =
+    "id, params, cron, `interval`, status, next_run_at, last_run_at, last_campaign_id, last_error, created_at";
+
+#[derive(Debug, FromRow)]
+struct ScheduleRow {
+    id: String,
+    params: Vec<u8>,
+    cron: Option<String>,
+    interval: Option<u32>,
+    status: i32,
+    next_run_at: DateTime<Utc>,
+    last_run_at: Option<DateTime<Utc>>,
+    last_campaign_id: Option<String>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl CrmService {
+    pub async fn create_schedule(
+        &self,
+        request: CreateScheduleRequest,
+    ) -> Result<Response<Schedule>, Status> {
+        if request.id.is_empty() {
+            return Err(Status::invalid_argument("schedule id is required"));
+        }
+        if request.id.len() > MAX_SCHEDULE_ID_LEN {
+            return Err(Status::invalid_argument(format!(
+                "schedule id is longer than {} bytes",
+                MAX_SCHEDULE_ID_LEN
+            )));
+        }
+        let Some(params) = request.params.filter(|v| v.request.is_some()) else {
+            return Err(Status::invalid_argument("campaign params are required"));
+        };
+        self.check_params(&params).await?;
+        let Some(trigger) = request.trigger.map(Trigger::from) else {
+            return Err(Status::invalid_argument("cron or interval is required"));
+        };
+        let next_run_at = trigger.next_after(Utc::now()).map_err(invalid_trigger)?;
+        let (cron, interval) = match &trigger {
+            Trigger::Cron(v) => (Some(v.as_str()), None),
+            Trigger::Interval(v) => (None, Some(*v)),
+        };
+
+        sqlx::query(
+            "INSERT INTO schedules(id, params, cron, `interval`, status, next_run_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&request.id)
+        .bind(params.encode_to_vec())
+        .bind(cron)
+        .bind(interval)
+        .bind(ScheduleStatus::Active as i32)
+        .bind(next_run_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(err) if err.is_unique_violation() => {
+                Status::already_exists(format!("schedule {} already exists", request.id))
+            }
+            _ => db_err(e),
+        })?;
+
+        self.get_schedule(&request.id).await
+    }
+
+    pub async fn pause_schedule(
+        &self,
+        request: PauseScheduleRequest,
+    ) -> Result<Response<Schedule>, Status> {
+        sqlx::query("UPDATE schedules SET status = ? WHERE id = ?")
+            .bind(ScheduleStatus::Paused as i32)
+            .bind(&request.id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        self.get_schedule(&request.id).await
+    }
+
+    /// Resume a paused schedule; runs missed while paused are not caught up.
+    pub async fn resume_schedule(
+        &self,
+        request: ResumeScheduleRequest,
+    ) -> Result<Response<Schedule>, Status> {
+        let row = self
+            .find_schedule(&request.id)
+            .await?
+            .ok_or_else(|| schedule_not_found(&request.id))?;
+        let next_run_at = row.next_after(Utc::now()).map_err(invalid_trigger)?;
+
+        sqlx::query("UPDATE schedules SET status = ?, next_run_at = ? WHERE id = ?")
+            .bind(ScheduleStatus::Active as i32)
+            .bind(next_run_at)
+            .bind(&request.id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        self.get_schedule(&request.id).await
+    }
+
+    pub async fn list_schedules(
+        &self,
+        request: ListSchedulesRequest,
+    ) -> Result<Response<ScheduleStream>, Status> {
+        let sql = format!(
+            "SELECT {SCHEDULE_COLUMNS} FROM schedules WHERE (? OR status = ?) ORDER BY created_at"
+        );
+        let rows = sqlx::query_as::<_, ScheduleRow>(&sql)
+            .bind(request.include_paused)
+            .bind(ScheduleStatus::Active as i32)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        let schedules = rows.into_iter().map(Schedule::from).map(Ok);
+        Ok(Response::new(Box::pin(futures::stream::iter(schedules))))
+    }
+
+    /// Check for due schedules and triggered actions periodically and run them in background.
+    pub(crate) fn start_scheduler(&self) {
+        let svc = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCHEDULER_TICK);
+            loop {
+                interval.tick().await;
+                if let Err(e) = svc.run_due_schedules().await {
+                    warn!("failed to run due schedules: {:?}", e);
+                }
+                if let Err(e) = svc.run_due_actions().await {
+                    warn!("failed to run due triggered actions: {:?}", e);
+                }
+            }
+        });
+    }
+
+    async fn run_due_schedules(&self) -> Result<(), Status> {
+        let now = Utc::now();
+        let sql = format!(
+            "SELECT {SCHEDULE_COLUMNS} FROM schedules WHERE status = ? AND next_run_at <= ?"
+        );
+        let rows = sqlx::query_as::<_, ScheduleRow>(&sql)
+            .bind(ScheduleStatus::Active as i32)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        for row in rows {
+            let id = row.id.clone();
+            if let Err(e) = self.run_schedule(row, now).await {
+                warn!("failed to run schedule {}: {:?}", id, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn run_schedule(&self, row: ScheduleRow, now: DateTime<Utc>) -> Result<(), Status> {
+        let next_run_at = row.next_after(now).map_err(invalid_trigger)?;
+
+        // a run is skipped rather than stacked while the previous one is still going
+        let overlapping = match &row.last_campaign_id {
+            Some(id) => self
+                .campaign_status(id)
+                .await?
+                .is_some_and(|status| !status.is_terminal()),
+            None => false,
+        };
+        let campaign_id =
+            (!overlapping).then(|| format!("{}-{}", row.id, now.format(RUN_ID_FORMAT)));
+
+        // claim the due run, so that it is run once even with several crm instances
+        let ret = sqlx::query(
+            "UPDATE schedules SET next_run_at = ?, last_run_at = COALESCE(?, last_run_at), last_campaign_id = COALESCE(?, last_campaign_id), last_error = IF(? IS NULL, last_error, NULL) WHERE id = ? AND status = ? AND next_run_at = ?",
+        )
+        .bind(next_run_at)
+        .bind(campaign_id.as_ref().map(|_| now))
+        .bind(&campaign_id)
+        .bind(&campaign_id)
+        .bind(&row.id)
+        .bind(ScheduleStatus::Active as i32)
+        .bind(row.next_run_at)
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+        if ret.rows_affected() == 0 {
+            return Ok(());
+        }
+
+        let Some(campaign_id) = campaign_id else {
+            info!(
+                "schedule {} skipped, campaign {} is still running",
+                row.id,
+                row.last_campaign_id.unwrap_or_default()
+            );
+            return Ok(());
+        };
+
+        let params = CampaignParams::decode(row.params.as_slice())
+            .map_err(|e| Status::internal(format!("invalid params: {e}")))?
+            .with_id(&campaign_id);
+        info!("schedule {} starts campaign {}", row.id, campaign_id);
+        // the run is over either way, the next one is due as usual; the error stays on the
+        // schedule until a run starts
+        if let Err(e) = self.submit_campaign(params).await {
+            sqlx::query(
+                "UPDATE schedules SET last_error = ? WHERE id = ? AND last_campaign_id = ?",
+            )
+            .bind(truncate(e.message(), MAX_ERROR_LEN))
+            .bind(&row.id)
+            .bind(&campaign_id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn get_schedule(&self, id: &str) -> Result<Response<Schedule>, Status> {
+        match self.find_schedule(id).await? {
+            Some(row) => Ok(Response::new(row.into())),
+            None => Err(schedule_not_found(id)),
+        }
+    }
+
+    async fn find_schedule(&self, id: &str) -> Result<Option<ScheduleRow>, Status> {
+        let sql = format!("SELECT {SCHEDULE_COLUMNS} FROM schedules WHERE id = ?");
+        sqlx::query_as::<_, ScheduleRow>(&sql)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)
+    }
+}
+
+impl Trigger {
+    /// The first run due after the given time.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        match self {
+            Trigger::Cron(expr) => parse_cron(expr)?
+                .after(&after)
+                .next()
+                .ok_or_else(|| anyhow!("cron {expr} never fires")),
+            Trigger::Interval(0) => bail!("interval must be positive"),
+            Trigger::Interval(secs) => Ok(after + chrono::Duration::seconds(*secs as i64)),
+        }
+    }
+}
+
+/// Parse a cron expression, the seconds field is optional.
+fn parse_cron(expr: &str) -> Result<cron::Schedule> {
+    let expr = expr.trim();
+    let expr = match expr.split_whitespace().count() {
+        5 => format!("0 {expr}"),
+        _ => expr.to_string(),
+    };
+    cron::Schedule::from_str(&expr).map_err(|e| anyhow!("invalid cron {expr}: {e}"))
+}
+
+fn invalid_trigger(e: anyhow::Error) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+fn schedule_not_found(id: &str) -> Status {
+    Status::not_found(format!("schedule {id} not found"))
+}
+
+impl ScheduleRow {
+    fn trigger(&self) -> Option<Trigger> {
+        match (&self.cron, self.interval) {
+            (Some(cron), _) => Some(Trigger::Cron(cron.clone())),
+            (None, Some(interval)) => Some(Trigger::Interval(interval)),
+            (None, None) => None,
+        }
+    }
+
+    fn next_after(&self, after: DateTime<Utc>) -> Result<DateTime<Utc>> {
+        let trigger = self
+            .trigger()
+            .ok_or_else(|| anyhow!("schedule {} has no trigger", self.id))?;
+        trigger.next_after(after)
+    }
+}
+
+impl From<create_schedule_request::Trigger> for Trigger {
+    fn from(trigger: create_schedule_request::Trigger) -> Self {
+        match trigger {
+            create_schedule_request::Trigger::Cron(v) => Trigger::Cron(v),
+            create_schedule_request::Trigger::Interval(v) => Trigger::Interval(v),
+        }
+    }
+}
+
+impl From<ScheduleRow> for Schedule {
+    fn from(row: ScheduleRow) -> Self {
+        let params = CampaignParams::decode(row.params.as_slice())
+            .inspect_err(|e| warn!("failed to decode params of schedule {}: {:?}", row.id, e))
+            .ok();
+        let trigger = row.trigger();
+
+        Schedule {
+            id: row.id,
+            params,
+            trigger,
+            status: row.status,
+            next_run_at: Some(dt_to_ts(row.next_run_at)),
+            last_run_at: row.last_run_at.map(dt_to_ts),
+            last_campaign_id: row.last_campaign_id.unwrap_or_default(),
+            last_error: row.last_error.unwrap_or_default(),
+            created_at: Some(dt_to_ts(row.created_at)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::pb::RecallRequest;
+
+    #[test]
+    fn trigger_should_compute_next_run() -> Result<()> {
+        // 2024-01-02T03:04:05Z, a Tuesday
+        let now = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+
+        let next = Trigger::Cron("0 9 * * *".to_string()).next_after(now)?;
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 2, 9, 0, 0).unwrap());
+
+        let next = Trigger::Cron("30 0 9 * * Mon".to_string()).next_after(now)?;
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 8, 9, 0, 30).unwrap());
+
+        let next = Trigger::Interval(3600).next_after(now)?;
+        assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 2, 4, 4, 5).unwrap());
+
+        assert!(Trigger::Interval(0).next_after(now).is_err());
+        assert!(Trigger::Cron("every day".to_string())
+            .next_after(now)
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn schedule_should_pause_and_resume() -> Result<()> {
+        let (_tdb, svc) = CrmService::new_for_test().await?;
+        let params: CampaignParams = RecallRequest {
+            id: String::new(),
+            last_visit_interval: 30,
+            content_ids: vec![1],
+            channels: None,
+            dry_run: false,
+            experiment: None,
+            segment: None,
+            emails: vec![],
+        }
+        .into();
+        let req = CreateScheduleRequest {
+            id: "daily-recall".to_string(),
+            params: Some(params.clone()),
+            trigger: Some(create_schedule_request::Trigger::Cron(
+                "0 9 * * *".to_string(),
+            )),
+        };
+        let schedule = svc.create_schedule(req.clone()).await?.into_inner();
+        assert_eq!(schedule.status(), ScheduleStatus::Active);
+        assert_eq!(schedule.params, Some(params));
+
+        let err = svc.create_schedule(req.clone()).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        // its campaign ids wouldn't fit
+        let long = CreateScheduleRequest {
+            id: "x".repeat(MAX_SCHEDULE_ID_LEN + 1),
+            ..req
+        };
+        let err = svc.create_schedule(long).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let id = "daily-recall".to_string();
+        let schedule = svc
+            .pause_schedule(PauseScheduleRequest { id: id.clone() })
+            .await?
+            .into_inner();
+        assert_eq!(schedule.status(), ScheduleStatus::Paused);
+
+        let req = ListSchedulesRequest {
+            include_paused: false,
+        };
+        let stream = svc.list_schedules(req).await?.into_inner();
+        assert_eq!(futures::StreamExt::count(stream).await, 0);
+
+        let schedule = svc
+            .resume_schedule(ResumeScheduleRequest { id })
+            .await?
+            .into_inner();
+        assert_eq!(schedule.status(), ScheduleStatus::Active);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use futures::{future, stream::BoxStream, StreamExt};
+use prost::Message;
+use sqlx::FromRow;
+use tonic::{Response, Status};
+use user_stat::pb::{
+    condition::Cond, Condition, IdCondition, QueryRequest, TimeCondition, TimeQuery, User,
+};
+
+use crate::{
+    pb::{
+        campaign_params::Request, segment::Expr, CampaignParams, Channel, DaysRange,
+        DeleteSegmentRequest, ListSegmentsRequest, SaveSegmentRequest, SavedSegment, Segment,
+        SegmentList,
+    },
+    CrmService, SegmentStream,
+};
+
+use super::{
+    campaign::{db_err, dt_to_ts},
+    crm::to_ts,
+};
+
+/// Queries a segment may compile into at most.
+const MAX_QUERIES: usize = 32;
+/// Saved segments a segment may refer through at most.
+const MAX_DEPTH: usize = 8;
+
+/// Conditions that all hold; a segment compiles into a union of them.
+type Conjunct = Vec<Condition>;
+
+#[derive(Debug, FromRow)]
+struct SegmentRow {
+    name: String,
+    description: Option<String>,
+    segment: Vec<u8>,
+    updated_at: DateTime<Utc>,
+}
+
+impl CrmService {
+    pub async fn save_segment(
+        &self,
+        request: SaveSegmentRequest,
+    ) -> Result<Response<SavedSegment>, Status> {
+        if request.name.is_empty() {
+            return Err(Status::invalid_argument("segment name is required"));
+        }
+        let Some(segment) = request.segment else {
+            return Err(Status::invalid_argument("segment is required"));
+        };
+
+        let mut saved = self.saved_segments().await?;
+        saved.insert(request.name.clone(), segment.clone());
+        segment
+            .compile(&saved)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO segments(name, description, segment, updated_at) VALUES (?, ?, ?, ?) \
+             ON DUPLICATE KEY UPDATE description = VALUES(description), \
+             segment = VALUES(segment), updated_at = VALUES(updated_at)",
+        )
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(segment.encode_to_vec())
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        Ok(Response::new(SavedSegment {
+            name: request.name,
+            description: request.description,
+            segment: Some(segment),
+            updated_at: Some(dt_to_ts(now)),
+        }))
+    }
+
+    pub async fn list_segments(
+        &self,
+        _request: ListSegmentsRequest,
+    ) -> Result<Response<SegmentStream>, Status> {
+        let rows = self.segment_rows().await?;
+        let segments = rows.into_iter().map(SavedSegment::from).map(Ok);
+        Ok(Response::new(Box::pin(futures::stream::iter(segments))))
+    }
+
+    pub async fn delete_segment(
+        &self,
+        request: DeleteSegmentRequest,
+    ) -> Result<Response<SavedSegment>, Status> {
+        let mut saved: HashMap<String, SavedSegment> = self
+            .segment_rows()
+            .await?
+            .into_iter()
+            .map(|row| (row.name.clone(), row.into()))
+            .collect();
+        let Some(deleted) = saved.remove(&request.name) else {
+            return Err(Status::not_found(format!(
+                "segment {} not found",
+                request.name
+            )));
+        };
+        let referrer = saved.values().find(|s| {
+            s.segment
+                .as_ref()
+                .is_some_and(|v| v.refers_to(&request.name))
+        });
+        if let Some(referrer) = referrer {
+            return Err(Status::failed_precondition(format!(
+                "segment {} is used by segment {}",
+                request.name, referrer.name
+            )));
+        }
+
+        sqlx::query("DELETE FROM segments WHERE name = ?")
+            .bind(&request.name)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+        Ok(Response::new(deleted))
+    }
+
+    /// Compile the segment, with the saved segments it refers to, into user-stat queries.
+    pub(crate) async fn compile_segment(
+        &self,
+        segment: &Segment,
+    ) -> Result<Vec<QueryRequest>, Status> {
+        let saved = match segment.has_saved() {
+            true => self.saved_segments().await?,
+            false => HashMap::new(),
+        };
+        segment
+            .compile(&saved)
+            .map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+
+    /// Users of all the queries, each user once.
+    pub(crate) async fn query_users(
+        &self,
+        queries: Vec<QueryRequest>,
+    ) -> Result<BoxStream<'static, Result<User, Status>>, Status> {
+        let mut streams = Vec::with_capacity(queries.len());
+        for query in queries {
+            streams.push(self.user_stats().query(query).await?.into_inner());
+        }
+        if streams.len() == 1 {
+            return Ok(streams.pop().unwrap().boxed());
+        }
+
+        let mut seen = HashSet::new();
+        let users = futures::stream::iter(streams)
+            .flatten()
+            .filter(move |user| match user {
+                Ok(user) => future::ready(seen.insert(user.email.clone())),
+                Err(_) => future::ready(true),
+            });
+        Ok(users.boxed())
+    }
+
+    async fn saved_segments(&self) -> Result<HashMap<String, Segment>, Status> {
+        let saved = self
+            .segment_rows()
+            .await?
+            .into_iter()
+            .filter_map(|row| {
+                let segment = Segment::decode(row.segment.as_slice()).ok()?;
+                Some((row.name, segment))
+            })
+            .collect();
+        Ok(saved)
+    }
+
+    async fn segment_rows(&self) -> Result<Vec<SegmentRow>, Status> {
+        sqlx::query_as::<_, SegmentRow>(
+            "SELECT name, description, segment, updated_at FROM segments ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_err)
+    }
+}
+
+impl Segment {
+    /// Users whose time column is between the given days ago.
+    pub fn days(column: &str, from_days_ago: Option<u32>, to_days_ago: Option<u32>) -> Self {
+        Self {
+            expr: Some(Expr::Days(DaysRange {
+                column: column.to_string(),
+                from_days_ago,
+                to_days_ago,
+            })),
+        }
+    }
+
+    /// Users in all of the segments.
+    pub fn all(segments: impl IntoIterator<Item = Segment>) -> Self {
+        Self {
+            expr: Some(Expr::All(SegmentList {
+                segments: segments.into_iter().collect(),
+            })),
+        }
+    }
+
+    /// User-stat queries the union of which is the segment.
+    pub fn compile(&self, saved: &HashMap<String, Segment>) -> Result<Vec<QueryRequest>> {
+        let mut compiler = Compiler {
+            saved,
+            stack: vec![],
+        };
+        let conjuncts = compiler.dnf(self, false)?;
+        let queries = conjuncts
+            .into_iter()
+            .map(|conditions| QueryRequest {
+                conditions,
+                ..Default::default()
+            })
+            .collect();
+        Ok(queries)
+    }
+
+    fn has_saved(&self) -> bool {
+        self.any_saved(&|_| true)
+    }
+
+    fn refers_to(&self, name: &str) -> bool {
+        self.any_saved(&|v| v == name)
+    }
+
+    fn any_saved(&self, f: &dyn Fn(&str) -> bool) -> bool {
+        match &self.expr {
+            Some(Expr::All(list)) | Some(Expr::Any(list)) => {
+                list.segments.iter().any(|s| s.any_saved(f))
+            }
+            Some(Expr::Not(s)) => s.any_saved(f),
+            Some(Expr::Saved(name)) => f(name),
+            _ => false,
+        }
+    }
+}
+
+impl CampaignParams {
+    pub fn segment(&self) -> Option<&Segment> {
+        match &self.request {
+            Some(Request::Welcome(req)) => req.segment.as_ref(),
+            Some(Request::Recall(req)) => req.segment.as_ref(),
+            Some(Request::Remind(req)) => req.segment.as_ref(),
+            None => None,
+        }
+    }
+}
+
+impl From<SegmentRow> for SavedSegment {
+    fn from(row: SegmentRow) -> Self {
+        Self {
+            name: row.name,
+            description: row.description.unwrap_or_default(),
+            segment: Segment::decode(row.segment.as_slice()).ok(),
+            updated_at: Some(dt_to_ts(row.updated_at)),
+        }
+    }
+}
+
+struct Compiler<'a> {
+    saved: &'a HashMap<String, Segment>,
+    /// saved segments being expanded, to catch cycles
+    stack: Vec<String>,
+}
+
+impl Compiler<'_> {
+    /// Disjunctive normal form of the segment, negations pushed down to the conditions.
+    fn dnf(&mut self, segment: &Segment, negated: bool) -> Result<Vec<Conjunct>> {
+        let Some(expr) = &segment.expr else {
+            bail!("empty segment");
+        };
+
+        match expr {
+            Expr::All(list) if !negated => self.all(&list.segments, false),
+            Expr::All(list) => self.any(&list.segments, true),
+            Expr::Any(list) if !negated => self.any(&list.segments, false),
+            Expr::Any(list) => self.all(&list.segments, true),
+            Expr::Not(inner) => self.dnf(inner, !negated),
+            Expr::Saved(name) => {
+                if self.stack.contains(name) {
+                    bail!("segment {} refers to itself", name);
+                }
+                if self.stack.len() >= MAX_DEPTH {
+                    bail!("saved segments nested too deep at {}", name);
+                }
+                let saved = self
+                    .saved
+                    .get(name)
+                    .ok_or_else(|| anyhow!("segment {} not found", name))?;
+                self.stack.push(name.clone());
+                let ret = self.dnf(saved, negated);
+                self.stack.pop();
+                ret
+            }
+            expr => Ok(vec![vec![condition(expr, negated)?]]),
+        }
+    }
+
+    fn all(&mut self, segments: &[Segment], negated: bool) -> Result<Vec<Conjunct>> {
+        let mut ret = vec![vec![]];
+        for segment in segments {
+            let next = self.dnf(segment, negated)?;
+            let mut product = Vec::with_capacity(ret.len() * next.len());
+            for a in &ret {
+                for b in &next {
+                    product.push([a.as_slice(), b.as_slice()].concat());
+                }
+            }
+            ensure_size(product.len())?;
+            ret = product;
+        }
+        Ok(ret)
+    }
+
+    fn any(&mut self, segments: &[Segment], negated: bool) -> Result<Vec<Conjunct>> {
+        let mut ret = vec![];
+        for segment in segments {
+            ret.extend(self.dnf(segment, negated)?);
+            ensure_size(ret.len())?;
+        }
+        Ok(ret)
+    }
+}
+
+fn ensure_size(queries: usize) -> Result<()> {
+    if queries > MAX_QUERIES {
+        bail!("segment is too complex: more than {} queries", MAX_QUERIES);
+    }
+    Ok(())
+}
+
+fn condition(expr: &Expr, negated: bool) -> Result<Condition> {
+    let cond = match expr {
+        Expr::Days(range) => Cond::Time(TimeCondition {
+            column: range.column.clone(),
+            range: Some(TimeQuery {
+                lower: range.from_days_ago.map(|v| to_ts(v as _)),
+                upper: range.to_days_ago.map(|v| to_ts(v as _)),
+            }),
+        }),
+        Expr::Ids(ids) => Cond::Ids(IdCondition {
+            column: ids.column.clone(),
+            ids: ids.ids.clone(),
+        }),
+        Expr::Gender(gender) => Cond::Gender(gender.clone()),
+        Expr::Notified(notified) => {
+            let column = match notified.channel() {
+                Channel::Email => "last_email_notification",
+                Channel::Sms => "last_sms_notification",
+                Channel::InApp => "last_in_app_notification",
+                Channel::Unspecified => bail!("channel is required"),
+            };
+            Cond::Time(TimeCondition {
+                column: column.to_string(),
+                range: Some(TimeQuery {
+                    lower: Some(to_ts(notified.within_days as _)),
+                    upper: None,
+                }),
+            })
+        }
+        Expr::All(_) | Expr::Any(_) | Expr::Not(_) | Expr::Saved(_) => {
+            unreachable!("not a condition")
+        }
+    };
+
+    Ok(Condition {
+        negated,
+        cond: Some(cond),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::Notified;
+
+    fn gender(v: &str) -> Segment {
+        Segment {
+            expr: Some(Expr::Gender(v.to_string())),
+        }
+    }
+
+    fn any(segments: Vec<Segment>) -> Segment {
+        Segment {
+            expr: Some(Expr::Any(SegmentList { segments })),
+        }
+    }
+
+    fn not(segment: Segment) -> Segment {
+        Segment {
+            expr: Some(Expr::Not(Box::new(segment))),
+        }
+    }
+
+    fn saved(name: &str) -> Segment {
+        Segment {
+            expr: Some(Expr::Saved(name.to_string())),
+        }
+    }
+
+    /// Each query as its conditions, `!` marking negated ones.
+    fn summary(queries: &[QueryRequest]) -> Vec<Vec<String>> {
+        queries
+            .iter()
+            .map(|q| {
+                q.conditions
+                    .iter()
+                    .map(|c| {
+                        let name = match c.cond.as_ref().unwrap() {
+                            Cond::Time(t) => t.column.clone(),
+                            Cond::Ids(ids) => ids.column.clone(),
+                            Cond::Gender(g) => g.clone(),
+                        };
+                        match c.negated {
+                            true => format!("!{name}"),
+                            false => name,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn segment_should_compile_to_dnf() -> Result<()> {
+        let notified = Segment {
+            expr: Some(Expr::Notified(Notified {
+                channel: Channel::Sms as i32,
+                within_days: 7,
+            })),
+        };
+        // visited lately, M or F, and not (notified by sms or F)
+        let segment = Segment::all([
+            Segment::days("last_visited_at", Some(30), None),
+            any(vec![gender("M"), gender("F")]),
+            not(any(vec![notified, gender("F")])),
+        ]);
+
+        let queries = segment.compile(&HashMap::new())?;
+        assert_eq!(
+            summary(&queries),
+            vec![
+                vec!["last_visited_at", "M", "!last_sms_notification", "!F"],
+                vec!["last_visited_at", "F", "!last_sms_notification", "!F"],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn segment_should_expand_saved_segments() -> Result<()> {
+        let mut segments = HashMap::new();
+        segments.insert("men".to_string(), gender("M"));
+        segments.insert("not-men".to_string(), not(saved("men")));
+        segments.insert("loop".to_string(), any(vec![gender("F"), saved("loop")]));
+
+        let queries = not(saved("not-men")).compile(&segments)?;
+        assert_eq!(summary(&queries), vec![vec!["M"]]);
+
+        assert!(saved("loop").compile(&segments).is_err());
+        assert!(saved("missing").compile(&segments).is_err());
+        assert!(saved("loop").refers_to("loop"));
+        Ok(())
+    }
+
+    #[test]
+    fn segment_should_limit_queries() {
+        let pair = || any(vec![gender("M"), gender("F")]);
+        let segment = Segment::all((0..6).map(|_| pair()));
+        assert!(segment.compile(&HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,551 @@
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, MySql, QueryBuilder};
+use tonic::{Code, Response, Status};
+use tracing::{info, warn};
+
+use crate::{
+    pb::{
+        CampaignParams, Event, EventKind, IngestEventResponse, RecallRequest, RemindRequest,
+        TriggeredAction, TriggeredActionStatus, WelcomeRequest,
+    },
+    CrmService, EventName, FrequencyCap, RuleAction, TriggerRule,
+};
+
+use super::campaign::{db_err, dt_to_ts};
+
+const ACTION_COLUMNS: &str =
+    "id, rule, email, content_id, status, due_at, campaign_id, attempts, error";
+/// Times the campaign of an action is tried to start before the action fails.
+const MAX_FIRE_ATTEMPTS: u32 = 3;
+/// Actions claimed longer ago are taken over, the instance firing them having died.
+const FIRING_TIMEOUT_SECS: i64 = 600;
+/// Delay of the retry of an action, times the attempts so far.
+const RETRY_DELAY_SECS: i64 = 60;
+pub(super) const MAX_ERROR_LEN: usize = 1024;
+/// Actions fired in one run at most, the earliest due first; the rest wait for the next run.
+const MAX_DUE_ACTIONS: u32 = 100;
+
+#[derive(Debug, Clone, FromRow)]
+struct ActionRow {
+    id: u64,
+    rule: String,
+    email: String,
+    content_id: u32,
+    status: i32,
+    due_at: DateTime<Utc>,
+    campaign_id: Option<String>,
+    attempts: u32,
+    error: Option<String>,
+}
+
+impl CrmService {
+    pub async fn ingest_event(
+        &self,
+        event: Event,
+    ) -> Result<Response<IngestEventResponse>, Status> {
+        let Some(name) = EventName::from_kind(event.kind()) else {
+            return Err(Status::invalid_argument("event kind is required"));
+        };
+        let email = event.email.trim().to_lowercase();
+        if email.is_empty() {
+            return Err(Status::invalid_argument("email is required"));
+        }
+        let occurred_at = match &event.occurred_at {
+            Some(ts) => DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
+                .ok_or_else(|| Status::invalid_argument("invalid occurred_at"))?,
+            None => Utc::now(),
+        };
+
+        let rules = self.config().trigger_rules();
+        let cancelling: Vec<_> = rules
+            .iter()
+            .filter(|r| r.cancel_on.contains(&name))
+            .map(|r| r.name.as_str())
+            .collect();
+        let cancelled = self
+            .cancel_actions(&email, event.content_id, &cancelling)
+            .await?;
+
+        let mut scheduled = vec![];
+        for rule in rules.iter().filter(|r| r.matches(name, &event)) {
+            let due_at = occurred_at + chrono::Duration::seconds(rule.delay_secs as i64);
+            let ret = sqlx::query(
+                "INSERT INTO triggered_actions(rule, email, content_id, status, due_at) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&rule.name)
+            .bind(&email)
+            .bind(event.content_id)
+            .bind(TriggeredActionStatus::Pending as i32)
+            .bind(due_at)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+            let row = ActionRow {
+                id: ret.last_insert_id(),
+                rule: rule.name.clone(),
+                email: email.clone(),
+                content_id: event.content_id,
+                status: TriggeredActionStatus::Pending as i32,
+                due_at,
+                campaign_id: None,
+                attempts: 0,
+                error: None,
+            };
+            scheduled.push(row.into());
+        }
+
+        Ok(Response::new(IngestEventResponse {
+            scheduled,
+            cancelled: cancelled as u32,
+        }))
+    }
+
+    /// Start the campaigns of the due actions, and of those whose instance died firing them,
+    /// called by the scheduler.
+    pub(crate) async fn run_due_actions(&self) -> Result<(), Status> {
+        let sql = format!(
+            "SELECT {ACTION_COLUMNS} FROM triggered_actions WHERE due_at <= ? \
+             AND (status = ? OR (status = ? AND claimed_at < ?)) ORDER BY due_at LIMIT ?"
+        );
+        let rows = sqlx::query_as::<_, ActionRow>(&sql)
+            .bind(Utc::now())
+            .bind(TriggeredActionStatus::Pending as i32)
+            .bind(TriggeredActionStatus::Firing as i32)
+            .bind(firing_deadline())
+            .bind(MAX_DUE_ACTIONS)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_err)?;
+
+        for row in rows {
+            let id = row.id;
+            if let Err(e) = self.fire_action(row).await {
+                warn!("failed to fire triggered action {}: {:?}", id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fire the action: claim it, start its campaign and mark it fired, or put it back to be
+    /// retried, failed after `MAX_FIRE_ATTEMPTS`, if the campaign didn't start.
+    async fn fire_action(&self, row: ActionRow) -> Result<(), Status> {
+        let rules = self.config().trigger_rules();
+        let mut rule = rules.iter().find(|r| r.name == row.rule);
+        if rule.is_none() {
+            warn!("rule {} of action {} no longer exists", row.rule, row.id);
+        }
+        if let Some(cap) = rule.and_then(|r| r.frequency_cap) {
+            if self.capped(&row.email, cap).await? {
+                info!("{} capped, rule {} doesn't fire", row.email, row.rule);
+                rule = None;
+            }
+        }
+        let Some(rule) = rule else {
+            self.claim_action(&row, TriggeredActionStatus::Cancelled, None)
+                .await?;
+            return Ok(());
+        };
+
+        // claim the action, so that it is fired once even with several crm instances
+        let run_id = row.run_id();
+        let claimed = self
+            .claim_action(&row, TriggeredActionStatus::Firing, Some(&run_id))
+            .await?;
+        if !claimed {
+            return Ok(());
+        }
+
+        info!("rule {} fires for {}", rule.name, row.email);
+        let (status, error) = match self.submit_campaign(rule.params(&row)).await {
+            Ok(_) => (TriggeredActionStatus::Fired, None),
+            // started by an attempt whose instance died before marking the action
+            Err(e) if e.code() == Code::AlreadyExists => (TriggeredActionStatus::Fired, None),
+            Err(e) if row.attempts + 1 >= MAX_FIRE_ATTEMPTS => {
+                (TriggeredActionStatus::Failed, Some(e))
+            }
+            Err(e) => (TriggeredActionStatus::Pending, Some(e)),
+        };
+        let message = error.as_ref().map(|e| truncate(e.message(), MAX_ERROR_LEN));
+        let due_at = match status {
+            TriggeredActionStatus::Pending => {
+                let attempts = row.attempts as i64 + 1;
+                Utc::now() + chrono::Duration::seconds(RETRY_DELAY_SECS * attempts)
+            }
+            _ => row.due_at,
+        };
+        sqlx::query(
+            "UPDATE triggered_actions SET status = ?, campaign_id = ?, error = ?, due_at = ? \
+             WHERE id = ? AND status = ?",
+        )
+        .bind(status as i32)
+        .bind(error.is_none().then_some(&run_id))
+        .bind(message)
+        .bind(due_at)
+        .bind(row.id)
+        .bind(TriggeredActionStatus::Firing as i32)
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Move the action from pending, or from firing by a dead instance, to the status. Whether
+    /// the action was, rather than taken by another instance.
+    async fn claim_action(
+        &self,
+        row: &ActionRow,
+        status: TriggeredActionStatus,
+        campaign_id: Option<&str>,
+    ) -> Result<bool, Status> {
+        let attempt = u32::from(status == TriggeredActionStatus::Firing);
+        let ret = sqlx::query(
+            "UPDATE triggered_actions SET status = ?, campaign_id = ?, claimed_at = ?, \
+             attempts = attempts + ? WHERE id = ? \
+             AND (status = ? OR (status = ? AND claimed_at < ?))",
+        )
+        .bind(status as i32)
+        .bind(campaign_id)
+        .bind(Utc::now())
+        .bind(attempt)
+        .bind(row.id)
+        .bind(TriggeredActionStatus::Pending as i32)
+        .bind(TriggeredActionStatus::Firing as i32)
+        .bind(firing_deadline())
+        .execute(&self.pool)
+        .await
+        .map_err(db_err)?;
+        Ok(ret.rows_affected() > 0)
+    }
+
+    /// Cancel the pending actions of the rules for the user, returning how many were.
+    async fn cancel_actions(
+        &self,
+        email: &str,
+        content_id: u32,
+        rules: &[&str],
+    ) -> Result<u64, Status> {
+        if rules.is_empty() {
+            return Ok(0);
+        }
+
+        let mut builder: QueryBuilder<MySql> =
+            QueryBuilder::new("UPDATE triggered_actions SET status = ");
+        builder
+            .push_bind(TriggeredActionStatus::Cancelled as i32)
+            .push(" WHERE status = ")
+            .push_bind(TriggeredActionStatus::Pending as i32)
+            .push(" AND email = ")
+            .push_bind(email);
+        if content_id != 0 {
+            builder
+                .push(" AND content_id IN (0, ")
+                .push_bind(content_id)
+                .push(")");
+        }
+        builder.push(" AND rule IN (");
+        let mut names = builder.separated(", ");
+        for rule in rules {
+            names.push_bind(*rule);
+        }
+        names.push_unseparated(")");
+
+        let ret = builder.build().execute(&self.pool).await.map_err(db_err)?;
+        Ok(ret.rows_affected())
+    }
+
+    /// Whether the user got as many messages as the frequency cap allows.
+    async fn capped(&self, email: &str, cap: FrequencyCap) -> Result<bool, Status> {
+        let since = Utc::now() - chrono::Duration::hours(cap.within_hours as i64);
+        let (n,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM campaign_recipients WHERE email = ? AND messaged_at > ?",
+        )
+        .bind(email)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(db_err)?;
+        Ok(n >= cap.max_messages as i64)
+    }
+}
+
+impl TriggerRule {
+    fn matches(&self, name: EventName, event: &Event) -> bool {
+        self.event == name
+            && (name != EventName::Inactive || event.inactive_days >= self.min_inactive_days)
+    }
+
+    /// The campaign of the rule for the user of the action alone.
+    fn params(&self, row: &ActionRow) -> CampaignParams {
+        let id = row.run_id();
+        let content_ids = match (self.content_ids.is_empty(), row.content_id) {
+            (true, v) if v != 0 => vec![v],
+            _ => self.content_ids.clone(),
+        };
+        let emails = vec![row.email.clone()];
+        match self.action {
+            RuleAction::Welcome => WelcomeRequest {
+                id,
+                interval: self.interval_days,
+                content_ids,
+                emails,
+                ..Default::default()
+            }
+            .into(),
+            RuleAction::Recall => RecallRequest {
+                id,
+                last_visit_interval: self.interval_days,
+                content_ids,
+                emails,
+                ..Default::default()
+            }
+            .into(),
+            // a remind campaign reminds the user of all of their unfinished contents, the
+            // content of the event among them, and has no contents of its own to set
+            RuleAction::Remind => RemindRequest {
+                id,
+                last_visit_interval: self.interval_days,
+                emails,
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+}
+
+impl EventName {
+    fn from_kind(kind: EventKind) -> Option<Self> {
+        match kind {
+            EventKind::UserRegistered => Some(Self::UserRegistered),
+            EventKind::ContentStarted => Some(Self::ContentStarted),
+            EventKind::ContentFinished => Some(Self::ContentFinished),
+            EventKind::ContentAbandoned => Some(Self::ContentAbandoned),
+            EventKind::Inactive => Some(Self::Inactive),
+            EventKind::Unspecified => None,
+        }
+    }
+}
+
+impl ActionRow {
+    /// Id of the campaign the action runs, which fits a campaign id as rule names are bounded.
+    fn run_id(&self) -> String {
+        format!("{}-{}", self.rule, self.id)
+    }
+}
+
+/// Actions firing since before are taken over.
+fn firing_deadline() -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::seconds(FIRING_TIMEOUT_SECS)
+}
+
+/// The longest prefix of the text within `max` bytes.
+pub(super) fn truncate(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+impl From<ActionRow> for TriggeredAction {
+    fn from(row: ActionRow) -> Self {
+        TriggeredAction {
+            id: row.id,
+            rule: row.rule,
+            email: row.email,
+            content_id: row.content_id,
+            status: row.status,
+            due_at: Some(dt_to_ts(row.due_at)),
+            campaign_id: row.campaign_id.unwrap_or_default(),
+            error: row.error.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::super::campaign::MAX_CAMPAIGN_ID_LEN;
+    use super::*;
+    use crate::{
+        config::MAX_RULE_NAME_LEN, pb::campaign_params::Request, WelcomeConfig, SIGNUP_RULE,
+    };
+
+    fn event(kind: EventKind, email: &str, content_id: u32) -> Event {
+        Event {
+            kind: kind as i32,
+            email: email.to_string(),
+            content_id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rule_should_run_campaign_of_the_user() {
+        let rule = TriggerRule {
+            name: "unfinished".to_string(),
+            event: EventName::ContentStarted,
+            min_inactive_days: 0,
+            action: RuleAction::Remind,
+            delay_secs: 0,
+            cancel_on: vec![],
+            content_ids: vec![],
+            interval_days: 30,
+            frequency_cap: None,
+        };
+        let row = ActionRow {
+            id: 7,
+            rule: rule.name.clone(),
+            email: "alice@acme.org".to_string(),
+            content_id: 3,
+            status: TriggeredActionStatus::Pending as i32,
+            due_at: Utc::now(),
+            campaign_id: None,
+            attempts: 0,
+            error: None,
+        };
+        let params = rule.params(&row);
+        assert_eq!(params.id(), "unfinished-7");
+        let longest = ActionRow {
+            id: u64::MAX,
+            rule: "r".repeat(MAX_RULE_NAME_LEN),
+            ..row.clone()
+        };
+        assert_eq!(longest.run_id().len(), MAX_CAMPAIGN_ID_LEN);
+        let Some(Request::Remind(req)) = params.request else {
+            panic!("not a remind");
+        };
+        assert_eq!(req.emails, vec!["alice@acme.org"]);
+
+        let recall = TriggerRule {
+            action: RuleAction::Recall,
+            ..rule
+        };
+        let Some(Request::Recall(req)) = recall.params(&row).request else {
+            panic!("not a recall");
+        };
+        assert_eq!(req.content_ids, vec![3]);
+
+        let inactive = TriggerRule {
+            event: EventName::Inactive,
+            min_inactive_days: 14,
+            ..recall
+        };
+        let mut ev = event(EventKind::Inactive, "alice@acme.org", 0);
+        ev.inactive_days = 7;
+        assert!(!inactive.matches(EventName::Inactive, &ev));
+        ev.inactive_days = 14;
+        assert!(inactive.matches(EventName::Inactive, &ev));
+    }
+
+    #[test]
+    fn errors_should_be_truncated_at_char_boundaries() {
+        assert_eq!(truncate("héllo", 2), "h");
+        assert_eq!(truncate("héllo", 3), "hé");
+        assert_eq!(truncate("hello", 10), "hello");
+    }
+
+    #[test]
+    fn signup_rule_should_welcome_the_user_once_the_delay_is_over() {
+        let mut welcome = WelcomeConfig {
+            on_signup: true,
+            delay_secs: 0,
+            content_ids: vec![1],
+            frequency_cap: None,
+        };
+        let rule = welcome.signup_rule().unwrap();
+        assert_eq!(rule.name, SIGNUP_RULE);
+        assert!(rule.matches(
+            EventName::UserRegistered,
+            &event(EventKind::UserRegistered, "alice@acme.org", 0)
+        ));
+        assert_eq!(rule.interval_days, 1);
+
+        welcome.delay_secs = 3 * 24 * 3600 + 1;
+        let rule = welcome.signup_rule().unwrap();
+        assert_eq!(rule.interval_days, 4);
+        let row = ActionRow {
+            id: 9,
+            rule: rule.name.clone(),
+            email: "alice@acme.org".to_string(),
+            content_id: 0,
+            status: TriggeredActionStatus::Pending as i32,
+            due_at: Utc::now(),
+            campaign_id: None,
+            attempts: 0,
+            error: None,
+        };
+        let Some(Request::Welcome(req)) = rule.params(&row).request else {
+            panic!("not a welcome");
+        };
+        assert_eq!((req.interval, req.content_ids), (4, vec![1]));
+
+        welcome.on_signup = false;
+        assert!(welcome.signup_rule().is_none());
+    }
+
+    #[tokio::test]
+    async fn frequency_cap_should_count_recent_messages() -> Result<()> {
+        let (_tdb, svc) = CrmService::new_for_test().await?;
+        let cap = svc.config().welcome.frequency_cap.unwrap();
+        assert!(!svc.capped("alice@acme.org", cap).await?);
+
+        for i in 0..cap.max_messages {
+            sqlx::query("INSERT INTO campaign_recipients(campaign_id, email) VALUES (?, ?)")
+                .bind(format!("c{}", i))
+                .bind("alice@acme.org")
+                .execute(&svc.pool)
+                .await?;
+        }
+        assert!(svc.capped("alice@acme.org", cap).await?);
+        assert!(!svc.capped("bob@acme.org", cap).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn events_should_schedule_and_cancel_actions() -> Result<()> {
+        let (_tdb, svc) = CrmService::new_for_test().await?;
+
+        let res = svc
+            .ingest_event(event(EventKind::ContentStarted, "Alice@acme.org", 3))
+            .await?
+            .into_inner();
+        assert_eq!(res.scheduled.len(), 1);
+        let action = &res.scheduled[0];
+        assert_eq!(
+            (action.rule.as_str(), action.email.as_str()),
+            ("unfinished", "alice@acme.org")
+        );
+        svc.ingest_event(event(EventKind::ContentStarted, "alice@acme.org", 4))
+            .await?;
+
+        // finishing a content cancels the reminder of that content only
+        let res = svc
+            .ingest_event(event(EventKind::ContentFinished, "alice@acme.org", 3))
+            .await?
+            .into_inner();
+        assert_eq!((res.scheduled.len(), res.cancelled), (0, 1));
+
+        let sql = format!("SELECT {ACTION_COLUMNS} FROM triggered_actions ORDER BY id");
+        let rows: Vec<ActionRow> = sqlx::query_as(&sql).fetch_all(&svc.pool).await?;
+        let status: Vec<_> = rows.iter().map(|r| (r.content_id, r.status)).collect();
+        assert_eq!(
+            status,
+            vec![
+                (3, TriggeredActionStatus::Cancelled as i32),
+                (4, TriggeredActionStatus::Pending as i32)
+            ]
+        );
+
+        let err = svc
+            .ingest_event(event(EventKind::Unspecified, "alice@acme.org", 0))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        Ok(())
+    }
+}
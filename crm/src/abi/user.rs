@@ -1,23 +1,347 @@
-use std::time::SystemTime;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use tonic::{Code, Response, Status};
+use tracing::warn;
+use user_stat::pb::{
+    AddUserRequest, DeleteUserRequest as DeleteStatsRequest,
+    UpdateUserRequest as UpdateStatsRequest,
+};
 
-use prost_types::Timestamp;
+use crate::{
+    pb::{
+        CreateUserRequest, DeleteUserRequest, Event, EventKind, GetUserRequest, ListUsersRequest,
+        UpdateUserRequest, User,
+    },
+    CrmService, UserStream,
+};
 
-use crate::pb::User;
+use super::campaign::{db_err, dt_to_ts};
 
-impl User {
-    pub fn new(id: u64, name: &str, email: &str) -> Self {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+const MAX_NAME_LEN: usize = 64;
+const MAX_EMAIL_LEN: usize = 128;
 
+#[derive(Debug, FromRow)]
+struct UserRow {
+    id: u64,
+    name: String,
+    email: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl CrmService {
+    pub async fn get_user(&self, request: GetUserRequest) -> Result<Response<User>, Status> {
+        let row = self.user_row(request.id).await?;
+        Ok(Response::new(row.into()))
+    }
+
+    /// Register a user, and seed user-stat with them so that campaigns can reach them; the trigger
+    /// rules, the signup welcome among them, act on the signup.
+    pub async fn create_user(&self, request: CreateUserRequest) -> Result<Response<User>, Status> {
+        let name = validate_name(&request.name).map_err(invalid)?;
+        let email = validate_email(&request.email).map_err(invalid)?;
+
+        let now = Utc::now();
+        let ret = sqlx::query(
+            "INSERT INTO users(name, email, created_at, updated_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&name)
+        .bind(&email)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| unique_err(e, &email))?;
+
+        let user = UserRow {
+            id: ret.last_insert_id(),
+            name,
+            email,
+            created_at: now,
+            updated_at: now,
+        };
+        self.seed_user_stats(&user).await;
+        let user = User::from(user);
+        self.publish_registered(&user).await;
+        Ok(Response::new(user))
+    }
+
+    /// Rename a user, in user-stat as well. The email can't change: user-stat, consent and the
+    /// campaigns sent know the user by it.
+    pub async fn update_user(&self, request: UpdateUserRequest) -> Result<Response<User>, Status> {
+        let mut user = self.user_row(request.id).await?;
+        if let Some(name) = request.name {
+            user.name = validate_name(&name).map_err(invalid)?;
+        }
+        if let Some(email) = request.email {
+            let email = validate_email(&email).map_err(invalid)?;
+            if email != user.email {
+                return Err(Status::invalid_argument(
+                    "email can't be changed, register a new user instead",
+                ));
+            }
+        }
+
+        user.updated_at = Utc::now();
+        sqlx::query("UPDATE users SET name = ?, updated_at = ? WHERE id = ?")
+            .bind(&user.name)
+            .bind(user.updated_at)
+            .bind(user.id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+        self.rename_user_stats(&user).await;
+        Ok(Response::new(user.into()))
+    }
+
+    /// Delete a user, and their stats so that campaigns don't reach them anymore.
+    pub async fn delete_user(&self, request: DeleteUserRequest) -> Result<Response<User>, Status> {
+        let user = self.user_row(request.id).await?;
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(user.id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_err)?;
+        self.delete_user_stats(&user).await;
+        Ok(Response::new(user.into()))
+    }
+
+    pub async fn list_users(
+        &self,
+        request: ListUsersRequest,
+    ) -> Result<Response<UserStream>, Status> {
+        let limit = match request.limit {
+            0 => DEFAULT_PAGE_SIZE,
+            n => n.min(MAX_PAGE_SIZE),
+        };
+        let rows: Vec<UserRow> = sqlx::query_as(
+            "SELECT id, name, email, created_at, updated_at FROM users WHERE id > ? \
+             ORDER BY id LIMIT ?",
+        )
+        .bind(request.after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_err)?;
+
+        let users = rows.into_iter().map(User::from).map(Ok);
+        Ok(Response::new(Box::pin(futures::stream::iter(users))))
+    }
+
+    async fn user_row(&self, id: u64) -> Result<UserRow, Status> {
+        sqlx::query_as("SELECT id, name, email, created_at, updated_at FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_err)?
+            .ok_or_else(|| Status::not_found(format!("user {} not found", id)))
+    }
+
+    /// Let the trigger rules act on the signup.
+    async fn publish_registered(&self, user: &User) {
+        let event = Event {
+            kind: EventKind::UserRegistered as i32,
+            email: user.email.clone(),
+            occurred_at: user.created_at,
+            ..Default::default()
+        };
+        if let Err(e) = self.ingest_event(event).await {
+            warn!("failed to publish signup of user {}: {}", user.id, e);
+        }
+    }
+
+    /// A user missing from user-stat can't be targeted, but the user is registered all the same.
+    async fn seed_user_stats(&self, user: &UserRow) {
+        let req = AddUserRequest {
+            email: user.email.clone(),
+            name: user.name.clone(),
+            locale: String::new(),
+        };
+        if let Err(e) = self.user_stats().add_user(req).await {
+            warn!("failed to seed user {} into user-stat: {}", user.id, e);
+        }
+    }
+
+    /// A user never seeded into user-stat has nothing to rename.
+    async fn rename_user_stats(&self, user: &UserRow) {
+        let req = UpdateStatsRequest {
+            email: user.email.clone(),
+            name: user.name.clone(),
+        };
+        match self.user_stats().update_user(req).await {
+            Err(e) if e.code() != Code::NotFound => {
+                warn!("failed to rename user {} in user-stat: {}", user.id, e);
+            }
+            _ => {}
+        }
+    }
+
+    async fn delete_user_stats(&self, user: &UserRow) {
+        let req = DeleteStatsRequest {
+            email: user.email.clone(),
+        };
+        match self.user_stats().delete_user(req).await {
+            Err(e) if e.code() != Code::NotFound => {
+                warn!("failed to delete user {} from user-stat: {}", user.id, e);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl From<UserRow> for User {
+    fn from(row: UserRow) -> Self {
         User {
-            id,
+            id: row.id,
+            name: row.name,
+            email: row.email,
+            created_at: Some(dt_to_ts(row.created_at)),
+            updated_at: Some(dt_to_ts(row.updated_at)),
+        }
+    }
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("name is required");
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        bail!("name is longer than {} characters", MAX_NAME_LEN);
+    }
+    Ok(name.to_string())
+}
+
+/// The email, trimmed and lower cased, if it looks like `local@domain.tld`.
+fn validate_email(email: &str) -> Result<String> {
+    let email = email.trim().to_lowercase();
+    if email.len() > MAX_EMAIL_LEN {
+        bail!("email is longer than {} characters", MAX_EMAIL_LEN);
+    }
+    let Some((local, domain)) = email.split_once('@') else {
+        bail!("invalid email {}", email);
+    };
+    let valid = !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain.split('.').all(|v| !v.is_empty())
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control());
+    if !valid {
+        bail!("invalid email {}", email);
+    }
+    Ok(email)
+}
+
+fn invalid(e: anyhow::Error) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+fn unique_err(e: sqlx::Error, email: &str) -> Status {
+    match &e {
+        sqlx::Error::Database(v) if v.is_unique_violation() => {
+            Status::already_exists(format!("email {} is already registered", email))
+        }
+        _ => db_err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn email_should_be_validated_and_normalized() -> Result<()> {
+        assert_eq!(validate_email(" Alice@Acme.org ")?, "alice@acme.org");
+        assert_eq!(
+            validate_email("a.b+c@mail.acme.org")?,
+            "a.b+c@mail.acme.org"
+        );
+        for email in [
+            "",
+            "alice",
+            "@acme.org",
+            "alice@acme",
+            "a@b@acme.org",
+            "a b@acme.org",
+        ] {
+            assert!(validate_email(email).is_err(), "{email}");
+        }
+        assert!(validate_name("  ").is_err());
+        assert!(validate_name(&"a".repeat(65)).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn users_should_be_managed() -> Result<()> {
+        let (_tdb, svc) = CrmService::new_for_test().await?;
+        let create = |name: &str, email: &str| CreateUserRequest {
             name: name.to_string(),
             email: email.to_string(),
-            created_at: Some(Timestamp {
-                seconds: now.as_secs() as i64,
-                nanos: now.subsec_nanos() as i32,
-            }),
-        }
+        };
+
+        let alice = svc.create_user(create("Alice", "Alice@acme.org")).await?;
+        let alice = alice.into_inner();
+        assert_eq!(alice.email, "alice@acme.org");
+        let bob = svc.create_user(create("Bob", "bob@acme.org")).await?;
+        let bob = bob.into_inner();
+        assert!(bob.id > alice.id);
+
+        let err = svc
+            .create_user(create("Alice", "ALICE@acme.org"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        let req = UpdateUserRequest {
+            id: bob.id,
+            name: Some("Robert".to_string()),
+            email: None,
+        };
+        let updated = svc.update_user(req).await?.into_inner();
+        assert_eq!(
+            (updated.name.as_str(), updated.email.as_str()),
+            ("Robert", "bob@acme.org")
+        );
+        let req = UpdateUserRequest {
+            id: bob.id,
+            name: None,
+            email: Some(alice.email.clone()),
+        };
+        let err = svc.update_user(req).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        let req = UpdateUserRequest {
+            id: bob.id,
+            name: None,
+            email: Some("BOB@acme.org".to_string()),
+        };
+        svc.update_user(req).await?;
+
+        let req = ListUsersRequest {
+            after_id: 0,
+            limit: 1,
+        };
+        let page = svc.list_users(req).await?.into_inner();
+        let page: Vec<_> = page.collect().await;
+        assert_eq!(page.len(), 1);
+        let req = ListUsersRequest {
+            after_id: alice.id,
+            limit: 0,
+        };
+        let page = svc.list_users(req).await?.into_inner();
+        let ids: Vec<_> = page.map(|v| v.unwrap().id).collect().await;
+        assert_eq!(ids, vec![bob.id]);
+
+        svc.delete_user(DeleteUserRequest { id: bob.id }).await?;
+        let err = svc
+            .get_user(GetUserRequest { id: bob.id })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        Ok(())
     }
 }
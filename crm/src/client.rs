@@ -1,9 +1,14 @@
-use anyhow::Result;
+use std::{env, fs, time::Duration};
+
+use anyhow::{Context, Result};
 use crm::pb::{
-    crm_client::CrmClient, user_srv_client::UserSrvClient, CreateUserRequest, RecallRequest,
-    RemindRequest, WelcomeRequest,
+    crm_client::CrmClient, user_srv_client::UserSrvClient, Channel, ChannelPolicy,
+    CreateUserRequest, DeliveryMode, ListCampaignsRequest, RecallRequest, RemindRequest,
+    WatchCampaignRequest, WelcomeRequest,
 };
-use tonic::Request;
+use crm_common::{Claims, EncodingKey, TokenInterceptor};
+use futures::StreamExt;
+use tonic::{transport::Channel as GrpcChannel, Request};
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
 
@@ -22,12 +27,18 @@ async fn main() -> Result<()> {
 #[allow(dead_code)]
 async fn call_crm_welcome() -> Result<()> {
     info!("call crm welcome...");
-    let mut client = CrmClient::connect("http://[::1]:50000").await?;
+    let mut client =
+        CrmClient::with_interceptor(connect("http://localhost:50000").await?, token()?);
 
     let req = WelcomeRequest {
         id: uuid::Uuid::new_v4().to_string(),
         interval: 95u32,
         content_ids: vec![1u32],
+        channels: None,
+        dry_run: false,
+        experiment: None,
+        segment: None,
+        emails: vec![],
     };
 
     let response = client.welcome(req).await?;
@@ -39,12 +50,18 @@ async fn call_crm_welcome() -> Result<()> {
 #[allow(dead_code)]
 async fn call_crm_recall() -> Result<()> {
     info!("call crm recall...");
-    let mut client = CrmClient::connect("http://[::1]:50000").await?;
+    let mut client =
+        CrmClient::with_interceptor(connect("http://localhost:50000").await?, token()?);
 
     let req = RecallRequest {
         id: uuid::Uuid::new_v4().to_string(),
         last_visit_interval: 60u32,
         content_ids: vec![1u32],
+        channels: None,
+        dry_run: false,
+        experiment: None,
+        segment: None,
+        emails: vec![],
     };
 
     let response = client.recall(req).await?;
@@ -56,22 +73,59 @@ async fn call_crm_recall() -> Result<()> {
 #[allow(dead_code)]
 async fn call_crm_remind() -> Result<()> {
     info!("call crm remind...");
-    let mut client = CrmClient::connect("http://[::1]:50000").await?;
+    let mut client =
+        CrmClient::with_interceptor(connect("http://localhost:50000").await?, token()?);
 
     let req = RemindRequest {
         id: uuid::Uuid::new_v4().to_string(),
         last_visit_interval: 60u32,
+        // nudge in the app, fall back to email for users without the app
+        channels: Some(ChannelPolicy {
+            mode: DeliveryMode::Fallback as i32,
+            channels: vec![Channel::InApp as i32, Channel::Email as i32],
+        }),
+        dry_run: false,
+        experiment: None,
+        segment: None,
+        emails: vec![],
     };
 
-    let response = client.remind(req).await?;
+    let response = client.remind(req).await?.into_inner();
     info!("{:?}", response);
 
+    let req = WatchCampaignRequest { id: response.id };
+    let mut stream = client.watch_campaign(req).await?.into_inner();
+    while let Some(progress) = stream.next().await {
+        info!("{:?}", progress?);
+    }
+
+    Ok(())
+}
+
+#[allow(dead_code)]
+async fn call_crm_list_campaigns() -> Result<()> {
+    info!("call crm list campaigns...");
+    let mut client =
+        CrmClient::with_interceptor(connect("http://localhost:50000").await?, token()?);
+
+    let req = ListCampaignsRequest {
+        kind: 0,
+        limit: 10,
+        offset: 0,
+    };
+
+    let mut stream = client.list_campaigns(req).await?.into_inner();
+    while let Some(campaign) = stream.next().await {
+        info!("{:?}", campaign?);
+    }
+
     Ok(())
 }
 
 #[allow(dead_code)]
 async fn call_user_service() -> Result<()> {
-    let mut client = UserSrvClient::connect("http://[::1]:50051").await?;
+    let mut client =
+        UserSrvClient::with_interceptor(connect("http://localhost:50000").await?, token()?);
 
     let request = Request::new(CreateUserRequest {
         name: String::from("zhangsan"),
@@ -83,3 +137,16 @@ async fn call_user_service() -> Result<()> {
     println!("User created: {user:?}");
     Ok(())
 }
+
+async fn connect(addr: &'static str) -> Result<GrpcChannel> {
+    Ok(GrpcChannel::from_static(addr).connect().await?)
+}
+
+/// Sign a short-lived operator token for the calls, with the private key `CRM_SK` points to.
+fn token() -> Result<TokenInterceptor> {
+    let path = env::var("CRM_SK").context("CRM_SK must point to the key tokens are signed with")?;
+    let ek = EncodingKey::load(&fs::read_to_string(path)?)?;
+    let claims = Claims::new("crm-client", &["admin"]);
+    let token = ek.sign(claims, Duration::from_secs(600))?;
+    TokenInterceptor::new(&token)
+}
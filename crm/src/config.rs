@@ -1,34 +1,286 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::PathBuf,
+};
+
 use anyhow::{bail, Result};
+use crm_common::{
+    check, check_db_url, check_pk, check_service, check_service_keys, check_sk, check_tls,
+    ConfigLoader, ListenConfig, Reload, RolePolicy, TlsConfig, Validate,
+};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+
+/// Name of the rule welcoming the users as they sign up, reserved.
+pub const SIGNUP_RULE: &str = "signup-welcome";
+/// Longest rule name, leaving room in the 64 bytes of a campaign id for `-` and the id of the
+/// action.
+pub(crate) const MAX_RULE_NAME_LEN: usize = 64 - 21;
+const DAY_SECS: u64 = 24 * 3600;
+/// Longest delay of the welcome after the signup.
+const MAX_WELCOME_DELAY_SECS: u64 = 30 * DAY_SECS;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
+    #[serde(default)]
+    pub welcome: WelcomeConfig,
+    /// campaigns run for a single user some time after their events
+    #[serde(default)]
+    pub rules: Vec<TriggerRule>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AuthConfig {
     pub pk: String,
+    /// key of the service's own key pair, its tokens for downstream calls are signed with
+    pub sk: String,
+    /// PEM encoded public keys of the services calling with tokens of their own, by name
+    #[serde(default)]
+    pub services: HashMap<String, String>,
+    /// roles allowed to call each method, by full gRPC path
+    #[serde(default)]
+    pub policy: RolePolicy,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
+    #[serde(default = "default_port")]
     pub port: u16,
+    /// host or Unix domain socket to listen on, loopback by default
+    #[serde(default)]
+    pub listen: ListenConfig,
+    /// serve TLS, and call other services over TLS, if set
+    pub tls: Option<TlsConfig>,
+    pub db_url: String,
     pub sender_email: String,
+    /// sender number of sms messages
+    #[serde(default)]
+    pub sender_phone: String,
+    /// users personalised concurrently by a campaign
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// directory the messages of dry runs are written to
+    #[serde(default = "default_dry_run_dir")]
+    pub dry_run_dir: PathBuf,
     pub metadata: String,
     pub user_stats: String,
     pub notification: String,
 }
 
+/// Welcome of each user as they sign up, on top of the Welcome campaigns, run as the
+/// `signup-welcome` trigger rule.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WelcomeConfig {
+    /// welcome users created through UserSrv
+    #[serde(default)]
+    pub on_signup: bool,
+    /// seconds between the signup and the welcome, 30 days at most
+    #[serde(default)]
+    pub delay_secs: u64,
+    /// contents promoted by the welcome
+    #[serde(default)]
+    pub content_ids: Vec<u32>,
+    /// users messaged this often already are not welcomed, no cap if unset
+    #[serde(default)]
+    pub frequency_cap: Option<FrequencyCap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrequencyCap {
+    /// messages a user may get within the window
+    pub max_messages: u32,
+    pub within_hours: u32,
+}
+
+/// Campaign a user gets some time after an event of theirs, unless a later event cancels it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerRule {
+    /// unique, the campaigns of the rule are named after it
+    pub name: String,
+    pub event: EventName,
+    /// inactive events of fewer days don't match
+    #[serde(default)]
+    pub min_inactive_days: u32,
+    pub action: RuleAction,
+    /// seconds between the event and the campaign
+    #[serde(default)]
+    pub delay_secs: u64,
+    /// events of the user cancelling the pending campaign, for the same content if both have one
+    #[serde(default)]
+    pub cancel_on: Vec<EventName>,
+    /// contents promoted by welcome and recall, the content of the event if empty; remind
+    /// promotes the user's own unfinished contents and ignores both
+    #[serde(default)]
+    pub content_ids: Vec<u32>,
+    /// days back the campaign looks for the user in, by registration or last visit
+    #[serde(default = "default_interval_days")]
+    pub interval_days: u32,
+    /// users messaged this often already are skipped, no cap if unset
+    #[serde(default)]
+    pub frequency_cap: Option<FrequencyCap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventName {
+    UserRegistered,
+    ContentStarted,
+    ContentFinished,
+    ContentAbandoned,
+    Inactive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Welcome,
+    Recall,
+    Remind,
+}
+
+fn default_interval_days() -> u32 {
+    30
+}
+
+fn default_port() -> u16 {
+    50000
+}
+
+fn default_concurrency() -> usize {
+    16
+}
+
+fn default_dry_run_dir() -> PathBuf {
+    env::temp_dir().join("crm-dry-run")
+}
+
+impl WelcomeConfig {
+    /// The rule welcoming each user as they sign up, if enabled. Its campaign looks back far
+    /// enough for the signup once the delay is over.
+    pub fn signup_rule(&self) -> Option<TriggerRule> {
+        if !self.on_signup {
+            return None;
+        }
+        Some(TriggerRule {
+            name: SIGNUP_RULE.to_string(),
+            event: EventName::UserRegistered,
+            min_inactive_days: 0,
+            action: RuleAction::Welcome,
+            delay_secs: self.delay_secs,
+            cancel_on: vec![],
+            content_ids: self.content_ids.clone(),
+            interval_days: (self.delay_secs.min(MAX_WELCOME_DELAY_SECS) / DAY_SECS) as u32 + 1,
+            frequency_cap: self.frequency_cap,
+        })
+    }
+}
+
 impl AppConfig {
+    /// The configured rules, and the welcome of the signups if enabled.
+    pub fn trigger_rules(&self) -> Vec<TriggerRule> {
+        let signup = self.welcome.signup_rule();
+        self.rules.iter().cloned().chain(signup).collect()
+    }
+
+    /// Load `crm.yml`, or the file given by `--config` or `CRM_CONFIG`, with `CRM_*` overrides,
+    /// those of crm-metadata and crm-send aside.
     pub fn load() -> Result<Self> {
-        let config = match File::open("crm.yml") {
-            Ok(reader) => serde_yaml::from_reader(reader),
-            _ => bail!("Config file not found"),
-        };
+        Self::loader()?.load()
+    }
+
+    /// The loader of the config, honouring `--config` among the command line arguments.
+    pub fn loader() -> Result<ConfigLoader> {
+        ConfigLoader::new("crm.yml", "CRM")
+            .excluding(&["CRM_METADATA", "CRM_SEND"])
+            .with_process_args()
+    }
+}
+
+#[cfg(test)]
+impl AppConfig {
+    /// The config of the tests, trusting and signing with the test key.
+    pub fn for_test() -> Result<Self> {
+        Self::loader()?.load_for_test(&[
+            ("AUTH__PK", crm_common::TEST_PK),
+            ("AUTH__SK", crm_common::TEST_SK),
+        ])
+    }
+}
+
+impl Reload for AppConfig {
+    fn needs_restart(&self, new: &Self) -> bool {
+        let (old, new_server) = (&self.server, &new.server);
+        (old.port, &old.listen, &old.tls, &old.db_url)
+            != (
+                new_server.port,
+                &new_server.listen,
+                &new_server.tls,
+                &new_server.db_url,
+            )
+            || self.auth != new.auth
+    }
+}
+
+impl Validate for AppConfig {
+    fn validate(&self) -> Result<()> {
+        let server = &self.server;
+        check_db_url("server.db_url", &server.db_url)?;
+        check_service("server.metadata", &server.metadata)?;
+        check_service("server.user_stats", &server.user_stats)?;
+        check_service("server.notification", &server.notification)?;
+        check_tls("server.tls", server.tls.as_ref())?;
+        if server.concurrency == 0 {
+            bail!("server.concurrency must be positive");
+        }
+        check_pk("auth.pk", &self.auth.pk)?;
+        check_service_keys("auth.services", &self.auth.services)?;
+        check_sk("auth.sk", &self.auth.sk)?;
+        let services = ["crm.Crm", "user.UserSrv"];
+        check("auth.policy", self.auth.policy.check(&services))?;
+
+        let welcome = &self.welcome;
+        if welcome.delay_secs > MAX_WELCOME_DELAY_SECS {
+            bail!(
+                "welcome.delay_secs must be at most {}, got {}",
+                MAX_WELCOME_DELAY_SECS,
+                welcome.delay_secs
+            );
+        }
 
-        Ok(config?)
+        let mut names = HashSet::from([SIGNUP_RULE]);
+        for rule in &self.rules {
+            if rule.name.len() > MAX_RULE_NAME_LEN {
+                bail!(
+                    "rule names are at most {} bytes, got {:?}",
+                    MAX_RULE_NAME_LEN,
+                    rule.name
+                );
+            }
+            if rule.name.is_empty() || !names.insert(rule.name.as_str()) {
+                bail!(
+                    "rules need unique names other than {}, got {:?}",
+                    SIGNUP_RULE,
+                    rule.name
+                );
+            }
+            // the user would be welcomed twice
+            let welcomes =
+                rule.event == EventName::UserRegistered && rule.action == RuleAction::Welcome;
+            if welcome.on_signup && welcomes {
+                bail!(
+                    "rule {} welcomes the signups welcome.on_signup welcomes already, keep one",
+                    rule.name
+                );
+            }
+        }
+        Ok(())
     }
 }
@@ -3,27 +3,73 @@ mod config;
 
 pub mod pb;
 
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use abi::CampaignJob;
 use anyhow::Result;
 pub use config::*;
+use crm_common::{authorize, AuthChannel, AuthServer, Reloadable, ServiceToken};
 use crm_metadata::pb::metadata_client::MetadataClient;
 use crm_send::pb::notification_client::NotificationClient;
+use futures::Stream;
 use pb::{
     crm_server::{Crm, CrmServer},
-    RecallRequest, RecallResponse, RemindRequest, RemindResponse, WelcomeRequest, WelcomeResponse,
+    user_srv_server::{UserSrv, UserSrvServer},
+    Campaign, CampaignProgress, CampaignReport, CampaignReportRequest, CancelCampaignRequest,
+    CreateScheduleRequest, CreateUserRequest, DeleteSegmentRequest, DeleteUserRequest, Event,
+    GetCampaignRequest, GetUserRequest, IngestEventResponse, ListCampaignsRequest,
+    ListSchedulesRequest, ListSegmentsRequest, ListUsersRequest, PauseScheduleRequest,
+    RecallRequest, RecallResponse, RemindRequest, RemindResponse, ResumeScheduleRequest,
+    SaveSegmentRequest, SavedSegment, Schedule, UpdateUserRequest, User, WatchCampaignRequest,
+    WelcomeRequest, WelcomeResponse,
 };
-use tonic::{async_trait, transport::Channel, Request, Response, Status};
+use sqlx::MySqlPool;
+use tonic::{async_trait, Request, Response, Status};
 use user_stat::pb::user_stats_client::UserStatsClient;
 
-#[allow(unused)]
+type CampaignStream = Pin<Box<dyn Stream<Item = Result<Campaign, Status>> + Send>>;
+type ProgressStream = Pin<Box<dyn Stream<Item = Result<CampaignProgress, Status>> + Send>>;
+type ScheduleStream = Pin<Box<dyn Stream<Item = Result<Schedule, Status>> + Send>>;
+type SegmentStream = Pin<Box<dyn Stream<Item = Result<SavedSegment, Status>> + Send>>;
+type UserStream = Pin<Box<dyn Stream<Item = Result<User, Status>> + Send>>;
+
+#[derive(Clone)]
 pub struct CrmService {
-    config: AppConfig,
-    user_stats: UserStatsClient<Channel>,
-    notification: NotificationClient<Channel>,
-    metadata: MetadataClient<Channel>,
+    inner: Arc<CrmServiceInner>,
+}
+
+#[allow(unused)]
+pub struct CrmServiceInner {
+    config: Reloadable<AppConfig>,
+    pool: MySqlPool,
+    /// tokens of the calls to the downstream services
+    token: ServiceToken,
+    clients: Reloadable<Clients>,
+    /// campaigns running in this process, by id
+    jobs: Mutex<HashMap<String, CampaignJob>>,
+    /// id of this process among the crm instances sharing the database
+    instance: String,
+}
+
+/// Clients of the downstream services, replaced when their addresses change.
+struct Clients {
+    user_stats: UserStatsClient<AuthChannel>,
+    notification: NotificationClient<AuthChannel>,
+    metadata: MetadataClient<AuthChannel>,
 }
 
 #[async_trait]
 impl Crm for CrmService {
+    type ListCampaignsStream = CampaignStream;
+    type WatchCampaignStream = ProgressStream;
+    type ListSchedulesStream = ScheduleStream;
+    type ListSegmentsStream = SegmentStream;
+
     async fn welcome(
         &self,
         request: Request<WelcomeRequest>,
@@ -47,22 +93,289 @@ impl Crm for CrmService {
         let request = request.into_inner();
         self.remind(request).await
     }
+
+    async fn get_campaign(
+        &self,
+        request: Request<GetCampaignRequest>,
+    ) -> Result<Response<Campaign>, Status> {
+        let request = request.into_inner();
+        self.get_campaign(request).await
+    }
+
+    async fn list_campaigns(
+        &self,
+        request: Request<ListCampaignsRequest>,
+    ) -> Result<Response<Self::ListCampaignsStream>, Status> {
+        let request = request.into_inner();
+        self.list_campaigns(request).await
+    }
+
+    async fn watch_campaign(
+        &self,
+        request: Request<WatchCampaignRequest>,
+    ) -> Result<Response<Self::WatchCampaignStream>, Status> {
+        let request = request.into_inner();
+        self.watch_campaign(request).await
+    }
+
+    async fn cancel_campaign(
+        &self,
+        request: Request<CancelCampaignRequest>,
+    ) -> Result<Response<CampaignProgress>, Status> {
+        let request = request.into_inner();
+        self.cancel_campaign(request).await
+    }
+
+    async fn create_schedule(
+        &self,
+        request: Request<CreateScheduleRequest>,
+    ) -> Result<Response<Schedule>, Status> {
+        let request = request.into_inner();
+        self.create_schedule(request).await
+    }
+
+    async fn pause_schedule(
+        &self,
+        request: Request<PauseScheduleRequest>,
+    ) -> Result<Response<Schedule>, Status> {
+        let request = request.into_inner();
+        self.pause_schedule(request).await
+    }
+
+    async fn resume_schedule(
+        &self,
+        request: Request<ResumeScheduleRequest>,
+    ) -> Result<Response<Schedule>, Status> {
+        let request = request.into_inner();
+        self.resume_schedule(request).await
+    }
+
+    async fn list_schedules(
+        &self,
+        request: Request<ListSchedulesRequest>,
+    ) -> Result<Response<Self::ListSchedulesStream>, Status> {
+        let request = request.into_inner();
+        self.list_schedules(request).await
+    }
+
+    async fn get_campaign_report(
+        &self,
+        request: Request<CampaignReportRequest>,
+    ) -> Result<Response<CampaignReport>, Status> {
+        let request = request.into_inner();
+        self.get_campaign_report(request).await
+    }
+
+    async fn save_segment(
+        &self,
+        request: Request<SaveSegmentRequest>,
+    ) -> Result<Response<SavedSegment>, Status> {
+        let request = request.into_inner();
+        self.save_segment(request).await
+    }
+
+    async fn list_segments(
+        &self,
+        request: Request<ListSegmentsRequest>,
+    ) -> Result<Response<Self::ListSegmentsStream>, Status> {
+        let request = request.into_inner();
+        self.list_segments(request).await
+    }
+
+    async fn delete_segment(
+        &self,
+        request: Request<DeleteSegmentRequest>,
+    ) -> Result<Response<SavedSegment>, Status> {
+        let request = request.into_inner();
+        self.delete_segment(request).await
+    }
+
+    async fn ingest_event(
+        &self,
+        request: Request<Event>,
+    ) -> Result<Response<IngestEventResponse>, Status> {
+        let request = request.into_inner();
+        self.ingest_event(request).await
+    }
+}
+
+#[async_trait]
+impl UserSrv for CrmService {
+    type ListUsersStream = UserStream;
+
+    async fn get_user(&self, request: Request<GetUserRequest>) -> Result<Response<User>, Status> {
+        let request = request.into_inner();
+        self.get_user(request).await
+    }
+
+    async fn create_user(
+        &self,
+        request: Request<CreateUserRequest>,
+    ) -> Result<Response<User>, Status> {
+        let request = request.into_inner();
+        self.create_user(request).await
+    }
+
+    async fn update_user(
+        &self,
+        request: Request<UpdateUserRequest>,
+    ) -> Result<Response<User>, Status> {
+        let request = request.into_inner();
+        self.update_user(request).await
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<User>, Status> {
+        let request = request.into_inner();
+        self.delete_user(request).await
+    }
+
+    async fn list_users(
+        &self,
+        request: Request<ListUsersRequest>,
+    ) -> Result<Response<Self::ListUsersStream>, Status> {
+        let request = request.into_inner();
+        self.list_users(request).await
+    }
 }
 
 impl CrmService {
     pub async fn try_new(config: AppConfig) -> Result<Self> {
-        let user_stats = UserStatsClient::connect(config.server.user_stats.clone()).await?;
-        let notification = NotificationClient::connect(config.server.notification.clone()).await?;
-        let metadata = MetadataClient::connect(config.server.metadata.clone()).await?;
+        let pool = MySqlPool::connect(&config.server.db_url).await?;
+        let token = ServiceToken::load(&config.auth.sk, "crm")?;
+        let clients = Clients::connect(&token, &config.server).await?;
+        let svc = Self::from_inner(CrmServiceInner {
+            config: Reloadable::new(config),
+            pool,
+            token,
+            clients: Reloadable::new(clients),
+            jobs: Mutex::new(HashMap::new()),
+            instance: uuid::Uuid::new_v4().to_string(),
+        });
+        svc.abort_stale_campaigns().await?;
+        svc.start_heartbeat();
+        svc.start_scheduler();
+        Ok(svc)
+    }
+
+    /// The server letting through callers with a token signed with the key of the issuer or of a
+    /// trusted service, and the roles the policy asks for.
+    pub fn into_server(self) -> Result<AuthServer<CrmServer<Self>>> {
+        let auth = self.config().auth.clone();
+        authorize(CrmServer::new(self), &auth.pk, &auth.services, auth.policy)
+    }
+
+    /// The user registry, behind the same checks as `into_server`.
+    pub fn into_user_server(self) -> Result<AuthServer<UserSrvServer<Self>>> {
+        let auth = self.config().auth.clone();
+        authorize(
+            UserSrvServer::new(self),
+            &auth.pk,
+            &auth.services,
+            auth.policy,
+        )
+    }
+
+    /// Swap in a reloaded config, reconnecting to the downstream services whose address changed.
+    /// Configs changing the listen address, TLS, database or auth, set at start, are rejected.
+    pub fn reload(&self, config: AppConfig) -> Result<()> {
+        let current = self.config.check_reload(&config)?;
+        let (old, new) = (&current.server, &config.server);
+        if (&old.user_stats, &old.notification, &old.metadata)
+            != (&new.user_stats, &new.notification, &new.metadata)
+        {
+            self.clients.set(Clients::connect_lazy(&self.token, new)?);
+        }
+        self.config.set(config);
+        Ok(())
+    }
+
+    /// The current config; a campaign sticks to the one it started with.
+    pub(crate) fn config(&self) -> Arc<AppConfig> {
+        self.config.get()
+    }
+
+    pub(crate) fn user_stats(&self) -> UserStatsClient<AuthChannel> {
+        self.clients.get().user_stats.clone()
+    }
+
+    pub(crate) fn notification(&self) -> NotificationClient<AuthChannel> {
+        self.clients.get().notification.clone()
+    }
+
+    pub(crate) fn metadata(&self) -> MetadataClient<AuthChannel> {
+        self.clients.get().metadata.clone()
+    }
+
+    fn from_inner(inner: CrmServiceInner) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl Clients {
+    async fn connect(token: &ServiceToken, server: &ServerConfig) -> Result<Self> {
+        let tls = server.tls.as_ref();
+        let user_stats = token.connect(&server.user_stats, tls).await?;
+        let notification = token.connect(&server.notification, tls).await?;
+        let metadata = token.connect(&server.metadata, tls).await?;
         Ok(Self {
-            config,
-            user_stats,
-            notification,
-            metadata,
+            user_stats: UserStatsClient::new(user_stats),
+            notification: NotificationClient::new(notification),
+            metadata: MetadataClient::new(metadata),
         })
     }
 
-    pub fn into_server(self) -> CrmServer<Self> {
-        CrmServer::new(self)
+    /// Clients connecting on their first call.
+    fn connect_lazy(token: &ServiceToken, server: &ServerConfig) -> Result<Self> {
+        let tls = server.tls.as_ref();
+        let lazy = |addr: &str| token.connect_lazy(addr, tls);
+        Ok(Self {
+            user_stats: UserStatsClient::new(lazy(&server.user_stats)?),
+            notification: NotificationClient::new(lazy(&server.notification)?),
+            metadata: MetadataClient::new(lazy(&server.metadata)?),
+        })
+    }
+}
+
+impl Deref for CrmService {
+    type Target = CrmServiceInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod test_utils {
+    use std::{env, path::Path};
+
+    use crm_common::TestMysql;
+
+    use super::*;
+
+    impl CrmService {
+        /// A service backed by a test database, with downstream clients connected lazily.
+        pub async fn new_for_test() -> Result<(TestMysql, Self)> {
+            let config = AppConfig::for_test()?;
+            let p = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("migrations");
+            let tdb = TestMysql::new("localhost", 3306, "root", "123456", p);
+            let pool = tdb.get_pool().await;
+
+            let token = ServiceToken::for_test("crm");
+            let clients = Clients::connect_lazy(&token, &config.server)?;
+            let svc = Self::from_inner(CrmServiceInner {
+                config: Reloadable::new(config),
+                pool,
+                token,
+                clients: Reloadable::new(clients),
+                jobs: Mutex::new(HashMap::new()),
+                instance: uuid::Uuid::new_v4().to_string(),
+            });
+            Ok((tdb, svc))
+        }
     }
 }
@@ -1,4 +1,147 @@
 // This file is @generated by prost-build.
+/// how to reach users of a campaign, email only if not set
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChannelPolicy {
+    #[prost(enumeration = "DeliveryMode", tag = "1")]
+    pub mode: i32,
+    /// channels in order of preference
+    #[prost(enumeration = "Channel", repeated, tag = "2")]
+    pub channels: ::prost::alloc::vec::Vec<i32>,
+}
+/// a variant of the message of a campaign
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Variant {
+    /// unique in the experiment, `holdout` is reserved
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// share of the users, relative to the other variants and the holdout
+    #[prost(uint32, tag = "2")]
+    pub weight: u32,
+    /// template to render, the template of the campaign if empty
+    #[prost(string, tag = "3")]
+    pub template: ::prost::alloc::string::String,
+    /// contents to promote, the contents of the campaign if empty
+    #[prost(uint32, repeated, tag = "4")]
+    pub content_ids: ::prost::alloc::vec::Vec<u32>,
+}
+/// split the users of a campaign into variants and a holdout that receives nothing
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Experiment {
+    /// salt of the assignment, users keep their variant across campaigns with the same name;
+    /// the campaign id if empty
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub variants: ::prost::alloc::vec::Vec<Variant>,
+    /// share of the users held out
+    #[prost(uint32, tag = "3")]
+    pub holdout_weight: u32,
+}
+/// users to target, combining conditions with and, or and not
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Segment {
+    #[prost(oneof = "segment::Expr", tags = "1, 2, 3, 4, 5, 6, 7, 8")]
+    pub expr: ::core::option::Option<segment::Expr>,
+}
+/// Nested message and enum types in `Segment`.
+pub mod segment {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Expr {
+        /// all hold, every user if empty
+        #[prost(message, tag = "1")]
+        All(super::SegmentList),
+        /// any holds, no user if empty
+        #[prost(message, tag = "2")]
+        Any(super::SegmentList),
+        #[prost(message, tag = "3")]
+        Not(::prost::alloc::boxed::Box<super::Segment>),
+        #[prost(message, tag = "4")]
+        Days(super::DaysRange),
+        #[prost(message, tag = "5")]
+        Ids(super::IdMembership),
+        /// M, F or U
+        #[prost(string, tag = "6")]
+        Gender(::prost::alloc::string::String),
+        /// notified on the channel in the last days
+        #[prost(message, tag = "7")]
+        Notified(super::Notified),
+        /// name of a saved segment
+        #[prost(string, tag = "8")]
+        Saved(::prost::alloc::string::String),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SegmentList {
+    #[prost(message, repeated, tag = "1")]
+    pub segments: ::prost::alloc::vec::Vec<Segment>,
+}
+/// a time column between the given days ago, unbounded if unset
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DaysRange {
+    /// created_at, last_visited_at, last_watched_at, ..
+    #[prost(string, tag = "1")]
+    pub column: ::prost::alloc::string::String,
+    #[prost(uint32, optional, tag = "2")]
+    pub from_days_ago: ::core::option::Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub to_days_ago: ::core::option::Option<u32>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IdMembership {
+    /// recent_watched, viewed_but_not_started, started_but_not_finished or finished
+    #[prost(string, tag = "1")]
+    pub column: ::prost::alloc::string::String,
+    /// any of the ids is in the list
+    #[prost(uint32, repeated, tag = "2")]
+    pub ids: ::prost::alloc::vec::Vec<u32>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct Notified {
+    #[prost(enumeration = "Channel", tag = "1")]
+    pub channel: i32,
+    #[prost(uint32, tag = "2")]
+    pub within_days: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SavedSegment {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub description: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub segment: ::core::option::Option<Segment>,
+    #[prost(message, optional, tag = "4")]
+    pub updated_at: ::core::option::Option<::prost_types::Timestamp>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SaveSegmentRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub description: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub segment: ::core::option::Option<Segment>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ListSegmentsRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteSegmentRequest {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct WelcomeRequest {
@@ -9,12 +152,28 @@ pub struct WelcomeRequest {
     pub interval: u32,
     #[prost(uint32, repeated, tag = "3")]
     pub content_ids: ::prost::alloc::vec::Vec<u32>,
+    #[prost(message, optional, tag = "4")]
+    pub channels: ::core::option::Option<ChannelPolicy>,
+    /// render the messages into a file instead of sending them
+    #[prost(bool, tag = "5")]
+    pub dry_run: bool,
+    #[prost(message, optional, tag = "6")]
+    pub experiment: ::core::option::Option<Experiment>,
+    /// narrows the users registered in the interval
+    #[prost(message, optional, tag = "7")]
+    pub segment: ::core::option::Option<Segment>,
+    /// only the users with these emails, e.g. a user who just signed up, no restriction if empty
+    #[prost(string, repeated, tag = "8")]
+    pub emails: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct WelcomeResponse {
     #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
+    /// the campaign runs in background once accepted
+    #[prost(enumeration = "CampaignStatus", tag = "2")]
+    pub status: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -25,12 +184,28 @@ pub struct RecallRequest {
     pub last_visit_interval: u32,
     #[prost(uint32, repeated, tag = "3")]
     pub content_ids: ::prost::alloc::vec::Vec<u32>,
+    #[prost(message, optional, tag = "4")]
+    pub channels: ::core::option::Option<ChannelPolicy>,
+    /// render the messages into a file instead of sending them
+    #[prost(bool, tag = "5")]
+    pub dry_run: bool,
+    #[prost(message, optional, tag = "6")]
+    pub experiment: ::core::option::Option<Experiment>,
+    /// narrows the users visited in the interval
+    #[prost(message, optional, tag = "7")]
+    pub segment: ::core::option::Option<Segment>,
+    /// only the users with these emails, no restriction if empty
+    #[prost(string, repeated, tag = "8")]
+    pub emails: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct RecallResponse {
     #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
+    /// the campaign runs in background once accepted
+    #[prost(enumeration = "CampaignStatus", tag = "2")]
+    pub status: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -39,12 +214,585 @@ pub struct RemindRequest {
     pub id: ::prost::alloc::string::String,
     #[prost(uint32, tag = "2")]
     pub last_visit_interval: u32,
+    #[prost(message, optional, tag = "3")]
+    pub channels: ::core::option::Option<ChannelPolicy>,
+    /// render the messages into a file instead of sending them
+    #[prost(bool, tag = "4")]
+    pub dry_run: bool,
+    /// variants can't set contents, each user is reminded of their own
+    #[prost(message, optional, tag = "5")]
+    pub experiment: ::core::option::Option<Experiment>,
+    /// narrows the users visited in the interval
+    #[prost(message, optional, tag = "6")]
+    pub segment: ::core::option::Option<Segment>,
+    /// only the users with these emails, no restriction if empty
+    #[prost(string, repeated, tag = "7")]
+    pub emails: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct RemindResponse {
     #[prost(string, tag = "1")]
     pub id: ::prost::alloc::string::String,
+    /// the campaign runs in background once accepted
+    #[prost(enumeration = "CampaignStatus", tag = "2")]
+    pub status: i32,
+}
+/// the request a campaign is started with
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CampaignParams {
+    #[prost(oneof = "campaign_params::Request", tags = "1, 2, 3")]
+    pub request: ::core::option::Option<campaign_params::Request>,
+}
+/// Nested message and enum types in `CampaignParams`.
+pub mod campaign_params {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Request {
+        #[prost(message, tag = "1")]
+        Welcome(super::WelcomeRequest),
+        #[prost(message, tag = "2")]
+        Recall(super::RecallRequest),
+        #[prost(message, tag = "3")]
+        Remind(super::RemindRequest),
+    }
+}
+/// a campaign run, keyed by the id of its request
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Campaign {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    #[prost(enumeration = "CampaignKind", tag = "2")]
+    pub kind: i32,
+    #[prost(message, optional, tag = "3")]
+    pub params: ::core::option::Option<CampaignParams>,
+    #[prost(enumeration = "CampaignStatus", tag = "4")]
+    pub status: i32,
+    #[prost(message, optional, tag = "5")]
+    pub started_at: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(message, optional, tag = "6")]
+    pub finished_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// users returned by user-stat for the campaign
+    #[prost(uint64, tag = "7")]
+    pub users_matched: u64,
+    /// messages accepted by crm-send
+    #[prost(uint64, tag = "8")]
+    pub messages_sent: u64,
+    /// messages failed to build or to send
+    #[prost(uint64, tag = "9")]
+    pub messages_failed: u64,
+    /// why the campaign failed, empty otherwise
+    #[prost(string, tag = "10")]
+    pub error: ::prost::alloc::string::String,
+    /// messages handed over to crm-send, or written to the output of a dry run
+    #[prost(uint64, tag = "11")]
+    pub messages_queued: u64,
+    /// file the messages of a dry run are written to, one JSON object per line
+    #[prost(string, tag = "12")]
+    pub output: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetCampaignRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ListCampaignsRequest {
+    /// unspecified for all kinds
+    #[prost(enumeration = "CampaignKind", tag = "1")]
+    pub kind: i32,
+    /// 0 for the default page size
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+    #[prost(uint32, tag = "3")]
+    pub offset: u32,
+}
+/// progress of a campaign run
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CampaignProgress {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    #[prost(enumeration = "CampaignStatus", tag = "2")]
+    pub status: i32,
+    #[prost(uint64, tag = "3")]
+    pub users_scanned: u64,
+    #[prost(uint64, tag = "4")]
+    pub messages_queued: u64,
+    #[prost(uint64, tag = "5")]
+    pub messages_delivered: u64,
+    #[prost(uint64, tag = "6")]
+    pub messages_failed: u64,
+    #[prost(string, tag = "7")]
+    pub error: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WatchCampaignRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelCampaignRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+/// a campaign run automatically by crm, each run gets a new campaign id
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Schedule {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// the id in the params is replaced by the id of each run
+    #[prost(message, optional, tag = "2")]
+    pub params: ::core::option::Option<CampaignParams>,
+    #[prost(enumeration = "ScheduleStatus", tag = "5")]
+    pub status: i32,
+    #[prost(message, optional, tag = "6")]
+    pub next_run_at: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(message, optional, tag = "7")]
+    pub last_run_at: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(string, tag = "8")]
+    pub last_campaign_id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "9")]
+    pub created_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// why the campaign of the last run failed to start, empty once one starts
+    #[prost(string, tag = "10")]
+    pub last_error: ::prost::alloc::string::String,
+    #[prost(oneof = "schedule::Trigger", tags = "3, 4")]
+    pub trigger: ::core::option::Option<schedule::Trigger>,
+}
+/// Nested message and enum types in `Schedule`.
+pub mod schedule {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Trigger {
+        /// cron expression in UTC, with or without the seconds field, e.g. `0 9 * * MON`
+        #[prost(string, tag = "3")]
+        Cron(::prost::alloc::string::String),
+        /// seconds between two runs
+        #[prost(uint32, tag = "4")]
+        Interval(u32),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateScheduleRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub params: ::core::option::Option<CampaignParams>,
+    #[prost(oneof = "create_schedule_request::Trigger", tags = "3, 4")]
+    pub trigger: ::core::option::Option<create_schedule_request::Trigger>,
+}
+/// Nested message and enum types in `CreateScheduleRequest`.
+pub mod create_schedule_request {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Trigger {
+        #[prost(string, tag = "3")]
+        Cron(::prost::alloc::string::String),
+        #[prost(uint32, tag = "4")]
+        Interval(u32),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PauseScheduleRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResumeScheduleRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ListSchedulesRequest {
+    /// list paused schedules as well
+    #[prost(bool, tag = "1")]
+    pub include_paused: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CampaignReportRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// ignored: user-stat keeps the latest activity only, so all activity since the campaign
+    /// started is attributed to it
+    #[deprecated]
+    #[prost(uint32, tag = "2")]
+    pub window_days: u32,
+}
+/// activity of a group of users since the campaign started
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GroupReport {
+    /// experiment variant, `holdout` for the holdout, empty without experiment
+    #[prost(string, tag = "1")]
+    pub variant: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub users: u64,
+    #[prost(uint64, tag = "3")]
+    pub visited: u64,
+    #[prost(double, tag = "4")]
+    pub visit_rate: f64,
+    /// users who watched anything
+    #[prost(uint64, tag = "5")]
+    pub watched: u64,
+    #[prost(double, tag = "6")]
+    pub watch_rate: f64,
+    /// relative to the holdout, e.g. 0.1 is 10% higher; 0 without holdout
+    #[prost(double, tag = "7")]
+    pub visit_lift: f64,
+    #[prost(double, tag = "8")]
+    pub watch_lift: f64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ContentConversion {
+    #[prost(uint32, tag = "1")]
+    pub content_id: u32,
+    /// recipients the content was promoted to
+    #[prost(uint64, tag = "2")]
+    pub recipients: u64,
+    /// recipients who recently watched it
+    #[prost(uint64, tag = "3")]
+    pub watched: u64,
+    #[prost(double, tag = "4")]
+    pub watch_rate: f64,
+}
+/// user-stat keeps the latest visit and watch only, a user counts as active when either is
+/// after the campaign started: the later the report, the more unrelated activity it includes
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CampaignReport {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// when the campaign started
+    #[prost(message, optional, tag = "2")]
+    pub window_start: ::core::option::Option<::prost_types::Timestamp>,
+    /// when the activity was read from user-stat
+    #[prost(message, optional, tag = "3")]
+    pub window_end: ::core::option::Option<::prost_types::Timestamp>,
+    /// all recipients
+    #[prost(message, optional, tag = "4")]
+    pub recipients: ::core::option::Option<GroupReport>,
+    /// recipients by variant, then the holdout
+    #[prost(message, repeated, tag = "5")]
+    pub groups: ::prost::alloc::vec::Vec<GroupReport>,
+    /// by promoted content id
+    #[prost(message, repeated, tag = "6")]
+    pub contents: ::prost::alloc::vec::Vec<ContentConversion>,
+}
+/// something a user did, or stopped doing, that trigger rules may act on
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Event {
+    #[prost(enumeration = "EventKind", tag = "1")]
+    pub kind: i32,
+    #[prost(string, tag = "2")]
+    pub email: ::prost::alloc::string::String,
+    /// content started, finished or abandoned, 0 otherwise
+    #[prost(uint32, tag = "3")]
+    pub content_id: u32,
+    /// days the user has been inactive for
+    #[prost(uint32, tag = "4")]
+    pub inactive_days: u32,
+    /// now if unset, the delays of the rules count from it
+    #[prost(message, optional, tag = "5")]
+    pub occurred_at: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// a campaign for a single user, run by a trigger rule once due
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TriggeredAction {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    /// name of the rule
+    #[prost(string, tag = "2")]
+    pub rule: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub email: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "4")]
+    pub content_id: u32,
+    #[prost(enumeration = "TriggeredActionStatus", tag = "5")]
+    pub status: i32,
+    #[prost(message, optional, tag = "6")]
+    pub due_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// id of the campaign once fired
+    #[prost(string, tag = "7")]
+    pub campaign_id: ::prost::alloc::string::String,
+    /// why its campaign failed to start last, if it did
+    #[prost(string, tag = "8")]
+    pub error: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IngestEventResponse {
+    /// actions of the rules the event matched
+    #[prost(message, repeated, tag = "1")]
+    pub scheduled: ::prost::alloc::vec::Vec<TriggeredAction>,
+    /// pending actions of the user the event cancelled
+    #[prost(uint32, tag = "2")]
+    pub cancelled: u32,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Channel {
+    Unspecified = 0,
+    Email = 1,
+    Sms = 2,
+    InApp = 3,
+}
+impl Channel {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Channel::Unspecified => "CHANNEL_UNSPECIFIED",
+            Channel::Email => "CHANNEL_EMAIL",
+            Channel::Sms => "CHANNEL_SMS",
+            Channel::InApp => "CHANNEL_IN_APP",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CHANNEL_UNSPECIFIED" => Some(Self::Unspecified),
+            "CHANNEL_EMAIL" => Some(Self::Email),
+            "CHANNEL_SMS" => Some(Self::Sms),
+            "CHANNEL_IN_APP" => Some(Self::InApp),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DeliveryMode {
+    /// same as single
+    Unspecified = 0,
+    /// the first channel only
+    Single = 1,
+    /// the first channel the user can be reached on
+    Fallback = 2,
+    /// every channel the user can be reached on
+    All = 3,
+}
+impl DeliveryMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DeliveryMode::Unspecified => "DELIVERY_MODE_UNSPECIFIED",
+            DeliveryMode::Single => "DELIVERY_MODE_SINGLE",
+            DeliveryMode::Fallback => "DELIVERY_MODE_FALLBACK",
+            DeliveryMode::All => "DELIVERY_MODE_ALL",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DELIVERY_MODE_UNSPECIFIED" => Some(Self::Unspecified),
+            "DELIVERY_MODE_SINGLE" => Some(Self::Single),
+            "DELIVERY_MODE_FALLBACK" => Some(Self::Fallback),
+            "DELIVERY_MODE_ALL" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CampaignKind {
+    Unspecified = 0,
+    Welcome = 1,
+    Recall = 2,
+    Remind = 3,
+}
+impl CampaignKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CampaignKind::Unspecified => "CAMPAIGN_KIND_UNSPECIFIED",
+            CampaignKind::Welcome => "CAMPAIGN_KIND_WELCOME",
+            CampaignKind::Recall => "CAMPAIGN_KIND_RECALL",
+            CampaignKind::Remind => "CAMPAIGN_KIND_REMIND",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CAMPAIGN_KIND_UNSPECIFIED" => Some(Self::Unspecified),
+            "CAMPAIGN_KIND_WELCOME" => Some(Self::Welcome),
+            "CAMPAIGN_KIND_RECALL" => Some(Self::Recall),
+            "CAMPAIGN_KIND_REMIND" => Some(Self::Remind),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CampaignStatus {
+    Unspecified = 0,
+    Running = 1,
+    Completed = 2,
+    Failed = 3,
+    /// accepted and waiting to run
+    Accepted = 4,
+    Cancelled = 5,
+}
+impl CampaignStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CampaignStatus::Unspecified => "CAMPAIGN_STATUS_UNSPECIFIED",
+            CampaignStatus::Running => "CAMPAIGN_STATUS_RUNNING",
+            CampaignStatus::Completed => "CAMPAIGN_STATUS_COMPLETED",
+            CampaignStatus::Failed => "CAMPAIGN_STATUS_FAILED",
+            CampaignStatus::Accepted => "CAMPAIGN_STATUS_ACCEPTED",
+            CampaignStatus::Cancelled => "CAMPAIGN_STATUS_CANCELLED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CAMPAIGN_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "CAMPAIGN_STATUS_RUNNING" => Some(Self::Running),
+            "CAMPAIGN_STATUS_COMPLETED" => Some(Self::Completed),
+            "CAMPAIGN_STATUS_FAILED" => Some(Self::Failed),
+            "CAMPAIGN_STATUS_ACCEPTED" => Some(Self::Accepted),
+            "CAMPAIGN_STATUS_CANCELLED" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ScheduleStatus {
+    Unspecified = 0,
+    Active = 1,
+    Paused = 2,
+}
+impl ScheduleStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ScheduleStatus::Unspecified => "SCHEDULE_STATUS_UNSPECIFIED",
+            ScheduleStatus::Active => "SCHEDULE_STATUS_ACTIVE",
+            ScheduleStatus::Paused => "SCHEDULE_STATUS_PAUSED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SCHEDULE_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "SCHEDULE_STATUS_ACTIVE" => Some(Self::Active),
+            "SCHEDULE_STATUS_PAUSED" => Some(Self::Paused),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum EventKind {
+    Unspecified = 0,
+    UserRegistered = 1,
+    ContentStarted = 2,
+    ContentFinished = 3,
+    ContentAbandoned = 4,
+    /// the user has not visited for some days
+    Inactive = 5,
+}
+impl EventKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            EventKind::Unspecified => "EVENT_KIND_UNSPECIFIED",
+            EventKind::UserRegistered => "EVENT_KIND_USER_REGISTERED",
+            EventKind::ContentStarted => "EVENT_KIND_CONTENT_STARTED",
+            EventKind::ContentFinished => "EVENT_KIND_CONTENT_FINISHED",
+            EventKind::ContentAbandoned => "EVENT_KIND_CONTENT_ABANDONED",
+            EventKind::Inactive => "EVENT_KIND_INACTIVE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "EVENT_KIND_UNSPECIFIED" => Some(Self::Unspecified),
+            "EVENT_KIND_USER_REGISTERED" => Some(Self::UserRegistered),
+            "EVENT_KIND_CONTENT_STARTED" => Some(Self::ContentStarted),
+            "EVENT_KIND_CONTENT_FINISHED" => Some(Self::ContentFinished),
+            "EVENT_KIND_CONTENT_ABANDONED" => Some(Self::ContentAbandoned),
+            "EVENT_KIND_INACTIVE" => Some(Self::Inactive),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TriggeredActionStatus {
+    Unspecified = 0,
+    /// waiting for its due time
+    Pending = 1,
+    /// the campaign of the action was started
+    Fired = 2,
+    /// cancelled by a later event of the user, or skipped once due
+    Cancelled = 3,
+    /// claimed by an instance starting its campaign
+    Firing = 4,
+    /// its campaign failed to start too many times
+    Failed = 5,
+}
+impl TriggeredActionStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            TriggeredActionStatus::Unspecified => "TRIGGERED_ACTION_STATUS_UNSPECIFIED",
+            TriggeredActionStatus::Pending => "TRIGGERED_ACTION_STATUS_PENDING",
+            TriggeredActionStatus::Fired => "TRIGGERED_ACTION_STATUS_FIRED",
+            TriggeredActionStatus::Cancelled => "TRIGGERED_ACTION_STATUS_CANCELLED",
+            TriggeredActionStatus::Firing => "TRIGGERED_ACTION_STATUS_FIRING",
+            TriggeredActionStatus::Failed => "TRIGGERED_ACTION_STATUS_FAILED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "TRIGGERED_ACTION_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "TRIGGERED_ACTION_STATUS_PENDING" => Some(Self::Pending),
+            "TRIGGERED_ACTION_STATUS_FIRED" => Some(Self::Fired),
+            "TRIGGERED_ACTION_STATUS_CANCELLED" => Some(Self::Cancelled),
+            "TRIGGERED_ACTION_STATUS_FIRING" => Some(Self::Firing),
+            "TRIGGERED_ACTION_STATUS_FAILED" => Some(Self::Failed),
+            _ => None,
+        }
+    }
 }
 /// Generated client implementations.
 pub mod crm_client {
@@ -181,61 +929,383 @@ pub mod crm_client {
                 .insert(GrpcMethod::new("crm.Crm", "Remind"));
             self.inner.unary(req, path, codec).await
         }
-    }
-}
-/// Generated server implementations.
-pub mod crm_server {
-    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
-    use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with CrmServer.
-    #[async_trait]
-    pub trait Crm: Send + Sync + 'static {
-        /// user has registered X days ago, give them a welcome message
-        async fn welcome(
-            &self,
-            request: tonic::Request<super::WelcomeRequest>,
-        ) -> std::result::Result<tonic::Response<super::WelcomeResponse>, tonic::Status>;
-        /// last watched in X days, given them something to watch
-        async fn recall(
-            &self,
-            request: tonic::Request<super::RecallRequest>,
-        ) -> std::result::Result<tonic::Response<super::RecallResponse>, tonic::Status>;
-        /// last watched in X days, and user still have unfinished contents
-        async fn remind(
-            &self,
-            request: tonic::Request<super::RemindRequest>,
-        ) -> std::result::Result<tonic::Response<super::RemindResponse>, tonic::Status>;
-    }
-    #[derive(Debug)]
-    pub struct CrmServer<T: Crm> {
-        inner: Arc<T>,
-        accept_compression_encodings: EnabledCompressionEncodings,
-        send_compression_encodings: EnabledCompressionEncodings,
-        max_decoding_message_size: Option<usize>,
-        max_encoding_message_size: Option<usize>,
-    }
-    impl<T: Crm> CrmServer<T> {
-        pub fn new(inner: T) -> Self {
-            Self::from_arc(Arc::new(inner))
+        /// get a campaign run by the id of its request
+        pub async fn get_campaign(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetCampaignRequest>,
+        ) -> std::result::Result<tonic::Response<super::Campaign>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/GetCampaign");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "GetCampaign"));
+            self.inner.unary(req, path, codec).await
         }
-        pub fn from_arc(inner: Arc<T>) -> Self {
-            Self {
-                inner,
-                accept_compression_encodings: Default::default(),
-                send_compression_encodings: Default::default(),
-                max_decoding_message_size: None,
-                max_encoding_message_size: None,
-            }
+        /// list campaign runs, latest first
+        pub async fn list_campaigns(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListCampaignsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::Campaign>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/ListCampaigns");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "ListCampaigns"));
+            self.inner.server_streaming(req, path, codec).await
         }
-        pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
-        where
-            F: tonic::service::Interceptor,
-        {
-            InterceptedService::new(Self::new(inner), interceptor)
+        /// stream the progress of a campaign until it finishes
+        pub async fn watch_campaign(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchCampaignRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::CampaignProgress>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/WatchCampaign");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "WatchCampaign"));
+            self.inner.server_streaming(req, path, codec).await
         }
-        /// Enable decompressing requests with the given encoding.
-        #[must_use]
-        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+        /// stop a running campaign
+        pub async fn cancel_campaign(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CancelCampaignRequest>,
+        ) -> std::result::Result<tonic::Response<super::CampaignProgress>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/CancelCampaign");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "CancelCampaign"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// run a campaign on a cron or interval schedule
+        pub async fn create_schedule(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateScheduleRequest>,
+        ) -> std::result::Result<tonic::Response<super::Schedule>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/CreateSchedule");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "CreateSchedule"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn pause_schedule(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PauseScheduleRequest>,
+        ) -> std::result::Result<tonic::Response<super::Schedule>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/PauseSchedule");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "PauseSchedule"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn resume_schedule(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ResumeScheduleRequest>,
+        ) -> std::result::Result<tonic::Response<super::Schedule>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/ResumeSchedule");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "ResumeSchedule"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn list_schedules(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListSchedulesRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::Schedule>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/ListSchedules");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "ListSchedules"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        /// how the recipients of a campaign visited and watched afterwards
+        pub async fn get_campaign_report(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CampaignReportRequest>,
+        ) -> std::result::Result<tonic::Response<super::CampaignReport>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/GetCampaignReport");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "GetCampaignReport"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// save a named segment campaigns can refer to, replacing the one of the same name
+        pub async fn save_segment(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SaveSegmentRequest>,
+        ) -> std::result::Result<tonic::Response<super::SavedSegment>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/SaveSegment");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "SaveSegment"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn list_segments(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListSegmentsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::SavedSegment>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/ListSegments");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "ListSegments"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        pub async fn delete_segment(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteSegmentRequest>,
+        ) -> std::result::Result<tonic::Response<super::SavedSegment>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/DeleteSegment");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "DeleteSegment"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// record an event of a user, scheduling the actions of the trigger rules it matches
+        pub async fn ingest_event(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Event>,
+        ) -> std::result::Result<tonic::Response<super::IngestEventResponse>, tonic::Status>
+        {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/crm.Crm/IngestEvent");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("crm.Crm", "IngestEvent"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod crm_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with CrmServer.
+    #[async_trait]
+    pub trait Crm: Send + Sync + 'static {
+        /// user has registered X days ago, give them a welcome message
+        async fn welcome(
+            &self,
+            request: tonic::Request<super::WelcomeRequest>,
+        ) -> std::result::Result<tonic::Response<super::WelcomeResponse>, tonic::Status>;
+        /// last watched in X days, given them something to watch
+        async fn recall(
+            &self,
+            request: tonic::Request<super::RecallRequest>,
+        ) -> std::result::Result<tonic::Response<super::RecallResponse>, tonic::Status>;
+        /// last watched in X days, and user still have unfinished contents
+        async fn remind(
+            &self,
+            request: tonic::Request<super::RemindRequest>,
+        ) -> std::result::Result<tonic::Response<super::RemindResponse>, tonic::Status>;
+        /// get a campaign run by the id of its request
+        async fn get_campaign(
+            &self,
+            request: tonic::Request<super::GetCampaignRequest>,
+        ) -> std::result::Result<tonic::Response<super::Campaign>, tonic::Status>;
+        /// Server streaming response type for the ListCampaigns method.
+        type ListCampaignsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::Campaign, tonic::Status>,
+            > + Send
+            + 'static;
+        /// list campaign runs, latest first
+        async fn list_campaigns(
+            &self,
+            request: tonic::Request<super::ListCampaignsRequest>,
+        ) -> std::result::Result<tonic::Response<Self::ListCampaignsStream>, tonic::Status>;
+        /// Server streaming response type for the WatchCampaign method.
+        type WatchCampaignStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::CampaignProgress, tonic::Status>,
+            > + Send
+            + 'static;
+        /// stream the progress of a campaign until it finishes
+        async fn watch_campaign(
+            &self,
+            request: tonic::Request<super::WatchCampaignRequest>,
+        ) -> std::result::Result<tonic::Response<Self::WatchCampaignStream>, tonic::Status>;
+        /// stop a running campaign
+        async fn cancel_campaign(
+            &self,
+            request: tonic::Request<super::CancelCampaignRequest>,
+        ) -> std::result::Result<tonic::Response<super::CampaignProgress>, tonic::Status>;
+        /// run a campaign on a cron or interval schedule
+        async fn create_schedule(
+            &self,
+            request: tonic::Request<super::CreateScheduleRequest>,
+        ) -> std::result::Result<tonic::Response<super::Schedule>, tonic::Status>;
+        async fn pause_schedule(
+            &self,
+            request: tonic::Request<super::PauseScheduleRequest>,
+        ) -> std::result::Result<tonic::Response<super::Schedule>, tonic::Status>;
+        async fn resume_schedule(
+            &self,
+            request: tonic::Request<super::ResumeScheduleRequest>,
+        ) -> std::result::Result<tonic::Response<super::Schedule>, tonic::Status>;
+        /// Server streaming response type for the ListSchedules method.
+        type ListSchedulesStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::Schedule, tonic::Status>,
+            > + Send
+            + 'static;
+        async fn list_schedules(
+            &self,
+            request: tonic::Request<super::ListSchedulesRequest>,
+        ) -> std::result::Result<tonic::Response<Self::ListSchedulesStream>, tonic::Status>;
+        /// how the recipients of a campaign visited and watched afterwards
+        async fn get_campaign_report(
+            &self,
+            request: tonic::Request<super::CampaignReportRequest>,
+        ) -> std::result::Result<tonic::Response<super::CampaignReport>, tonic::Status>;
+        /// save a named segment campaigns can refer to, replacing the one of the same name
+        async fn save_segment(
+            &self,
+            request: tonic::Request<super::SaveSegmentRequest>,
+        ) -> std::result::Result<tonic::Response<super::SavedSegment>, tonic::Status>;
+        /// Server streaming response type for the ListSegments method.
+        type ListSegmentsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::SavedSegment, tonic::Status>,
+            > + Send
+            + 'static;
+        async fn list_segments(
+            &self,
+            request: tonic::Request<super::ListSegmentsRequest>,
+        ) -> std::result::Result<tonic::Response<Self::ListSegmentsStream>, tonic::Status>;
+        async fn delete_segment(
+            &self,
+            request: tonic::Request<super::DeleteSegmentRequest>,
+        ) -> std::result::Result<tonic::Response<super::SavedSegment>, tonic::Status>;
+        /// record an event of a user, scheduling the actions of the trigger rules it matches
+        async fn ingest_event(
+            &self,
+            request: tonic::Request<super::Event>,
+        ) -> std::result::Result<tonic::Response<super::IngestEventResponse>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct CrmServer<T: Crm> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T: Crm> CrmServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
             self.accept_compression_encodings.enable(encoding);
             self
         }
@@ -390,6 +1460,514 @@ pub mod crm_server {
                     };
                     Box::pin(fut)
                 }
+                "/crm.Crm/GetCampaign" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetCampaignSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::GetCampaignRequest> for GetCampaignSvc<T> {
+                        type Response = super::Campaign;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetCampaignRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::get_campaign(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetCampaignSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/ListCampaigns" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListCampaignsSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::ServerStreamingService<super::ListCampaignsRequest>
+                        for ListCampaignsSvc<T>
+                    {
+                        type Response = super::Campaign;
+                        type ResponseStream = T::ListCampaignsStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListCampaignsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::list_campaigns(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListCampaignsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/WatchCampaign" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchCampaignSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::ServerStreamingService<super::WatchCampaignRequest>
+                        for WatchCampaignSvc<T>
+                    {
+                        type Response = super::CampaignProgress;
+                        type ResponseStream = T::WatchCampaignStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchCampaignRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::watch_campaign(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = WatchCampaignSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/CancelCampaign" => {
+                    #[allow(non_camel_case_types)]
+                    struct CancelCampaignSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::CancelCampaignRequest> for CancelCampaignSvc<T> {
+                        type Response = super::CampaignProgress;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CancelCampaignRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::cancel_campaign(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CancelCampaignSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/CreateSchedule" => {
+                    #[allow(non_camel_case_types)]
+                    struct CreateScheduleSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::CreateScheduleRequest> for CreateScheduleSvc<T> {
+                        type Response = super::Schedule;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateScheduleRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::create_schedule(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CreateScheduleSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/PauseSchedule" => {
+                    #[allow(non_camel_case_types)]
+                    struct PauseScheduleSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::PauseScheduleRequest> for PauseScheduleSvc<T> {
+                        type Response = super::Schedule;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PauseScheduleRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::pause_schedule(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = PauseScheduleSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/ResumeSchedule" => {
+                    #[allow(non_camel_case_types)]
+                    struct ResumeScheduleSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::ResumeScheduleRequest> for ResumeScheduleSvc<T> {
+                        type Response = super::Schedule;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ResumeScheduleRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::resume_schedule(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ResumeScheduleSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/ListSchedules" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListSchedulesSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::ServerStreamingService<super::ListSchedulesRequest>
+                        for ListSchedulesSvc<T>
+                    {
+                        type Response = super::Schedule;
+                        type ResponseStream = T::ListSchedulesStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListSchedulesRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::list_schedules(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListSchedulesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/GetCampaignReport" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetCampaignReportSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::CampaignReportRequest> for GetCampaignReportSvc<T> {
+                        type Response = super::CampaignReport;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CampaignReportRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Crm>::get_campaign_report(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetCampaignReportSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/SaveSegment" => {
+                    #[allow(non_camel_case_types)]
+                    struct SaveSegmentSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::SaveSegmentRequest> for SaveSegmentSvc<T> {
+                        type Response = super::SavedSegment;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SaveSegmentRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::save_segment(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SaveSegmentSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/ListSegments" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListSegmentsSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::ServerStreamingService<super::ListSegmentsRequest>
+                        for ListSegmentsSvc<T>
+                    {
+                        type Response = super::SavedSegment;
+                        type ResponseStream = T::ListSegmentsStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListSegmentsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::list_segments(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ListSegmentsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/DeleteSegment" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteSegmentSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::DeleteSegmentRequest> for DeleteSegmentSvc<T> {
+                        type Response = super::SavedSegment;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteSegmentRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::delete_segment(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = DeleteSegmentSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/crm.Crm/IngestEvent" => {
+                    #[allow(non_camel_case_types)]
+                    struct IngestEventSvc<T: Crm>(pub Arc<T>);
+                    impl<T: Crm> tonic::server::UnaryService<super::Event> for IngestEventSvc<T> {
+                        type Response = super::IngestEventResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(&mut self, request: tonic::Request<super::Event>) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as Crm>::ingest_event(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = IngestEventSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
@@ -10,7 +10,6 @@ fn main() -> Result<()> {
         .build_client(true)
         .build_server(true)
         .out_dir("src/pb")
-        .with_sqlx_from_row(&["User"], None)
         .compile(
             &[
                 "../protos/user-stats/messages.proto",
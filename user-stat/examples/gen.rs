@@ -4,7 +4,7 @@ use anyhow::Result;
 use chrono::{DateTime, Days, Utc};
 use fake::{
     faker::chrono::en::DateTimeBetween, faker::internet::en::SafeEmail, faker::name::zh_cn::Name,
-    Dummy, Fake, Faker,
+    faker::phone_number::en::PhoneNumber, Dummy, Fake, Faker,
 };
 use nanoid::nanoid;
 use rand::Rng;
@@ -40,6 +40,13 @@ struct UserStat {
     last_in_app_notification: DateTime<Utc>,
     #[dummy(faker = "DateTimeBetween(before(90), now())")]
     last_sms_notification: DateTime<Utc>,
+
+    #[dummy(faker = "Locale")]
+    locale: String,
+    #[dummy(faker = "PhoneNumber()")]
+    phone: String,
+    #[dummy(faker = "DeviceId")]
+    device_id: String,
 }
 
 #[derive(Debug, Clone, Dummy, Serialize, Deserialize, PartialEq, Eq)]
@@ -72,11 +79,11 @@ async fn main() -> Result<()> {
 
 async fn bulk_insert(users: HashSet<UserStat>, pool: &MySqlPool) -> Result<()> {
     let mut sql = String::with_capacity(1024);
-    sql.push_str("INSERT INTO user_stats(email, name, gender, created_at, last_visited_at, last_watched_at, recent_watched, viewed_but_not_started, started_but_not_finished, finished, last_email_notification, last_in_app_notification, last_sms_notification)
+    sql.push_str("INSERT INTO user_stats(email, name, gender, created_at, last_visited_at, last_watched_at, recent_watched, viewed_but_not_started, started_but_not_finished, finished, last_email_notification, last_in_app_notification, last_sms_notification, locale, phone, device_id)
     VALUES");
     for user in users {
         sql.push_str(&format!(
-            "('{}', '{}', '{:?}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}'),",
+            "('{}', '{}', '{:?}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}'),",
             user.email,
             user.name,
             user.gender,
@@ -90,6 +97,9 @@ async fn bulk_insert(users: HashSet<UserStat>, pool: &MySqlPool) -> Result<()> {
             user.last_email_notification.to_rfc3339(),
             user.last_in_app_notification.to_rfc3339(),
             user.last_sms_notification.to_rfc3339(),
+            user.locale,
+            user.phone,
+            user.device_id,
         ));
     }
 
@@ -152,3 +162,22 @@ impl Dummy<UniqueEmail> for String {
         format!("{}.{}{}", &email[..at], id, &email[at..])
     }
 }
+
+struct Locale;
+const LOCALES: [&str; 6] = ["en", "en-US", "zh-CN", "zh-TW", "fr-FR", "de-DE"];
+impl Dummy<Locale> for String {
+    fn dummy_with_rng<R: Rng + ?Sized>(_: &Locale, rng: &mut R) -> String {
+        LOCALES[rng.gen_range(0..LOCALES.len())].to_string()
+    }
+}
+
+/// about half of the users have the app installed
+struct DeviceId;
+impl Dummy<DeviceId> for String {
+    fn dummy_with_rng<R: Rng + ?Sized>(_: &DeviceId, rng: &mut R) -> String {
+        match rng.gen_bool(0.5) {
+            true => nanoid!(),
+            false => String::new(),
+        }
+    }
+}
@@ -13,6 +13,9 @@ CREATE TABLE `user_stats` (
   `last_email_notification` datetime DEFAULT NULL COMMENT 'the last email notfiy time',
   `last_in_app_notification` datetime DEFAULT NULL COMMENT 'the last in-app notify time',
   `last_sms_notification` datetime DEFAULT NULL COMMENT 'the last sms notify time',
+  `locale` varchar(16) COLLATE utf8mb4_general_ci NOT NULL DEFAULT 'en' COMMENT 'user locale, e.g. en, zh-CN',
+  `phone` varchar(32) COLLATE utf8mb4_general_ci NOT NULL DEFAULT '' COMMENT 'phone number for sms',
+  `device_id` varchar(64) COLLATE utf8mb4_general_ci NOT NULL DEFAULT '' COMMENT 'device id for in-app messages',
   PRIMARY KEY (`email`),
   KEY `idx_created_at` (`created_at`),
   KEY `idx_last_visited_at` (`last_visited_at`),
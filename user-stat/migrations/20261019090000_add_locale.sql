@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table user_stats add column locale varchar(16) NOT NULL DEFAULT 'en' COMMENT 'user locale, e.g. en, zh-CN';
@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table user_stats add column phone varchar(32) NOT NULL DEFAULT '' COMMENT 'phone number for sms';
+alter table user_stats add column device_id varchar(64) NOT NULL DEFAULT '' COMMENT 'device id for in-app messages';
@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table user_stats add column sample_bucket int unsigned AS (CRC32(email) % 1000000) STORED COMMENT 'bucket of the email hash approximate counts sample by';
+alter table user_stats add index `idx_sample_bucket` (sample_bucket);
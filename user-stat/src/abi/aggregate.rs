@@ -0,0 +1,235 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{FromRow, MySql, QueryBuilder, Row};
+use tonic::{Response, Status};
+use tracing::info;
+
+use crate::{
+    pb::{AggregateRequest, AggregateRow, Dimension, QueryRequest, RetentionRequest, RetentionRow},
+    AggregateStream, RetentionStream, ServiceResult, UserStatsService,
+};
+
+use super::push_where;
+
+const DEFAULT_RETENTION_WEEKS: u32 = 8;
+const MAX_RETENTION_WEEKS: u32 = 52;
+/// Last visit buckets by days since the visit, most recent first.
+const LAST_VISIT_BUCKETS: &[(i64, &str)] =
+    &[(1, "<1d"), (7, "1-7d"), (30, "7-30d"), (90, "30-90d")];
+
+#[derive(Debug, FromRow)]
+struct GroupRow {
+    gender: String,
+    cohort: String,
+    last_visit: String,
+    n: i64,
+}
+
+impl UserStatsService {
+    pub async fn aggregate(&self, req: AggregateRequest) -> ServiceResult<AggregateStream> {
+        let dims: Vec<Dimension> = req.group_by().collect();
+        let query = req.query.unwrap_or_default();
+        let mut builder = aggregate_sql(&dims, &query, Utc::now())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        info!("Generated SQL: {}", builder.sql());
+        let rows = builder
+            .build_query_as::<GroupRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to aggregate users: {}", e)))?;
+
+        let rows = rows.into_iter().map(AggregateRow::from).map(Ok);
+        Ok(Response::new(Box::pin(futures::stream::iter(rows))))
+    }
+
+    pub async fn retention(&self, req: RetentionRequest) -> ServiceResult<RetentionStream> {
+        let weeks = match req.weeks {
+            0 => DEFAULT_RETENTION_WEEKS,
+            v => v.min(MAX_RETENTION_WEEKS),
+        };
+        let query = req.query.unwrap_or_default();
+        let mut builder =
+            retention_sql(weeks, &query).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        info!("Generated SQL: {}", builder.sql());
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to compute retention: {}", e)))?;
+
+        let now = Utc::now();
+        let mut ret = Vec::with_capacity(rows.len());
+        for row in rows {
+            let users: i64 = row.try_get("users").map_err(decode_err)?;
+            let last_signup: DateTime<Utc> = row.try_get("last_signup").map_err(decode_err)?;
+            let retained = (1..=weeks)
+                .map(|k| row.try_get::<i64, _>(format!("w{}", k).as_str()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(decode_err)?;
+
+            ret.push(Ok(RetentionRow {
+                cohort: row.try_get("cohort").map_err(decode_err)?,
+                users: users as u64,
+                active_after: active_after(users, &retained, last_signup, now),
+            }));
+        }
+        Ok(Response::new(Box::pin(futures::stream::iter(ret))))
+    }
+}
+
+impl From<GroupRow> for AggregateRow {
+    fn from(row: GroupRow) -> Self {
+        Self {
+            gender: row.gender,
+            cohort: row.cohort,
+            last_visit: row.last_visit,
+            count: row.n as u64,
+        }
+    }
+}
+
+fn aggregate_sql(
+    dims: &[Dimension],
+    query: &QueryRequest,
+    now: DateTime<Utc>,
+) -> Result<QueryBuilder<'static, MySql>> {
+    let mut gender = None;
+    let mut cohort = None;
+    let mut last_visit = None;
+    for dim in dims {
+        let (slot, expr) = match dim {
+            Dimension::Gender => (&mut gender, "IFNULL(gender, 'U')".to_string()),
+            Dimension::CohortDay => (&mut cohort, cohort_expr("%Y-%m-%d")),
+            Dimension::CohortWeek => (&mut cohort, cohort_expr("%x-W%v")),
+            Dimension::CohortMonth => (&mut cohort, cohort_expr("%Y-%m")),
+            // grouped by its alias, the buckets are bound
+            Dimension::LastVisit => (&mut last_visit, "last_visit".to_string()),
+            Dimension::Unspecified => bail!("dimension is required"),
+        };
+        match slot {
+            Some(v) if *v != expr => bail!("at most one cohort dimension is allowed"),
+            _ => *slot = Some(expr),
+        }
+    }
+
+    // group by the expressions, an alias would resolve to the column of the same name; no
+    // column is called last_visit
+    let group: Vec<&str> = [&gender, &cohort, &last_visit]
+        .into_iter()
+        .filter_map(|v| v.as_deref())
+        .collect();
+    let select = |v: &Option<String>| v.clone().unwrap_or_else(|| "''".to_string());
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT {} AS gender, {} AS cohort, ",
+        select(&gender),
+        select(&cohort),
+    ));
+    if last_visit.is_some() {
+        push_last_visit(&mut builder, now);
+    } else {
+        builder.push("''");
+    }
+    builder.push(" AS last_visit, COUNT(*) AS n FROM user_stats WHERE ");
+    push_where(&mut builder, query)?;
+    if !group.is_empty() {
+        let group = group.join(", ");
+        builder.push(format!(" GROUP BY {} ORDER BY {}", group, group));
+    }
+    Ok(builder)
+}
+
+fn cohort_expr(format: &str) -> String {
+    format!("DATE_FORMAT(created_at, '{}')", format)
+}
+
+fn push_last_visit(builder: &mut QueryBuilder<'static, MySql>, now: DateTime<Utc>) {
+    builder.push("CASE WHEN last_visited_at IS NULL THEN 'never'");
+    for (days, name) in LAST_VISIT_BUCKETS {
+        builder
+            .push(" WHEN last_visited_at >= ")
+            .push_bind(now - Duration::days(*days))
+            .push(format!(" THEN '{}'", name));
+    }
+    builder.push(" ELSE '>=90d' END");
+}
+
+fn retention_sql(weeks: u32, query: &QueryRequest) -> Result<QueryBuilder<'static, MySql>> {
+    let retained: String = (1..=weeks)
+        .map(|k| {
+            format!(
+                ", CAST(SUM(IFNULL(last_visited_at >= created_at + INTERVAL {} WEEK, 0)) AS SIGNED) AS w{}",
+                k, k
+            )
+        })
+        .collect();
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT DATE_FORMAT(created_at, '%x-W%v') AS cohort, COUNT(*) AS users, MAX(created_at) AS last_signup{} FROM user_stats WHERE ",
+        retained
+    ));
+    push_where(&mut builder, query)?;
+    builder.push(" GROUP BY cohort ORDER BY cohort");
+    Ok(builder)
+}
+
+/// Share of the cohort last active after each week, up to the last week over for the whole
+/// cohort.
+fn active_after(
+    users: i64,
+    retained: &[i64],
+    last_signup: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Vec<f64> {
+    if users == 0 {
+        return vec![];
+    }
+    let elapsed = (now - last_signup).num_weeks().max(0) as usize;
+    retained
+        .iter()
+        .take(elapsed)
+        .map(|n| *n as f64 / users as f64)
+        .collect()
+}
+
+fn decode_err(e: sqlx::Error) -> Status {
+    Status::internal(format!("failed to decode row: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate_sql_should_group_by_dimensions() -> Result<()> {
+        let now = Utc::now();
+        let all = QueryRequest::default();
+        let sql = |dims: &[Dimension]| aggregate_sql(dims, &all, now).map(|v| v.into_sql());
+        assert_eq!(
+            sql(&[Dimension::Gender, Dimension::CohortMonth])?,
+            "SELECT IFNULL(gender, 'U') AS gender, DATE_FORMAT(created_at, '%Y-%m') AS cohort, '' AS last_visit, COUNT(*) AS n FROM user_stats WHERE 1=1 GROUP BY IFNULL(gender, 'U'), DATE_FORMAT(created_at, '%Y-%m') ORDER BY IFNULL(gender, 'U'), DATE_FORMAT(created_at, '%Y-%m')"
+        );
+
+        assert!(!sql(&[])?.contains("GROUP BY"));
+        let by_last_visit = sql(&[Dimension::LastVisit])?;
+        assert!(by_last_visit
+            .contains("WHEN last_visited_at >= ? THEN '30-90d' ELSE '>=90d' END AS last_visit"));
+        assert!(by_last_visit.ends_with("GROUP BY last_visit ORDER BY last_visit"));
+
+        assert!(sql(&[Dimension::CohortDay, Dimension::CohortWeek]).is_err());
+        assert!(sql(&[Dimension::CohortDay, Dimension::CohortDay]).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn active_after_should_leave_out_unfinished_weeks() {
+        let now = Utc::now();
+        let retained = [8, 5, 4, 2];
+
+        let ret = active_after(10, &retained, now - Duration::days(20), now);
+        assert_eq!(ret, vec![0.8, 0.5]);
+        let ret = active_after(10, &retained, now - Duration::days(100), now);
+        assert_eq!(ret, vec![0.8, 0.5, 0.4, 0.2]);
+        assert!(active_after(0, &retained, now, now).is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use sqlx::{FromRow, QueryBuilder};
+use tonic::{Response, Status};
+use tracing::info;
+
+use crate::{
+    pb::{CountBucket, CountRequest, CountResponse, QueryRequest},
+    ServiceResult, UserStatsService,
+};
+
+use super::push_where;
+
+const DEFAULT_SAMPLE_SIZE: u32 = 10000;
+/// Users are sampled by the indexed `sample_bucket` their email hashes into, out of this many.
+const SAMPLE_BUCKETS: u32 = 1_000_000;
+
+/// Users of a gender signed up in a month.
+#[derive(Debug, FromRow)]
+struct CountRow {
+    gender: Option<String>,
+    cohort: Option<String>,
+    n: i64,
+}
+
+impl UserStatsService {
+    pub async fn count(&self, req: CountRequest) -> ServiceResult<CountResponse> {
+        let query = req.query.unwrap_or_default();
+        let sample_size = match req.sample_size {
+            0 => DEFAULT_SAMPLE_SIZE,
+            v => v,
+        };
+        let table_rows = match req.approximate {
+            true => self.estimated_rows().await?,
+            false => 0,
+        };
+        let mut buckets = sample_buckets(table_rows, sample_size);
+        let mut rows = self.count_rows(&query, buckets).await?;
+        // the table size only sizes the first sample, a query matching a small share of it
+        // is sampled again as wide as its own matches call for
+        if let Some(sampled) = buckets {
+            let matched: i64 = rows.iter().map(|r| r.n).sum();
+            let wider = widen_sample(sampled, matched as u64, sample_size);
+            if wider != buckets {
+                buckets = wider;
+                rows = self.count_rows(&query, buckets).await?;
+            }
+        }
+
+        // scaled by the share of the buckets sampled, the estimate of the rows only sizes it
+        let scale = match buckets {
+            Some(v) => SAMPLE_BUCKETS as f64 / v as f64,
+            None => 1.0,
+        };
+        let mut res = tally(&rows, scale);
+        res.approximate = buckets.is_some();
+        Ok(Response::new(res))
+    }
+
+    /// Count the users matching the query by gender and cohort, only those in the first
+    /// `buckets` sample buckets if given.
+    async fn count_rows(
+        &self,
+        query: &QueryRequest,
+        buckets: Option<u32>,
+    ) -> Result<Vec<CountRow>, Status> {
+        let mut builder = QueryBuilder::new(
+            "SELECT gender, DATE_FORMAT(created_at, '%Y-%m') AS cohort, COUNT(*) AS n FROM user_stats WHERE ",
+        );
+        push_where(&mut builder, query).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if let Some(buckets) = buckets {
+            builder.push(" AND sample_bucket < ").push_bind(buckets);
+        }
+        builder.push(" GROUP BY gender, cohort");
+        info!("Generated SQL: {}", builder.sql());
+        builder
+            .build_query_as::<CountRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to count users: {}", e)))
+    }
+
+    /// Rows of user_stats as estimated by the storage engine.
+    async fn estimated_rows(&self) -> Result<u64, Status> {
+        let rows: Option<Option<u64>> = sqlx::query_scalar(
+            "SELECT TABLE_ROWS FROM information_schema.TABLES WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'user_stats'",
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| Status::internal(format!("failed to estimate table size: {}", e)))?;
+
+        Ok(rows.flatten().unwrap_or_default())
+    }
+}
+
+/// Buckets to sample for about `sample_size` of the rows, `None` to count them all when the
+/// table is no larger than the sample.
+fn sample_buckets(table_rows: u64, sample_size: u32) -> Option<u32> {
+    if table_rows <= sample_size as u64 {
+        return None;
+    }
+    let buckets = (sample_size as u64 * SAMPLE_BUCKETS as u64).div_ceil(table_rows);
+    Some(buckets.clamp(1, SAMPLE_BUCKETS as u64) as u32)
+}
+
+/// Buckets to sample again when the first `buckets` matched fewer than `sample_size` users,
+/// estimating the matches of the whole table from them; `None` to count them all.
+fn widen_sample(buckets: u32, matched: u64, sample_size: u32) -> Option<u32> {
+    if matched >= sample_size as u64 {
+        return Some(buckets);
+    }
+    if matched == 0 {
+        return None;
+    }
+    let wider = (buckets as u64 * sample_size as u64).div_ceil(matched);
+    (wider < SAMPLE_BUCKETS as u64).then_some(wider as u32)
+}
+
+/// Sum up the counts, scaling those of a sample up to the whole table.
+fn tally(rows: &[CountRow], scale: f64) -> CountResponse {
+    let mut total = 0;
+    let mut by_gender: BTreeMap<&str, i64> = BTreeMap::new();
+    let mut by_cohort: BTreeMap<&str, i64> = BTreeMap::new();
+    for row in rows {
+        total += row.n;
+        *by_gender
+            .entry(row.gender.as_deref().unwrap_or("U"))
+            .or_default() += row.n;
+        *by_cohort
+            .entry(row.cohort.as_deref().unwrap_or_default())
+            .or_default() += row.n;
+    }
+
+    let scaled = |n: i64| (n as f64 * scale).round() as u64;
+    let buckets = |counts: BTreeMap<&str, i64>| {
+        counts
+            .into_iter()
+            .map(|(key, n)| CountBucket {
+                key: key.to_string(),
+                count: scaled(n),
+            })
+            .collect()
+    };
+    CountResponse {
+        count: scaled(total),
+        approximate: false,
+        by_gender: buckets(by_gender),
+        by_cohort: buckets(by_cohort),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(gender: Option<&str>, cohort: &str, n: i64) -> CountRow {
+        CountRow {
+            gender: gender.map(String::from),
+            cohort: Some(cohort.to_string()),
+            n,
+        }
+    }
+
+    #[test]
+    fn sample_should_cover_a_share_of_the_table() {
+        assert_eq!(sample_buckets(0, 10), None);
+        assert_eq!(sample_buckets(10, 10), None);
+        assert_eq!(sample_buckets(100, 10), Some(SAMPLE_BUCKETS / 10));
+        assert_eq!(sample_buckets(3, 1), Some(333_334));
+        assert_eq!(sample_buckets(u64::MAX, 1), Some(1));
+    }
+
+    #[test]
+    fn sample_should_widen_for_few_matches() {
+        assert_eq!(widen_sample(100, 10, 10), Some(100));
+        assert_eq!(widen_sample(100, 5, 10), Some(200));
+        assert_eq!(widen_sample(100, 3, 10), Some(334));
+        assert_eq!(widen_sample(100, 0, 10), None);
+        assert_eq!(widen_sample(SAMPLE_BUCKETS / 2, 1, 10), None);
+    }
+
+    #[test]
+    fn tally_should_break_down_and_scale() {
+        let rows = vec![
+            row(Some("M"), "2024-07", 3),
+            row(Some("F"), "2024-07", 2),
+            row(None, "2024-06", 1),
+            row(Some("F"), "2024-06", 4),
+        ];
+
+        let res = tally(&rows, 1.0);
+        assert_eq!(res.count, 10);
+        let by_gender: Vec<_> = res
+            .by_gender
+            .iter()
+            .map(|b| (b.key.as_str(), b.count))
+            .collect();
+        assert_eq!(by_gender, vec![("F", 6), ("M", 3), ("U", 1)]);
+        let by_cohort: Vec<_> = res
+            .by_cohort
+            .iter()
+            .map(|b| (b.key.as_str(), b.count))
+            .collect();
+        assert_eq!(by_cohort, vec![("2024-06", 5), ("2024-07", 5)]);
+
+        let res = tally(&rows, 2.5);
+        assert_eq!(res.count, 25);
+        assert_eq!(res.by_gender[0].count, 15);
+    }
+}
@@ -1,35 +1,81 @@
-use chrono::{DateTime, TimeZone, Utc};
-use itertools::Itertools;
+mod aggregate;
+mod count;
+mod user;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
 use prost_types::Timestamp;
-use tonic::Response;
+use sqlx::{FromRow, MySql, QueryBuilder};
+use tonic::{Response, Status};
 use tracing::info;
 
 use crate::{
-    pb::{QueryRequest, RawQueryRequest, User},
+    pb::{condition::Cond, Condition, QueryRequest, RawQueryRequest, User},
     ResponseStream, ServiceResult, UserStatsService,
 };
 
+/// Columns a time condition can be put on.
+const TIME_COLUMNS: &[&str] = &[
+    "created_at",
+    "last_visited_at",
+    "last_watched_at",
+    "last_email_notification",
+    "last_in_app_notification",
+    "last_sms_notification",
+];
+/// Columns holding comma separated id lists.
+const ID_COLUMNS: &[&str] = &[
+    "recent_watched",
+    "viewed_but_not_started",
+    "started_but_not_finished",
+    "finished",
+];
+
+/// A row of user_stats; columns not selected by a query are left empty.
+#[derive(Debug, FromRow)]
+struct UserRow {
+    email: String,
+    name: String,
+    #[sqlx(default)]
+    locale: String,
+    #[sqlx(default)]
+    phone: String,
+    #[sqlx(default)]
+    device_id: String,
+    #[sqlx(default)]
+    started_but_not_finished: Option<String>,
+    #[sqlx(default)]
+    finished: Option<String>,
+    #[sqlx(default)]
+    last_visited_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    last_watched_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    recent_watched: Option<String>,
+}
+
 impl UserStatsService {
     pub async fn query(&self, query: QueryRequest) -> ServiceResult<ResponseStream> {
         // info!("{:?}", query);
 
-        let ts_where = query
-            .timestamps
-            .into_iter()
-            .map(|(k, v)| timestamp_query(&k, v.lower, v.upper))
-            .join(" AND ");
-        println!("ts where -> {:?}", ts_where);
-
-        let mut sql = "select email, name from user_stats where ".to_string();
-        sql.push_str(&ts_where);
+        let mut builder = QueryBuilder::new(
+            "select email, name, locale, phone, device_id, started_but_not_finished, finished, last_visited_at, last_watched_at, recent_watched from user_stats where ",
+        );
+        push_where(&mut builder, &query).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        info!("Generated SQL: {}", builder.sql());
 
-        info!("Generated SQL: {}", sql);
-
-        self.raw_query(RawQueryRequest { query: sql }).await
+        let ret = builder
+            .build_query_as::<UserRow>()
+            .fetch_all(&self.inner.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to query users: {}", e)))?;
+        Ok(Response::new(Box::pin(futures::stream::iter(
+            ret.into_iter().map(User::from).map(Ok),
+        ))))
     }
 
     pub async fn raw_query(&self, req: RawQueryRequest) -> ServiceResult<ResponseStream> {
-        let Ok(ret) = sqlx::query_as::<_, User>(&req.query)
+        let Ok(ret) = sqlx::query_as::<_, UserRow>(&req.query)
             .fetch_all(&self.inner.pool)
             .await
         else {
@@ -43,36 +89,164 @@ impl UserStatsService {
         };
 
         Ok(Response::new(Box::pin(futures::stream::iter(
-            ret.into_iter().map(Ok),
+            ret.into_iter().map(User::from).map(Ok),
         ))))
     }
 }
 
-fn timestamp_query(name: &str, lower: Option<Timestamp>, upper: Option<Timestamp>) -> String {
-    if lower.is_none() && upper.is_none() {
-        return "1=1".to_string();
+impl From<UserRow> for User {
+    fn from(row: UserRow) -> Self {
+        User {
+            email: row.email,
+            name: row.name,
+            locale: row.locale,
+            phone: row.phone,
+            device_id: row.device_id,
+            started_but_not_finished: row
+                .started_but_not_finished
+                .as_deref()
+                .map(parse_ids)
+                .unwrap_or_default(),
+            finished: row.finished.as_deref().map(parse_ids).unwrap_or_default(),
+            last_visited_at: row.last_visited_at.map(utc_to_ts),
+            last_watched_at: row.last_watched_at.map(utc_to_ts),
+            recent_watched: row
+                .recent_watched
+                .as_deref()
+                .map(parse_ids)
+                .unwrap_or_default(),
+        }
     }
+}
+
+/// Parse a comma separated id list, skipping malformed entries.
+fn parse_ids(v: &str) -> Vec<u32> {
+    v.split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
 
-    if lower.is_none() {
-        let upper = ts_to_utc(upper.unwrap());
-        return format!("{} <= '{}'", name, upper.to_rfc3339());
+/// Append the conditions of the query on user_stats, joined by AND, binding their values.
+pub(crate) fn push_where(
+    builder: &mut QueryBuilder<'_, MySql>,
+    query: &QueryRequest,
+) -> Result<()> {
+    builder.push("1=1");
+    for (column, range) in &query.timestamps {
+        if !TIME_COLUMNS.contains(&column.as_str()) {
+            bail!("invalid time column: {}", column);
+        }
+        builder.push(" AND ");
+        push_timestamp(builder, column, range.lower, range.upper)?;
+    }
+    if !query.emails.is_empty() {
+        builder.push(" AND ");
+        push_emails(builder, &query.emails);
+    }
+    for cond in &query.conditions {
+        builder.push(" AND ");
+        push_condition(builder, cond)?;
+    }
+    Ok(())
+}
+
+fn push_timestamp(
+    builder: &mut QueryBuilder<'_, MySql>,
+    column: &str,
+    lower: Option<Timestamp>,
+    upper: Option<Timestamp>,
+) -> Result<()> {
+    let lower = lower.map(ts_to_utc).transpose()?;
+    let upper = upper.map(ts_to_utc).transpose()?;
+    match (lower, upper) {
+        (None, None) => builder.push("1=1"),
+        (None, Some(upper)) => builder.push(column).push(" <= ").push_bind(upper),
+        (Some(lower), None) => builder.push(column).push(" >= ").push_bind(lower),
+        (Some(lower), Some(upper)) => builder
+            .push(column)
+            .push(" BETWEEN ")
+            .push_bind(lower)
+            .push(" AND ")
+            .push_bind(upper),
+    };
+    Ok(())
+}
+
+fn push_emails(builder: &mut QueryBuilder<'_, MySql>, emails: &[String]) {
+    builder.push("email IN (");
+    let mut values = builder.separated(", ");
+    for email in emails {
+        values.push_bind(email.clone());
+    }
+    values.push_unseparated(")");
+}
+
+fn push_condition(builder: &mut QueryBuilder<'_, MySql>, cond: &Condition) -> Result<()> {
+    // check before pushing anything, the columns are not bound
+    match &cond.cond {
+        Some(Cond::Time(c)) if !TIME_COLUMNS.contains(&c.column.as_str()) => {
+            bail!("invalid time column: {}", c.column)
+        }
+        Some(Cond::Ids(c)) if !ID_COLUMNS.contains(&c.column.as_str()) => {
+            bail!("invalid id column: {}", c.column)
+        }
+        Some(Cond::Gender(g)) if !matches!(g.as_str(), "M" | "F" | "U") => {
+            bail!("invalid gender: {}", g)
+        }
+        _ => {}
+    }
+
+    // a NULL column doesn't satisfy the condition, so it satisfies the negation
+    if cond.negated {
+        builder.push("NOT IFNULL(");
+    }
+    match &cond.cond {
+        Some(Cond::Time(c)) => {
+            let range = c.range.unwrap_or_default();
+            push_timestamp(builder, &c.column, range.lower, range.upper)?;
+        }
+        Some(Cond::Ids(c)) => push_ids(builder, &c.column, &c.ids),
+        Some(Cond::Gender(g)) => {
+            builder.push("gender = ").push_bind(g.clone());
+        }
+        None => {
+            builder.push("1=1");
+        }
+    }
+    if cond.negated {
+        builder.push(", FALSE)");
     }
+    Ok(())
+}
 
-    if upper.is_none() {
-        let lower = ts_to_utc(lower.unwrap());
-        return format!("{} >= '{}'", name, lower.to_rfc3339());
+fn push_ids(builder: &mut QueryBuilder<'_, MySql>, column: &str, ids: &[u32]) {
+    if ids.is_empty() {
+        builder.push("1=0");
+        return;
+    }
+    builder.push("(");
+    for (i, id) in ids.iter().enumerate() {
+        if i > 0 {
+            builder.push(" OR ");
+        }
+        builder
+            .push("FIND_IN_SET(")
+            .push_bind(id.to_string())
+            .push(format!(", REPLACE({}, ' ', '')) > 0", column));
     }
+    builder.push(")");
+}
 
-    format!(
-        "{} BETWEEN '{}' AND '{}'",
-        name,
-        ts_to_utc(lower.unwrap()).to_rfc3339(),
-        ts_to_utc(upper.unwrap()).to_rfc3339()
-    )
+fn ts_to_utc(ts: Timestamp) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
+        .ok_or_else(|| anyhow!("invalid timestamp: {}s {}ns", ts.seconds, ts.nanos))
 }
 
-fn ts_to_utc(ts: Timestamp) -> DateTime<Utc> {
-    Utc.timestamp_opt(ts.seconds, ts.nanos as _).unwrap()
+fn utc_to_ts(dt: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as _,
+    }
 }
 
 #[cfg(test)]
@@ -81,8 +255,109 @@ mod tests {
 
     use anyhow::Result;
     use futures::StreamExt;
+    use prost_types::Timestamp;
+
+    use sqlx::QueryBuilder;
+
+    use super::{parse_ids, push_condition, push_where};
+    use crate::{
+        pb::{condition::Cond, Condition, IdCondition, QueryRequest, TimeCondition, TimeQuery},
+        test_utils::tq,
+        UserStatsService,
+    };
+
+    fn condition_sql(cond: &Condition) -> Result<String> {
+        let mut builder = QueryBuilder::new("");
+        push_condition(&mut builder, cond)?;
+        Ok(builder.sql().to_string())
+    }
 
-    use crate::{pb::QueryRequest, test_utils::tq, UserStatsService};
+    #[test]
+    fn where_should_bind_values() -> Result<()> {
+        let query = QueryRequest {
+            emails: vec![
+                "alice@acme.org".to_string(),
+                "x'; drop table user_stats; --".to_string(),
+            ],
+            ..Default::default()
+        };
+        let mut builder = QueryBuilder::new("");
+        push_where(&mut builder, &query)?;
+        assert_eq!(builder.sql(), "1=1 AND email IN (?, ?)");
+
+        let mut timestamps = HashMap::new();
+        timestamps.insert("1=1; drop table user_stats".to_string(), tq(Some(1), None));
+        let query = QueryRequest {
+            timestamps,
+            ..Default::default()
+        };
+        assert!(push_where(&mut QueryBuilder::new(""), &query).is_err());
+
+        let mut timestamps = HashMap::new();
+        let invalid = Timestamp {
+            seconds: i64::MAX,
+            nanos: -1,
+        };
+        timestamps.insert(
+            "created_at".to_string(),
+            TimeQuery {
+                lower: Some(invalid),
+                upper: None,
+            },
+        );
+        let query = QueryRequest {
+            timestamps,
+            ..Default::default()
+        };
+        assert!(push_where(&mut QueryBuilder::new(""), &query).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn condition_query_should_negate_and_validate() -> Result<()> {
+        let ids = Condition {
+            negated: false,
+            cond: Some(Cond::Ids(IdCondition {
+                column: "finished".to_string(),
+                ids: vec![1, 2],
+            })),
+        };
+        assert_eq!(
+            condition_sql(&ids)?,
+            "(FIND_IN_SET(?, REPLACE(finished, ' ', '')) > 0 OR FIND_IN_SET(?, REPLACE(finished, ' ', '')) > 0)"
+        );
+
+        let gender = Condition {
+            negated: true,
+            cond: Some(Cond::Gender("F".to_string())),
+        };
+        assert_eq!(condition_sql(&gender)?, "NOT IFNULL(gender = ?, FALSE)");
+
+        let injected = Condition {
+            negated: false,
+            cond: Some(Cond::Time(TimeCondition {
+                column: "1=1; drop table user_stats".to_string(),
+                range: Some(tq(Some(1), None)),
+            })),
+        };
+        assert!(condition_sql(&injected).is_err());
+        let gender = Condition {
+            negated: false,
+            cond: Some(Cond::Gender("X' OR '1'='1".to_string())),
+        };
+        assert!(condition_sql(&gender).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ids_should_skip_malformed_entries() {
+        assert_eq!(
+            parse_ids("360039,334103, 339461"),
+            vec![360039, 334103, 339461]
+        );
+        assert_eq!(parse_ids("1,,x,2"), vec![1, 2]);
+        assert!(parse_ids("").is_empty());
+    }
 
     #[tokio::test]
     async fn user_stats_query_should_work() -> Result<()> {
@@ -96,7 +371,7 @@ mod tests {
 
         let query = QueryRequest {
             timestamps,
-            ids: HashMap::new(),
+            ..Default::default()
         };
 
         println!("{query:?}");
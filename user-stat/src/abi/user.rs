@@ -0,0 +1,142 @@
+use tonic::{Response, Status};
+
+use crate::{
+    pb::{AddUserRequest, DeleteUserRequest, UpdateUserRequest, User},
+    ServiceResult, UserStatsService,
+};
+
+use super::UserRow;
+
+const DEFAULT_LOCALE: &str = "en";
+const SELECT_USER: &str = "select email, name, locale, phone, device_id, started_but_not_finished, finished, last_visited_at, last_watched_at, recent_watched from user_stats where email = ?";
+
+impl UserStatsService {
+    pub async fn add_user(&self, req: AddUserRequest) -> ServiceResult<User> {
+        if req.email.is_empty() {
+            return Err(Status::invalid_argument("email is required"));
+        }
+        let locale = match req.locale.as_str() {
+            "" => DEFAULT_LOCALE,
+            v => v,
+        };
+
+        // a user already known keeps their stats
+        sqlx::query("INSERT IGNORE INTO user_stats(email, name, locale) VALUES (?, ?, ?)")
+            .bind(&req.email)
+            .bind(&req.name)
+            .bind(locale)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to add user: {}", e)))?;
+
+        let row = self.find_user(&req.email).await?;
+        let row = row.ok_or_else(|| Status::internal(format!("user {} went away", req.email)))?;
+        Ok(Response::new(row.into()))
+    }
+
+    pub async fn update_user(&self, req: UpdateUserRequest) -> ServiceResult<User> {
+        sqlx::query("UPDATE user_stats SET name = ? WHERE email = ?")
+            .bind(&req.name)
+            .bind(&req.email)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to update user: {}", e)))?;
+        match self.find_user(&req.email).await? {
+            Some(row) => Ok(Response::new(row.into())),
+            None => Err(not_found(&req.email)),
+        }
+    }
+
+    pub async fn delete_user(&self, req: DeleteUserRequest) -> ServiceResult<User> {
+        let row = self.find_user(&req.email).await?;
+        let row = row.ok_or_else(|| not_found(&req.email))?;
+        sqlx::query("DELETE FROM user_stats WHERE email = ?")
+            .bind(&req.email)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to delete user: {}", e)))?;
+        Ok(Response::new(row.into()))
+    }
+
+    async fn find_user(&self, email: &str) -> Result<Option<UserRow>, Status> {
+        sqlx::query_as(SELECT_USER)
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| Status::internal(format!("failed to fetch user: {}", e)))
+    }
+}
+
+fn not_found(email: &str) -> Status {
+    Status::not_found(format!("user {} not found", email))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn add_user_should_keep_known_users() -> Result<()> {
+        let (_tdb, svc) = UserStatsService::new_for_test().await?;
+        let req = AddUserRequest {
+            email: "new@acme.org".to_string(),
+            name: "New".to_string(),
+            locale: String::new(),
+        };
+        let user = svc.add_user(req.clone()).await?.into_inner();
+        assert_eq!(user.name, "New");
+        assert_eq!(user.locale, "en");
+
+        let req = AddUserRequest {
+            name: "Renamed".to_string(),
+            ..req
+        };
+        let user = svc.add_user(req).await?.into_inner();
+        assert_eq!(user.name, "New");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn users_should_be_renamed_and_deleted() -> Result<()> {
+        let (_tdb, svc) = UserStatsService::new_for_test().await?;
+        let email = "renamed@acme.org".to_string();
+        let req = AddUserRequest {
+            email: email.clone(),
+            name: "Old".to_string(),
+            locale: String::new(),
+        };
+        svc.add_user(req).await?;
+
+        let req = UpdateUserRequest {
+            email: email.clone(),
+            name: "New".to_string(),
+        };
+        let user = svc.update_user(req.clone()).await?.into_inner();
+        assert_eq!(user.name, "New");
+        // renaming to the same name is no error
+        svc.update_user(req).await?;
+
+        let user = svc
+            .delete_user(DeleteUserRequest {
+                email: email.clone(),
+            })
+            .await?;
+        assert_eq!(user.into_inner().name, "New");
+        for ret in [
+            svc.delete_user(DeleteUserRequest {
+                email: email.clone(),
+            })
+            .await,
+            svc.update_user(UpdateUserRequest {
+                email,
+                name: "Again".to_string(),
+            })
+            .await,
+        ] {
+            assert_eq!(ret.unwrap_err().code(), tonic::Code::NotFound);
+        }
+        Ok(())
+    }
+}
@@ -1,31 +1,89 @@
-use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+use anyhow::Result;
+use crm_common::{
+    check, check_db_url, check_pk, check_service_keys, check_tls, ConfigLoader, ListenConfig,
+    Reload, RolePolicy, TlsConfig, Validate,
+};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AuthConfig {
     pub pk: String,
+    /// PEM encoded public keys of the services calling with tokens of their own, by name
+    #[serde(default)]
+    pub services: HashMap<String, String>,
+    /// roles allowed to call each method, by full gRPC path
+    #[serde(default)]
+    pub policy: RolePolicy,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
+    #[serde(default = "default_port")]
     pub port: u16,
+    /// host or Unix domain socket to listen on, loopback by default
+    #[serde(default)]
+    pub listen: ListenConfig,
+    /// serve TLS, and call other services over TLS, if set
+    pub tls: Option<TlsConfig>,
     pub db_url: String,
 }
 
 impl AppConfig {
+    /// Load `user_stat.yml`, or the file given by `--config` or `USER_STAT_CONFIG`, with `USER_STAT_*` overrides.
     pub fn load() -> Result<Self> {
-        let config = match File::open("user_stat.yml") {
-            Ok(reader) => serde_yaml::from_reader(reader),
-            _ => bail!("Config file not found"),
-        };
+        Self::loader()?.load()
+    }
+
+    /// The loader of the config, honouring `--config` among the command line arguments.
+    pub fn loader() -> Result<ConfigLoader> {
+        ConfigLoader::new("user_stat.yml", "USER_STAT").with_process_args()
+    }
+}
 
-        Ok(config?)
+#[cfg(feature = "test_utils")]
+impl AppConfig {
+    /// The config of the tests, trusting the test key.
+    pub fn for_test() -> Result<Self> {
+        Self::loader()?.load_for_test(&[("AUTH__PK", crm_common::TEST_PK)])
     }
 }
+
+impl Reload for AppConfig {
+    fn needs_restart(&self, new: &Self) -> bool {
+        let (old, new_server) = (&self.server, &new.server);
+        (old.port, &old.listen, &old.tls, &old.db_url)
+            != (
+                new_server.port,
+                &new_server.listen,
+                &new_server.tls,
+                &new_server.db_url,
+            )
+            || self.auth != new.auth
+    }
+}
+
+impl Validate for AppConfig {
+    fn validate(&self) -> Result<()> {
+        check_db_url("server.db_url", &self.server.db_url)?;
+        check_tls("server.tls", self.server.tls.as_ref())?;
+        check_pk("auth.pk", &self.auth.pk)?;
+        check_service_keys("auth.services", &self.auth.services)?;
+        let services = ["user_stats.UserStats"];
+        check("auth.policy", self.auth.policy.check(&services))
+    }
+}
+
+fn default_port() -> u16 {
+    50001
+}
@@ -7,14 +7,21 @@ use std::{ops::Deref, sync::Arc};
 
 pub use config::AppConfig;
 
+use anyhow::Result;
+use crm_common::{authorize, AuthServer, Reloadable};
 use futures::Stream;
 use pb::user_stats_server::{UserStats, UserStatsServer};
-use pb::{QueryRequest, RawQueryRequest, User};
+use pb::{
+    AddUserRequest, AggregateRequest, AggregateRow, CountRequest, CountResponse, DeleteUserRequest,
+    QueryRequest, RawQueryRequest, RetentionRequest, RetentionRow, UpdateUserRequest, User,
+};
 use sqlx::MySqlPool;
 use tonic::{async_trait, Request, Response, Status};
 
 type ServiceResult<T> = Result<Response<T>, Status>;
 type ResponseStream = Pin<Box<dyn Stream<Item = Result<User, Status>> + Send>>;
+type AggregateStream = Pin<Box<dyn Stream<Item = Result<AggregateRow, Status>> + Send>>;
+type RetentionStream = Pin<Box<dyn Stream<Item = Result<RetentionRow, Status>> + Send>>;
 
 #[derive(Clone)]
 pub struct UserStatsService {
@@ -23,7 +30,7 @@ pub struct UserStatsService {
 
 #[allow(unused)]
 pub struct UserStatsServiceInner {
-    config: AppConfig,
+    config: Reloadable<AppConfig>,
     pool: MySqlPool,
 }
 
@@ -31,6 +38,8 @@ pub struct UserStatsServiceInner {
 impl UserStats for UserStatsService {
     type QueryStream = ResponseStream;
     type RawQueryStream = ResponseStream;
+    type AggregateStream = AggregateStream;
+    type RetentionStream = RetentionStream;
 
     async fn query(&self, request: Request<QueryRequest>) -> ServiceResult<Self::QueryStream> {
         let query = request.into_inner();
@@ -44,6 +53,42 @@ impl UserStats for UserStatsService {
         let query = request.into_inner();
         self.raw_query(query).await
     }
+
+    async fn count(&self, request: Request<CountRequest>) -> ServiceResult<CountResponse> {
+        let request = request.into_inner();
+        self.count(request).await
+    }
+
+    async fn aggregate(
+        &self,
+        request: Request<AggregateRequest>,
+    ) -> ServiceResult<Self::AggregateStream> {
+        let request = request.into_inner();
+        self.aggregate(request).await
+    }
+
+    async fn retention(
+        &self,
+        request: Request<RetentionRequest>,
+    ) -> ServiceResult<Self::RetentionStream> {
+        let request = request.into_inner();
+        self.retention(request).await
+    }
+
+    async fn add_user(&self, request: Request<AddUserRequest>) -> ServiceResult<User> {
+        let request = request.into_inner();
+        self.add_user(request).await
+    }
+
+    async fn update_user(&self, request: Request<UpdateUserRequest>) -> ServiceResult<User> {
+        let request = request.into_inner();
+        self.update_user(request).await
+    }
+
+    async fn delete_user(&self, request: Request<DeleteUserRequest>) -> ServiceResult<User> {
+        let request = request.into_inner();
+        self.delete_user(request).await
+    }
 }
 
 impl UserStatsService {
@@ -52,15 +97,32 @@ impl UserStatsService {
             .await
             .expect("Failed to connect to db");
 
-        let inner = UserStatsServiceInner { config, pool };
+        let inner = UserStatsServiceInner {
+            config: Reloadable::new(config),
+            pool,
+        };
 
         Self {
             inner: Arc::new(inner),
         }
     }
 
-    pub fn into_server(self) -> UserStatsServer<Self> {
-        UserStatsServer::new(self)
+    /// Swap in a reloaded config. Configs changing the listen address, TLS, database or auth,
+    /// set at start, are rejected.
+    pub fn reload(&self, config: AppConfig) -> Result<()> {
+        self.config.reload(config)
+    }
+
+    /// The server letting through callers with a token signed with the key of the issuer or of a
+    /// trusted service, and the roles the policy asks for.
+    pub fn into_server(self) -> Result<AuthServer<UserStatsServer<Self>>> {
+        let auth = self.config.get().auth.clone();
+        authorize(
+            UserStatsServer::new(self),
+            &auth.pk,
+            &auth.services,
+            auth.policy,
+        )
     }
 }
 
@@ -78,7 +140,7 @@ pub mod test_utils {
 
     use anyhow::Result;
     use chrono::Utc;
-    use crm_common::TestMysql;
+    use crm_common::{Reloadable, TestMysql};
     use prost_types::Timestamp;
     use sqlx::{Executor, MySqlPool};
 
@@ -86,10 +148,13 @@ pub mod test_utils {
 
     impl UserStatsService {
         pub async fn new_for_test() -> Result<(TestMysql, Self)> {
-            let config = AppConfig::load()?;
+            let config = AppConfig::for_test()?;
             let (tdb, pool) = get_test_pool().await;
             let svc = Self {
-                inner: Arc::new(UserStatsServiceInner { config, pool }),
+                inner: Arc::new(UserStatsServiceInner {
+                    config: Reloadable::new(config),
+                    pool,
+                }),
             };
             Ok((tdb, svc))
         }
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use tonic::transport::Server;
+use crm_common::{serve, server_builder, watch_config};
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
 use user_stat::{AppConfig, UserStatsService};
@@ -9,18 +9,20 @@ async fn main() -> Result<()> {
     let layer = Layer::new().with_filter(LevelFilter::INFO);
     tracing_subscriber::registry().with(layer).init();
 
-    let config = AppConfig::load().expect("failed to load config");
+    let loader = AppConfig::loader()?;
+    let config: AppConfig = loader.load().expect("failed to load config");
     // info!("config: {:?}", config);
 
-    let addr = config.server.port;
-    let addr = format!("[::1]:{}", addr).parse().unwrap();
+    let server = &config.server;
+    let addr = server.listen.resolve(server.port).await?;
     info!("user-stats service listening on {}", addr);
 
-    let user_stats_srv = UserStatsService::new(config).await.into_server();
+    let tls = config.server.tls.clone();
+    let svc = UserStatsService::new(config).await;
+    let reloaded = svc.clone();
+    watch_config(loader, move |config| reloaded.reload(config));
+    let user_stats_srv = svc.into_server()?;
 
-    Server::builder()
-        .add_service(user_stats_srv)
-        .serve(addr)
-        .await?;
-    Ok(())
+    let router = server_builder(tls.as_ref())?.add_service(user_stats_srv);
+    serve(router, &addr).await
 }
@@ -1,5 +1,4 @@
 // This file is @generated by prost-build.
-#[derive(sqlx::FromRow)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct User {
@@ -7,6 +6,28 @@ pub struct User {
     pub email: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub name: ::prost::alloc::string::String,
+    /// BCP 47 language tag of the user, e.g. en, zh-CN
+    #[prost(string, tag = "3")]
+    pub locale: ::prost::alloc::string::String,
+    /// phone number for sms, empty if unknown
+    #[prost(string, tag = "4")]
+    pub phone: ::prost::alloc::string::String,
+    /// device to push in-app messages to, empty if the app is not installed
+    #[prost(string, tag = "5")]
+    pub device_id: ::prost::alloc::string::String,
+    /// contents the user started but not finished, most recent first
+    #[prost(uint32, repeated, tag = "6")]
+    pub started_but_not_finished: ::prost::alloc::vec::Vec<u32>,
+    /// contents the user finished
+    #[prost(uint32, repeated, tag = "7")]
+    pub finished: ::prost::alloc::vec::Vec<u32>,
+    #[prost(message, optional, tag = "8")]
+    pub last_visited_at: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(message, optional, tag = "9")]
+    pub last_watched_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// contents the user watched recently, most recent first
+    #[prost(uint32, repeated, tag = "10")]
+    pub recent_watched: ::prost::alloc::vec::Vec<u32>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -16,6 +37,55 @@ pub struct QueryRequest {
     pub timestamps: ::std::collections::HashMap<::prost::alloc::string::String, TimeQuery>,
     #[prost(map = "string, message", tag = "2")]
     pub ids: ::std::collections::HashMap<::prost::alloc::string::String, IdQuery>,
+    /// only users with these emails, empty for no restriction
+    #[prost(string, repeated, tag = "3")]
+    pub emails: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// all must hold, on top of the timestamps and emails
+    #[prost(message, repeated, tag = "4")]
+    pub conditions: ::prost::alloc::vec::Vec<Condition>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Condition {
+    /// users the condition does not hold for, including those with the column unset
+    #[prost(bool, tag = "1")]
+    pub negated: bool,
+    #[prost(oneof = "condition::Cond", tags = "2, 3, 4")]
+    pub cond: ::core::option::Option<condition::Cond>,
+}
+/// Nested message and enum types in `Condition`.
+pub mod condition {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Cond {
+        #[prost(message, tag = "2")]
+        Time(super::TimeCondition),
+        #[prost(message, tag = "3")]
+        Ids(super::IdCondition),
+        /// M, F or U
+        #[prost(string, tag = "4")]
+        Gender(::prost::alloc::string::String),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TimeCondition {
+    /// created_at, last_visited_at, last_watched_at, last_email_notification,
+    /// last_in_app_notification or last_sms_notification
+    #[prost(string, tag = "1")]
+    pub column: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub range: ::core::option::Option<TimeQuery>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IdCondition {
+    /// recent_watched, viewed_but_not_started, started_but_not_finished or finished
+    #[prost(string, tag = "1")]
+    pub column: ::prost::alloc::string::String,
+    /// any of the ids is in the list
+    #[prost(uint32, repeated, tag = "2")]
+    pub ids: ::prost::alloc::vec::Vec<u32>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -37,6 +107,154 @@ pub struct IdQuery {
     #[prost(uint32, repeated, tag = "1")]
     pub ids: ::prost::alloc::vec::Vec<u32>,
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CountRequest {
+    #[prost(message, optional, tag = "1")]
+    pub query: ::core::option::Option<QueryRequest>,
+    /// estimate from a sample of the table instead of scanning it all
+    #[prost(bool, tag = "2")]
+    pub approximate: bool,
+    /// rows sampled for an approximate count, 0 for the default
+    #[prost(uint32, tag = "3")]
+    pub sample_size: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CountResponse {
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+    /// whether the counts are estimated from a sample
+    #[prost(bool, tag = "2")]
+    pub approximate: bool,
+    /// by gender: M, F or U
+    #[prost(message, repeated, tag = "3")]
+    pub by_gender: ::prost::alloc::vec::Vec<CountBucket>,
+    /// by month of signup, e.g. 2024-07
+    #[prost(message, repeated, tag = "4")]
+    pub by_cohort: ::prost::alloc::vec::Vec<CountBucket>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CountBucket {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AggregateRequest {
+    #[prost(message, optional, tag = "1")]
+    pub query: ::core::option::Option<QueryRequest>,
+    /// at most one cohort dimension, the total count if empty
+    #[prost(enumeration = "Dimension", repeated, tag = "2")]
+    pub group_by: ::prost::alloc::vec::Vec<i32>,
+}
+/// a group of users, fields of the dimensions not grouped by are empty
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AggregateRow {
+    #[prost(string, tag = "1")]
+    pub gender: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub cohort: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub last_visit: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RetentionRequest {
+    #[prost(message, optional, tag = "1")]
+    pub query: ::core::option::Option<QueryRequest>,
+    /// weeks after signup to report, 0 for 8
+    #[prost(uint32, tag = "2")]
+    pub weeks: u32,
+}
+/// user-stat keeps the latest visit only, so this is not retention by week: a user who
+/// visited in week 1 only and one who came back in week 5 both count from week 1 on
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RetentionRow {
+    /// signup ISO week, e.g. 2024-W31
+    #[prost(string, tag = "1")]
+    pub cohort: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub users: u64,
+    /// share of the cohort whose last visit is at least 1, 2, .. weeks after signing up;
+    /// weeks not over for the whole cohort yet are left out
+    #[prost(double, repeated, tag = "3")]
+    pub active_after: ::prost::alloc::vec::Vec<f64>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AddUserRequest {
+    #[prost(string, tag = "1")]
+    pub email: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    /// en if empty
+    #[prost(string, tag = "3")]
+    pub locale: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UpdateUserRequest {
+    #[prost(string, tag = "1")]
+    pub email: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteUserRequest {
+    #[prost(string, tag = "1")]
+    pub email: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Dimension {
+    Unspecified = 0,
+    Gender = 1,
+    /// signup day, e.g. 2024-07-29
+    CohortDay = 2,
+    /// signup ISO week, e.g. 2024-W31
+    CohortWeek = 3,
+    /// signup month, e.g. 2024-07
+    CohortMonth = 4,
+    /// time since the last visit: <1d, 1-7d, 7-30d, 30-90d, >=90d or never
+    LastVisit = 5,
+}
+impl Dimension {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Dimension::Unspecified => "DIMENSION_UNSPECIFIED",
+            Dimension::Gender => "GENDER",
+            Dimension::CohortDay => "COHORT_DAY",
+            Dimension::CohortWeek => "COHORT_WEEK",
+            Dimension::CohortMonth => "COHORT_MONTH",
+            Dimension::LastVisit => "LAST_VISIT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DIMENSION_UNSPECIFIED" => Some(Self::Unspecified),
+            "GENDER" => Some(Self::Gender),
+            "COHORT_DAY" => Some(Self::CohortDay),
+            "COHORT_WEEK" => Some(Self::CohortWeek),
+            "COHORT_MONTH" => Some(Self::CohortMonth),
+            "LAST_VISIT" => Some(Self::LastVisit),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod user_stats_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -157,6 +375,120 @@ pub mod user_stats_client {
                 .insert(GrpcMethod::new("user_stats.UserStats", "RawQuery"));
             self.inner.server_streaming(req, path, codec).await
         }
+        /// how many users a query hits, by gender and signup cohort
+        pub async fn count(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CountRequest>,
+        ) -> std::result::Result<tonic::Response<super::CountResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/user_stats.UserStats/Count");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("user_stats.UserStats", "Count"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// count users grouped by gender, signup cohort or last visit
+        pub async fn aggregate(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AggregateRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::AggregateRow>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/user_stats.UserStats/Aggregate");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("user_stats.UserStats", "Aggregate"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        /// share of each weekly signup cohort last active after each of the following weeks
+        pub async fn retention(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RetentionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::RetentionRow>>,
+            tonic::Status,
+        > {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/user_stats.UserStats/Retention");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("user_stats.UserStats", "Retention"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        /// register a user with no activity yet, a known user is left as is
+        pub async fn add_user(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AddUserRequest>,
+        ) -> std::result::Result<tonic::Response<super::User>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/user_stats.UserStats/AddUser");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("user_stats.UserStats", "AddUser"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// rename a known user
+        pub async fn update_user(
+            &mut self,
+            request: impl tonic::IntoRequest<super::UpdateUserRequest>,
+        ) -> std::result::Result<tonic::Response<super::User>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/user_stats.UserStats/UpdateUser");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("user_stats.UserStats", "UpdateUser"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// forget a known user and their stats, returning what was removed
+        pub async fn delete_user(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteUserRequest>,
+        ) -> std::result::Result<tonic::Response<super::User>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/user_stats.UserStats/DeleteUser");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("user_stats.UserStats", "DeleteUser"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -184,6 +516,46 @@ pub mod user_stats_server {
             &self,
             request: tonic::Request<super::RawQueryRequest>,
         ) -> std::result::Result<tonic::Response<Self::RawQueryStream>, tonic::Status>;
+        /// how many users a query hits, by gender and signup cohort
+        async fn count(
+            &self,
+            request: tonic::Request<super::CountRequest>,
+        ) -> std::result::Result<tonic::Response<super::CountResponse>, tonic::Status>;
+        /// Server streaming response type for the Aggregate method.
+        type AggregateStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::AggregateRow, tonic::Status>,
+            > + Send
+            + 'static;
+        /// count users grouped by gender, signup cohort or last visit
+        async fn aggregate(
+            &self,
+            request: tonic::Request<super::AggregateRequest>,
+        ) -> std::result::Result<tonic::Response<Self::AggregateStream>, tonic::Status>;
+        /// Server streaming response type for the Retention method.
+        type RetentionStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::RetentionRow, tonic::Status>,
+            > + Send
+            + 'static;
+        /// share of each weekly signup cohort last active after each of the following weeks
+        async fn retention(
+            &self,
+            request: tonic::Request<super::RetentionRequest>,
+        ) -> std::result::Result<tonic::Response<Self::RetentionStream>, tonic::Status>;
+        /// register a user with no activity yet, a known user is left as is
+        async fn add_user(
+            &self,
+            request: tonic::Request<super::AddUserRequest>,
+        ) -> std::result::Result<tonic::Response<super::User>, tonic::Status>;
+        /// rename a known user
+        async fn update_user(
+            &self,
+            request: tonic::Request<super::UpdateUserRequest>,
+        ) -> std::result::Result<tonic::Response<super::User>, tonic::Status>;
+        /// forget a known user and their stats, returning what was removed
+        async fn delete_user(
+            &self,
+            request: tonic::Request<super::DeleteUserRequest>,
+        ) -> std::result::Result<tonic::Response<super::User>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct UserStatsServer<T: UserStats> {
@@ -339,6 +711,243 @@ pub mod user_stats_server {
                     };
                     Box::pin(fut)
                 }
+                "/user_stats.UserStats/Count" => {
+                    #[allow(non_camel_case_types)]
+                    struct CountSvc<T: UserStats>(pub Arc<T>);
+                    impl<T: UserStats> tonic::server::UnaryService<super::CountRequest> for CountSvc<T> {
+                        type Response = super::CountResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CountRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { <T as UserStats>::count(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CountSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/user_stats.UserStats/Aggregate" => {
+                    #[allow(non_camel_case_types)]
+                    struct AggregateSvc<T: UserStats>(pub Arc<T>);
+                    impl<T: UserStats>
+                        tonic::server::ServerStreamingService<super::AggregateRequest>
+                        for AggregateSvc<T>
+                    {
+                        type Response = super::AggregateRow;
+                        type ResponseStream = T::AggregateStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AggregateRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as UserStats>::aggregate(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = AggregateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/user_stats.UserStats/Retention" => {
+                    #[allow(non_camel_case_types)]
+                    struct RetentionSvc<T: UserStats>(pub Arc<T>);
+                    impl<T: UserStats>
+                        tonic::server::ServerStreamingService<super::RetentionRequest>
+                        for RetentionSvc<T>
+                    {
+                        type Response = super::RetentionRow;
+                        type ResponseStream = T::RetentionStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RetentionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as UserStats>::retention(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RetentionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/user_stats.UserStats/AddUser" => {
+                    #[allow(non_camel_case_types)]
+                    struct AddUserSvc<T: UserStats>(pub Arc<T>);
+                    impl<T: UserStats> tonic::server::UnaryService<super::AddUserRequest> for AddUserSvc<T> {
+                        type Response = super::User;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AddUserRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as UserStats>::add_user(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = AddUserSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/user_stats.UserStats/UpdateUser" => {
+                    #[allow(non_camel_case_types)]
+                    struct UpdateUserSvc<T: UserStats>(pub Arc<T>);
+                    impl<T: UserStats> tonic::server::UnaryService<super::UpdateUserRequest> for UpdateUserSvc<T> {
+                        type Response = super::User;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::UpdateUserRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as UserStats>::update_user(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = UpdateUserSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/user_stats.UserStats/DeleteUser" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteUserSvc<T: UserStats>(pub Arc<T>);
+                    impl<T: UserStats> tonic::server::UnaryService<super::DeleteUserRequest> for DeleteUserSvc<T> {
+                        type Response = super::User;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteUserRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as UserStats>::delete_user(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = DeleteUserSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
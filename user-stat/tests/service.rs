@@ -1,12 +1,18 @@
 use std::{collections::HashMap, net::SocketAddr, time::Duration};
 
 use anyhow::Result;
-use crm_common::TestMysql;
+use crm_common::{TestMysql, TokenInterceptor};
 use futures::StreamExt;
 use tokio::time::sleep;
-use tonic::transport::Server;
+use tonic::{
+    service::interceptor::InterceptedService,
+    transport::{Channel, Server},
+};
 use user_stat::{
-    pb::{user_stats_client::UserStatsClient, QueryRequest, RawQueryRequest},
+    pb::{
+        user_stats_client::UserStatsClient, AggregateRequest, CountRequest, Dimension,
+        QueryRequest, RawQueryRequest,
+    },
     test_utils::tq,
     UserStatsService,
 };
@@ -16,7 +22,7 @@ const PORT_BASE: u32 = 60000;
 #[tokio::test]
 async fn raw_query_should_work() -> Result<()> {
     let (_tdb, addr) = start_serve(PORT_BASE).await?;
-    let mut client = UserStatsClient::connect(format!("http://{addr}")).await?;
+    let mut client = connect(addr).await?;
 
     let raw_req = RawQueryRequest {
         query: "SELECT * FROM user_stats LIMIT 1".to_string(),
@@ -34,14 +40,14 @@ async fn raw_query_should_work() -> Result<()> {
 #[tokio::test]
 async fn stat_query_should_work() -> Result<()> {
     let (_tdb, addr) = start_serve(PORT_BASE + 1).await?;
-    let mut client = UserStatsClient::connect(format!("http://{addr}")).await?;
+    let mut client = connect(addr).await?;
 
     let mut timestamps = HashMap::new();
     timestamps.insert("created_at".to_string(), tq(Some(93), None));
 
     let req = QueryRequest {
         timestamps,
-        ids: HashMap::new(),
+        ..Default::default()
     };
 
     let stream = client.query(req).await?.into_inner();
@@ -50,14 +56,53 @@ async fn stat_query_should_work() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn count_should_break_down_by_gender() -> Result<()> {
+    let (_tdb, addr) = start_serve(PORT_BASE + 2).await?;
+    let mut client = connect(addr).await?;
+
+    let req = CountRequest {
+        query: Some(QueryRequest::default()),
+        ..Default::default()
+    };
+    let res = client.count(req).await?.into_inner();
+    assert!(!res.approximate);
+    let by_gender: u64 = res.by_gender.iter().map(|b| b.count).sum();
+    assert_eq!(by_gender, res.count);
+    Ok(())
+}
+
+#[tokio::test]
+async fn aggregate_should_group_by_gender() -> Result<()> {
+    let (_tdb, addr) = start_serve(PORT_BASE + 3).await?;
+    let mut client = connect(addr).await?;
+
+    let req = AggregateRequest {
+        query: Some(QueryRequest::default()),
+        group_by: vec![Dimension::Gender as i32],
+    };
+    let rows = client
+        .aggregate(req)
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(!rows.is_empty());
+    for row in rows {
+        assert!(["M", "F", "U"].contains(&row?.gender.as_str()));
+    }
+    Ok(())
+}
+
 async fn start_serve(port: u32) -> Result<(TestMysql, SocketAddr)> {
     let addr = format!("[::1]:{}", port).parse()?;
 
     let (tdb, svc) = UserStatsService::new_for_test().await?;
 
+    let svc = svc.into_server()?;
     tokio::spawn(async move {
         Server::builder()
-            .add_service(svc.into_server())
+            .add_service(svc)
             .serve(addr)
             .await
             .unwrap();
@@ -66,3 +111,13 @@ async fn start_serve(port: u32) -> Result<(TestMysql, SocketAddr)> {
 
     Ok((tdb, addr))
 }
+
+async fn connect(
+    addr: SocketAddr,
+) -> Result<UserStatsClient<InterceptedService<Channel, TokenInterceptor>>> {
+    let channel = Channel::from_shared(format!("http://{addr}"))?
+        .connect()
+        .await?;
+    let auth = TokenInterceptor::for_test("test", &["admin"]);
+    Ok(UserStatsClient::with_interceptor(channel, auth))
+}